@@ -19,7 +19,7 @@
       nativeBuildInputs = [pkgs.pkg-config pkgs.makeWrapper];
       postInstall = ''
         wrapProgram $out/bin/niri-screen-recorder \
-          --prefix PATH : ${pkgs.lib.makeBinPath [pkgs.slurp pkgs.gpu-screen-recorder]}
+          --prefix PATH : ${pkgs.lib.makeBinPath [pkgs.slurp pkgs.gpu-screen-recorder pkgs.ffmpeg pkgs.wl-clipboard pkgs.curl pkgs.grim]}
       '';
       meta.mainProgram = "niri-screen-recorder";
     };
@@ -87,7 +87,8 @@
           wantedBy = ["graphical-session.target"];
           after = ["graphical-session.target"];
           serviceConfig = {
-            Type = "simple";
+            Type = "notify";
+            WatchdogSec = 30;
             ExecStart = "${self.packages."x86_64-linux".default}/bin/niri-screen-recorder daemon";
             Restart = "on-failure";
             RestartSec = 5;
@@ -0,0 +1,124 @@
+# Benachrichtigungen
+
+recording-saved = Aufnahme gespeichert
+saved-to = Gespeichert unter: { $path }
+keep-recording = Aufnahme behalten?
+recording = Aufnahme läuft
+recording-paused = Aufnahme pausiert
+recording-elapsed = Dauer: { $elapsed }
+capture-details = { $size } · { $fps } fps · ohne Ton
+capture-details-output = { $size } auf { $output } · { $fps } fps · ohne Ton
+error-summary = Fehler bei der Bildschirmaufnahme
+upload-complete = Hochladen abgeschlossen
+upload-copied = { $url }
+    Link in die Zwischenablage kopiert
+uploading = Aufnahme wird hochgeladen
+battery-low = Akku fast leer
+battery-low-body = Der Akku ist bei { $percentage } %. Beende die Aufnahme am besten, bevor er leer ist.
+recording-on-battery = Aufnahme im Akkubetrieb
+recording-on-battery-body = Aufnahme mit { $fps } fps im Akkubetrieb
+recorder-exited =
+    Die Aufnahme wurde beendet, weil gpu-screen-recorder unerwartet beendet wurde ({ $status }).
+    Möglicherweise wurde der aufgenommene Bildschirm getrennt oder ausgeschaltet.
+
+# Aktionen
+
+action-open-file = Datei öffnen
+action-open-folder = Ordner öffnen
+action-copy-path = Pfad kopieren
+action-copy-file = Datei kopieren
+action-delete = Löschen
+action-share = Teilen
+action-record-again = Erneut aufnehmen
+action-keep = Behalten
+action-discard = Verwerfen
+action-stop = Beenden
+action-view-log = Protokoll anzeigen
+
+# Tray
+
+tray-title = Bildschirmaufnahme
+tray-recording = Aufnahme läuft
+tray-idle = Keine Aufnahme
+tray-start = Aufnahme starten
+tray-stop = Aufnahme beenden
+tray-start-output = Fokussierten Bildschirm aufnehmen
+tray-replay-start = Replay-Puffer starten
+tray-replay-stop = Replay-Puffer beenden
+tray-open-folder = Ordner öffnen
+
+# Aufnahmemenü
+
+menu-region = Bereich aufnehmen
+menu-output = Diesen Bildschirm aufnehmen
+menu-window = Dieses Fenster aufnehmen
+menu-replay = Replay speichern
+
+# Nachbearbeitung
+
+job-remux = Aufnahme wird neu verpackt
+job-remux-done = Neu verpacken abgeschlossen
+job-gif = Umwandlung in GIF
+job-gif-done = GIF fertig
+job-compress = Aufnahme wird komprimiert
+job-compress-done = Komprimierte Aufnahme fertig
+chapter-part = Teil { $number }
+chapter-start = Anfang
+chapter-marker = Markierung bei { $elapsed }
+webcam-compositing = Webcam wird eingefügt
+clicks-highlighting = Klicks werden hervorgehoben
+
+# Kommandozeile
+
+cli-error = Fehler: { $error }
+cli-failed = Konnte nicht { $action }: { $error }
+cli-not-running = Fehler: Keine Verbindung zum Daemon. Läuft er? (niri-screen-recorder daemon)
+cli-details = Details: { $error }
+cli-started = Aufnahme gestartet
+cli-already-recording = Es läuft bereits eine Aufnahme oder Bereichsauswahl
+cli-stopped = Aufnahme beendet
+cli-not-recording = Keine Aufnahme aktiv
+cli-cancelled = Ausstehender Start abgebrochen
+cli-not-selecting = Keine Bereichsauswahl aktiv
+cli-paused = Aufnahme pausiert
+cli-resumed = Aufnahme fortgesetzt
+cli-not-pausable = Keine Aufnahme zum Pausieren oder bereits pausiert
+cli-not-paused = Keine pausierte Aufnahme
+cli-marked = Markierung gesetzt
+cli-replay-started = Replay-Puffer gestartet
+cli-replay-stopped = Replay-Puffer beendet
+cli-replay-saved = Replay wird in { $path } gespeichert
+cli-replay-running = Der Replay-Puffer läuft bereits
+cli-replay-not-running = Der Replay-Puffer läuft nicht
+cli-nothing-focused = Nichts im Fokus, das aufgenommen werden kann
+cli-no-last-region = Noch kein Bereich aufgenommen
+cli-no-recording = Noch keine Aufnahme zum Hochladen
+cli-no-recordings = Keine Aufnahmen gefunden
+cli-not-in-history = Nicht im Aufnahmeverlauf: { $path }
+cli-uploading = Wird hochgeladen … { $percent } %
+cli-action-start = Aufnahme starten
+cli-action-toggle = Aufnahme umschalten
+cli-action-tag = Schlagwörter hinzufügen
+cli-action-pause = Aufnahme pausieren
+cli-action-resume = Aufnahme fortsetzen
+cli-action-mark = Markierung setzen
+cli-action-replay = Replay-Puffer steuern
+status-recording = Aufnahme
+status-paused = Pausiert
+status-start-queued = Start vorgemerkt
+status-file = Datei
+status-uptime = Laufzeit
+status-recordings = Aufnahmen
+status-bytes-written = Geschrieben
+status-encode-fps = Encoder-fps
+status-dropped-frames = Verlorene Frames (geschätzt)
+status-yes = ja
+status-no = nein
+list-started = Beginn
+list-duration = Dauer
+list-size = Größe
+list-file = Datei
+list-tags = Tags
+
+retention-summary = Alte Aufnahmen aufgeräumt
+retention-body = In den Papierkorb verschoben: { $count } alte Aufnahmen, insgesamt { $size }
@@ -0,0 +1,124 @@
+# Notifications
+
+recording-saved = Recording Saved
+saved-to = Saved to: { $path }
+keep-recording = Keep Recording?
+recording = Recording
+recording-paused = Recording Paused
+recording-elapsed = Elapsed: { $elapsed }
+capture-details = { $size } · { $fps } fps · no audio
+capture-details-output = { $size } on { $output } · { $fps } fps · no audio
+error-summary = Screen Recorder Error
+upload-complete = Upload Complete
+upload-copied = { $url }
+    Link copied to clipboard
+uploading = Uploading Recording
+battery-low = Battery Low
+battery-low-body = Battery is at { $percentage }%. Consider stopping the recording before it runs out.
+recording-on-battery = Recording on Battery
+recording-on-battery-body = Recording at { $fps } fps while on battery power
+recorder-exited =
+    Recording stopped because gpu-screen-recorder exited unexpectedly ({ $status }).
+    The captured output may have been disconnected or turned off.
+
+# Notification actions
+
+action-open-file = Open File
+action-open-folder = Open Folder
+action-copy-path = Copy Path
+action-copy-file = Copy File
+action-delete = Delete
+action-share = Share
+action-record-again = Record Again
+action-keep = Keep
+action-discard = Discard
+action-stop = Stop
+action-view-log = View Log
+
+# Tray
+
+tray-title = Screen Recorder
+tray-recording = Recording
+tray-idle = Not recording
+tray-start = Start Recording
+tray-stop = Stop Recording
+tray-start-output = Record Focused Output
+tray-replay-start = Start Replay Buffer
+tray-replay-stop = Stop Replay Buffer
+tray-open-folder = Open Folder
+
+# Capture menu
+
+menu-region = Record a region
+menu-output = Record this output
+menu-window = Record this window
+menu-replay = Save the replay
+
+# Post-processing
+
+job-remux = Remuxing Recording
+job-remux-done = Remux Finished
+job-gif = Converting to GIF
+job-gif-done = GIF Ready
+job-compress = Compressing Recording
+job-compress-done = Compressed Recording Ready
+chapter-part = Part { $number }
+chapter-start = Start
+chapter-marker = Marker at { $elapsed }
+webcam-compositing = Adding Webcam
+clicks-highlighting = Highlighting Clicks
+
+# Command line
+
+cli-error = Error: { $error }
+cli-failed = Failed to { $action }: { $error }
+cli-not-running = Error: Could not connect to daemon. Is it running? (niri-screen-recorder daemon)
+cli-details = Details: { $error }
+cli-started = Recording started
+cli-already-recording = Already recording or selecting a region
+cli-stopped = Recording stopped
+cli-not-recording = No recording in progress
+cli-cancelled = Pending start cancelled
+cli-not-selecting = No region selection in progress
+cli-paused = Recording paused
+cli-resumed = Recording resumed
+cli-not-pausable = No recording to pause, or it is already paused
+cli-not-paused = No paused recording
+cli-marked = Marker added
+cli-replay-started = Replay buffer started
+cli-replay-stopped = Replay buffer stopped
+cli-replay-saved = Saving the replay to { $path }
+cli-replay-running = The replay buffer is already running
+cli-replay-not-running = The replay buffer is not running
+cli-nothing-focused = Nothing has focus to record
+cli-no-last-region = No region recorded yet
+cli-no-recording = No recording to upload yet
+cli-no-recordings = No recordings found
+cli-not-in-history = Not in the recording history: { $path }
+cli-uploading = Uploading… { $percent }%
+cli-action-start = start recording
+cli-action-toggle = toggle recording
+cli-action-tag = tag recording
+cli-action-pause = pause recording
+cli-action-resume = resume recording
+cli-action-mark = add marker
+cli-action-replay = control the replay buffer
+status-recording = Recording
+status-paused = Paused
+status-start-queued = Start queued
+status-file = File
+status-uptime = Uptime
+status-recordings = Recordings
+status-bytes-written = Bytes written
+status-encode-fps = Encode fps
+status-dropped-frames = Dropped frames (estimated)
+status-yes = yes
+status-no = no
+list-started = Started
+list-duration = Duration
+list-size = Size
+list-file = File
+list-tags = Tags
+
+retention-summary = Old Recordings Cleaned Up
+retention-body = Moved to the trash: { $count } old recordings, { $size } in total
@@ -0,0 +1,92 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::i18n::t;
+use crate::postprocess;
+
+/// A point in a recording that players can jump to
+#[derive(Clone)]
+pub struct Chapter {
+    /// Position in the recording, not counting time spent paused
+    pub start: Duration,
+    pub title: String,
+}
+
+/// Escape a value for ffmpeg's metadata format
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The chapters in ffmpeg's metadata format, each running until the next.
+/// A "Start" chapter is added if none begins at the start.
+fn metadata(chapters: &[Chapter], duration: Duration) -> String {
+    let mut chapters = chapters.to_vec();
+    chapters.sort_by_key(|c| c.start);
+    if chapters.first().is_none_or(|c| !c.start.is_zero()) {
+        chapters.insert(
+            0,
+            Chapter {
+                start: Duration::ZERO,
+                title: t!("chapter-start"),
+            },
+        );
+    }
+
+    let mut content = String::from(";FFMETADATA1\n");
+    for (i, chapter) in chapters.iter().enumerate() {
+        let end = chapters.get(i + 1).map_or(duration, |next| next.start);
+        content += &format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            chapter.start.as_millis(),
+            end.max(chapter.start).as_millis(),
+            escape(&chapter.title)
+        );
+    }
+    content
+}
+
+/// Embed chapters in the recording `file` with ffmpeg, replacing it. The
+/// streams are copied as they are.
+pub async fn embed(file: &str, chapters: &[Chapter], duration: Duration) -> Result<()> {
+    let input = Path::new(file);
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = input.extension().unwrap_or_default().to_string_lossy();
+    let metadata_file = input.with_file_name(format!(".{}-chapters.txt", stem));
+    let output = input.with_file_name(format!("{}-chapters.{}", stem, extension));
+
+    std::fs::write(&metadata_file, metadata(chapters, duration))
+        .map_err(|e| Error::PostProcessFailed(format!("failed to write chapters: {}", e)))?;
+
+    let args: Vec<OsString> = vec![
+        "-i".into(),
+        file.into(),
+        "-i".into(),
+        metadata_file.clone().into(),
+        "-map".into(),
+        "0".into(),
+        "-map_chapters".into(),
+        "1".into(),
+        "-c".into(),
+        "copy".into(),
+    ];
+    let result = match postprocess::ffmpeg(args, output, duration) {
+        Ok(mut running) => {
+            while running.progress().await.is_some() {}
+            running.finish().await
+        }
+        Err(e) => Err(e),
+    };
+    std::fs::remove_file(&metadata_file).ok();
+
+    std::fs::rename(result?, file)
+        .map_err(|e| Error::PostProcessFailed(format!("failed to replace {}: {}", file, e)))
+}
@@ -0,0 +1,449 @@
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::unix::AsyncFd;
+use tokio::task::JoinHandle;
+
+use crate::config;
+use crate::error::Result;
+use crate::postprocess::{self, RunningJob};
+use crate::webcam::Corner;
+
+/// evdev event type and codes of the mouse buttons, from linux/input-event-codes.h
+const EV_KEY: u16 = 0x01;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+
+/// Size of a `struct input_event`, which ends in u16 type, u16 code and
+/// i32 value after a timestamp whose size depends on the platform
+const EVENT_SIZE: usize = std::mem::size_of::<nix::libc::input_event>();
+
+/// Shortest time a button is shown pressed, so quick clicks are visible
+const MIN_PRESS: f64 = 0.15;
+
+/// How long the badge stays up after the last click
+const LINGER: f64 = 0.6;
+
+/// How long each ring of the ripple around the badge is shown
+const RIPPLE_STEP: f64 = 0.08;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+}
+
+impl Button {
+    fn from_code(code: u16) -> Option<Self> {
+        match code {
+            BTN_LEFT => Some(Button::Left),
+            BTN_MIDDLE => Some(Button::Middle),
+            BTN_RIGHT => Some(Button::Right),
+            _ => None,
+        }
+    }
+}
+
+/// A mouse click during a recording
+#[derive(Clone, Copy, Debug)]
+pub struct Click {
+    pub button: Button,
+    /// Position in the recording when pressed, not counting time spent paused
+    pub pressed: Duration,
+    /// Position in the recording when released
+    pub released: Duration,
+}
+
+/// Clicks so far, timed against the recording
+struct Log {
+    started: Instant,
+    paused_since: Option<Instant>,
+    paused_for: Duration,
+    /// Buttons held down, with when they were pressed
+    held: Vec<(Button, Duration)>,
+    clicks: Vec<Click>,
+}
+
+impl Log {
+    /// Position in the recording, which stands still while paused
+    fn position(&self) -> Duration {
+        let paused = self.paused_for + self.paused_since.map_or(Duration::ZERO, |t| t.elapsed());
+        self.started.elapsed().saturating_sub(paused)
+    }
+
+    fn press(&mut self, button: Button) {
+        // Clicks while paused don't end up in the video
+        if self.paused_since.is_none() && !self.held.iter().any(|(b, _)| *b == button) {
+            let pressed = self.position();
+            self.held.push((button, pressed));
+        }
+    }
+
+    fn release(&mut self, button: Button) {
+        if let Some(i) = self.held.iter().position(|(b, _)| *b == button) {
+            let (button, pressed) = self.held.remove(i);
+            let released = self.position();
+            self.clicks.push(Click {
+                button,
+                pressed,
+                released,
+            });
+        }
+    }
+}
+
+/// Logs mouse clicks while recording, read from the pointer devices in
+/// /dev/input. Wayland keeps the pointer to the compositor, so reading the
+/// devices takes membership in the `input` group, and only the buttons are
+/// known, not where the pointer was.
+pub struct Listener {
+    log: Arc<Mutex<Log>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Listener {
+    /// Start listening on every device with mouse buttons. Best-effort:
+    /// returns `None`, after logging why, if none can be read.
+    pub fn start() -> Option<Self> {
+        let log = Arc::new(Mutex::new(Log {
+            started: Instant::now(),
+            paused_since: None,
+            paused_for: Duration::ZERO,
+            held: Vec::new(),
+            clicks: Vec::new(),
+        }));
+
+        let entries = match std::fs::read_dir("/sys/class/input") {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!("Not logging clicks, no input devices: {}", e);
+                return None;
+            }
+        };
+        let mut tasks = Vec::new();
+        let mut denied = false;
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let is_pointer = std::fs::read_to_string(entry.path().join("device/capabilities/key"))
+                .is_ok_and(|caps| has_buttons(&caps));
+            if !name.to_string_lossy().starts_with("event") || !is_pointer {
+                continue;
+            }
+
+            let path = Path::new("/dev/input").join(&name);
+            let device = OpenOptions::new()
+                .read(true)
+                .custom_flags(nix::libc::O_NONBLOCK | nix::libc::O_CLOEXEC)
+                .open(&path)
+                .and_then(AsyncFd::new);
+            match device {
+                Ok(device) => tasks.push(tokio::spawn(follow(device, log.clone()))),
+                Err(e) if e.kind() == ErrorKind::PermissionDenied => denied = true,
+                Err(e) => tracing::debug!("Failed to open {}: {}", path.display(), e),
+            }
+        }
+
+        if tasks.is_empty() {
+            if denied {
+                tracing::warn!("Not logging clicks, add yourself to the input group to allow it");
+            } else {
+                tracing::warn!("Not logging clicks, no mouse found");
+            }
+            return None;
+        }
+        tracing::debug!("Logging clicks from {} devices", tasks.len());
+        Some(Self { log, tasks })
+    }
+
+    pub fn pause(&self) {
+        let mut log = self.log.lock().unwrap();
+        log.paused_since.get_or_insert_with(Instant::now);
+    }
+
+    pub fn resume(&self) {
+        let mut log = self.log.lock().unwrap();
+        if let Some(since) = log.paused_since.take() {
+            log.paused_for += since.elapsed();
+        }
+    }
+
+    /// Stop listening. Returns the clicks in the order they were made;
+    /// buttons still held count as released now.
+    pub fn stop(self) -> Vec<Click> {
+        for task in &self.tasks {
+            task.abort();
+        }
+        let mut log = self.log.lock().unwrap();
+        while let Some(&(button, _)) = log.held.first() {
+            log.release(button);
+        }
+        let mut clicks = std::mem::take(&mut log.clicks);
+        clicks.sort_by_key(|c| c.pressed);
+        clicks
+    }
+}
+
+/// Whether a device's key capabilities, as the hex words of a bitmask
+/// with the highest word first, include the left mouse button
+fn has_buttons(caps: &str) -> bool {
+    let word = usize::from(BTN_LEFT) / 64;
+    caps.split_whitespace()
+        .rev()
+        .nth(word)
+        .and_then(|w| u64::from_str_radix(w, 16).ok())
+        .is_some_and(|w| w & (1 << (BTN_LEFT % 64)) != 0)
+}
+
+/// Feed the button events from `device` into the log until it goes away
+async fn follow(device: AsyncFd<File>, log: Arc<Mutex<Log>>) {
+    let mut buf = [0u8; EVENT_SIZE * 64];
+    loop {
+        let Ok(mut ready) = device.readable().await else {
+            return;
+        };
+        let read = match ready.try_io(|fd| fd.get_ref().read(&mut buf)) {
+            Ok(Ok(read)) if read > 0 => read,
+            Ok(Ok(_)) => return,
+            Ok(Err(e)) => {
+                tracing::debug!("Stopped reading clicks from a device: {}", e);
+                return;
+            }
+            Err(_would_block) => continue,
+        };
+
+        let mut log = log.lock().unwrap();
+        for event in buf[..read].chunks_exact(EVENT_SIZE) {
+            let (kind, rest) = event[EVENT_SIZE - 8..].split_at(2);
+            let (code, value) = rest.split_at(2);
+            let kind = u16::from_ne_bytes([kind[0], kind[1]]);
+            let code = u16::from_ne_bytes([code[0], code[1]]);
+            let value = i32::from_ne_bytes([value[0], value[1], value[2], value[3]]);
+            let Some(button) = Button::from_code(code).filter(|_| kind == EV_KEY) else {
+                continue;
+            };
+            // 1 is a press, 0 a release, and 2 an autorepeat
+            match value {
+                1 => log.press(button),
+                0 => log.release(button),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A length in pixels of a 1080p recording, scaled to the actual height
+fn px(n: i32) -> String {
+    format!("{}*ih/1080", n)
+}
+
+/// `origin` moved by `n` scaled pixels
+fn offset(origin: &str, n: i32) -> String {
+    if n < 0 {
+        format!("{}-{}", origin, px(-n))
+    } else {
+        format!("{}+{}", origin, px(n))
+    }
+}
+
+/// An ffmpeg expression that is true within any of `windows`, in seconds
+fn during(windows: impl Iterator<Item = (f64, f64)>) -> String {
+    let windows: Vec<String> = windows
+        .map(|(from, to)| format!("between(t,{:.3},{:.3})", from, to))
+        .collect();
+    windows.join("+")
+}
+
+/// The ffmpeg filter drawing a mouse badge into `corner` while clicks are
+/// made, with the pressed buttons lit and a ripple around it on each press
+fn filter(clicks: &[Click], corner: Corner) -> String {
+    const MARGIN: i32 = 24;
+    const WIDTH: i32 = 40;
+    const HEIGHT: i32 = 60;
+
+    let left = px(MARGIN);
+    let top = px(MARGIN);
+    let right = format!("iw-{}", px(MARGIN + WIDTH));
+    let bottom = format!("ih-{}", px(MARGIN + HEIGHT));
+    let (x, y) = match corner {
+        Corner::TopLeft => (left, top),
+        Corner::TopRight => (right, top),
+        Corner::BottomLeft => (left, bottom),
+        Corner::BottomRight => (right, bottom),
+    };
+
+    let seconds = |d: Duration| d.as_secs_f64();
+    let held = |button: Button| {
+        during(clicks.iter().filter(|c| c.button == button).map(|c| {
+            (
+                seconds(c.pressed),
+                seconds(c.released).max(seconds(c.pressed) + MIN_PRESS),
+            )
+        }))
+    };
+    let visible = during(
+        clicks
+            .iter()
+            .map(|c| (seconds(c.pressed), seconds(c.released) + LINGER)),
+    );
+
+    // x, y, w and h relative to the badge in 1080p pixels, thickness, color,
+    // and when to draw
+    let mut boxes = vec![
+        (
+            0,
+            0,
+            WIDTH,
+            HEIGHT,
+            "fill".to_string(),
+            "black@0.5",
+            visible.clone(),
+        ),
+        (0, 0, WIDTH, HEIGHT, px(3), "white@0.9", visible),
+        (
+            3,
+            3,
+            17,
+            24,
+            "fill".to_string(),
+            "0xe01b24@0.9",
+            held(Button::Left),
+        ),
+        (
+            20,
+            3,
+            17,
+            24,
+            "fill".to_string(),
+            "0xe01b24@0.9",
+            held(Button::Right),
+        ),
+        (
+            17,
+            6,
+            6,
+            14,
+            "fill".to_string(),
+            "0xe01b24",
+            held(Button::Middle),
+        ),
+    ];
+    for (ring, color) in (1..=3).zip(["0xe01b24@0.8", "0xe01b24@0.5", "0xe01b24@0.3"]) {
+        let step = f64::from(ring - 1) * RIPPLE_STEP;
+        let when = during(clicks.iter().map(|c| {
+            (
+                seconds(c.pressed) + step,
+                seconds(c.pressed) + step + RIPPLE_STEP,
+            )
+        }));
+        let grow = ring * 6;
+        boxes.push((
+            -grow,
+            -grow,
+            WIDTH + 2 * grow,
+            HEIGHT + 2 * grow,
+            px(2),
+            color,
+            when,
+        ));
+    }
+
+    let filters: Vec<String> = boxes
+        .into_iter()
+        .filter(|(.., when)| !when.is_empty())
+        .map(|(dx, dy, w, h, thickness, color, when)| {
+            format!(
+                "drawbox=x={}:y={}:w={}:h={}:t={}:color={}:enable='{}'",
+                offset(&x, dx),
+                offset(&y, dy),
+                px(w),
+                px(h),
+                thickness,
+                color,
+                when
+            )
+        })
+        .collect();
+    filters.join(",")
+}
+
+/// Start drawing `clicks` onto the recording `file`. The result is written
+/// next to it, to be moved over the original once done.
+pub fn highlight(file: &str, clicks: &[Click], duration: Duration) -> Result<RunningJob> {
+    let input = Path::new(file);
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = input.extension().unwrap_or_default().to_string_lossy();
+    let output = input.with_file_name(format!("{}-clicks.{}", stem, extension));
+
+    let filter = filter(clicks, config::get().clicks_corner);
+    let mut args: Vec<OsString> = vec!["-i".into(), file.into()];
+    args.extend(
+        [
+            "-vf", &filter, "-c:v", "libx264", "-preset", "veryfast", "-crf", "20", "-c:a", "copy",
+        ]
+        .map(OsString::from),
+    );
+    postprocess::ffmpeg(args, output, duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mice_have_buttons() {
+        // A mouse and a keyboard, as the kernel lists their keys
+        assert!(has_buttons("1f0000 0 0 0 0"));
+        assert!(!has_buttons(
+            "10000 7 ff9f207a c14057ff febeffdf ffefffff ffffffff fffffffe"
+        ));
+        assert!(!has_buttons("0"));
+    }
+
+    #[test]
+    fn time_spent_paused_is_left_out() {
+        // Recording for 10s, of which the last 2s paused, with the left
+        // button held since 5s in
+        let mut log = Log {
+            started: Instant::now() - Duration::from_secs(10),
+            paused_since: Some(Instant::now() - Duration::from_secs(2)),
+            paused_for: Duration::ZERO,
+            held: vec![(Button::Left, Duration::from_secs(5))],
+            clicks: Vec::new(),
+        };
+        log.release(Button::Left);
+        log.press(Button::Right);
+
+        assert_eq!(log.clicks.len(), 1);
+        let released = log.clicks[0].released.as_secs_f64();
+        assert!((released - 8.0).abs() < 0.1, "released at {}", released);
+        assert!(log.held.is_empty(), "presses while paused are dropped");
+    }
+
+    #[test]
+    fn each_button_lights_only_while_held() {
+        let click = |button, pressed: f64, released: f64| Click {
+            button,
+            pressed: Duration::from_secs_f64(pressed),
+            released: Duration::from_secs_f64(released),
+        };
+        let filter = filter(
+            &[
+                click(Button::Left, 1.0, 1.5),
+                click(Button::Right, 3.0, 3.05),
+            ],
+            Corner::BottomLeft,
+        );
+
+        assert!(filter.contains("enable='between(t,1.000,1.500)'"));
+        // Quick clicks are shown for a moment at least
+        assert!(filter.contains("enable='between(t,3.000,3.150)'"));
+        // Nothing was middle-clicked
+        assert_eq!(filter.matches("drawbox").count(), 7);
+    }
+}
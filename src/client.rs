@@ -0,0 +1,517 @@
+use anstyle::Style;
+use futures_util::StreamExt;
+use std::os::fd::OwnedFd;
+use std::time::Duration;
+use zbus::proxy::CacheProperties;
+use zbus::{Connection, proxy};
+
+use crate::ReplayAction;
+use crate::compositor;
+use crate::error::{self, Error};
+use crate::format;
+use crate::i18n::t;
+use crate::menu;
+use crate::persist::PersistentState;
+use crate::recorder;
+use crate::stats::Statistics;
+use crate::table::{self, Table};
+
+/// DBus proxy for the daemon's interface
+#[proxy(
+    interface = "org.matthew_hre.NiriScreenRecorder",
+    default_service = "org.matthew_hre.NiriScreenRecorder",
+    default_path = "/org/matthew_hre/NiriScreenRecorder"
+)]
+trait Recorder {
+    fn start_recording(&self) -> zbus::Result<bool>;
+    fn start_recording_region(&self, region: &str) -> zbus::Result<bool>;
+    fn start_recording_to_pipe(&self, region: &str) -> zbus::Result<zbus::zvariant::OwnedFd>;
+    fn start_recording_window(&self, id: u64) -> zbus::Result<bool>;
+    fn start_recording_outputs(&self, names: &[String]) -> zbus::Result<bool>;
+    fn stop_recording(&self) -> zbus::Result<bool>;
+    fn toggle_recording(&self) -> zbus::Result<bool>;
+    fn pause_recording(&self) -> zbus::Result<bool>;
+    fn resume_recording(&self) -> zbus::Result<bool>;
+    fn add_marker(&self, label: &str) -> zbus::Result<bool>;
+    fn start_replay(&self) -> zbus::Result<bool>;
+    fn stop_replay(&self) -> zbus::Result<bool>;
+    fn save_replay(&self) -> zbus::Result<bool>;
+    fn cancel_pending_start(&self) -> zbus::Result<bool>;
+    fn tag_recording(&self, file: &str, tags: &[String]) -> zbus::Result<bool>;
+    fn is_recording(&self) -> zbus::Result<bool>;
+    fn is_paused(&self) -> zbus::Result<bool>;
+    fn is_start_queued(&self) -> zbus::Result<bool>;
+    fn get_current_file(&self) -> zbus::Result<String>;
+    fn get_statistics(&self) -> zbus::Result<Statistics>;
+
+    #[zbus(signal)]
+    fn recording_started(&self) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn recording_paused(&self) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn recording_resumed(&self) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn recording_stopped(&self, file_path: &str) -> zbus::Result<()>;
+}
+
+/// Why a CLI command failed
+pub enum Failure {
+    /// The daemon couldn't be reached
+    Unreachable(zbus::Error),
+    /// Calling the daemon to do `action` failed
+    Call { action: String, error: zbus::Error },
+    /// Something failed on this side of the bus
+    Local(Box<dyn std::error::Error>),
+    /// The command couldn't be carried out, for the reason given
+    Refused(String),
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        Failure::Local(Box::new(e))
+    }
+}
+
+impl Failure {
+    fn call(action: String) -> impl FnOnce(zbus::Error) -> Self {
+        |error| Failure::Call { action, error }
+    }
+
+    /// Tell the user what went wrong and return the exit code. Errors the
+    /// daemon returned get their own exit code; anything else is 1.
+    pub fn report(&self) -> i32 {
+        match self {
+            Failure::Call {
+                action,
+                error: zbus::Error::MethodError(name, detail, _),
+            } if name.starts_with(error::DBUS_ERROR_PREFIX) => {
+                eprintln!(
+                    "{}",
+                    t!(
+                        "cli-failed",
+                        action = action,
+                        error = detail.as_deref().unwrap_or(name)
+                    )
+                );
+                error::exit_code(name)
+            }
+            Failure::Unreachable(error) | Failure::Call { error, .. } => {
+                eprintln!("{}", t!("cli-not-running"));
+                eprintln!("{}", t!("cli-details", error = error));
+                1
+            }
+            Failure::Local(e) => {
+                eprintln!("{}", t!("cli-error", error = e));
+                1
+            }
+            Failure::Refused(message) => {
+                eprintln!("{}", message);
+                1
+            }
+        }
+    }
+}
+
+/// A connection to the daemon, shared by every call a command makes
+pub struct Client {
+    recorder: RecorderProxy<'static>,
+}
+
+impl Client {
+    /// Connect to the session bus. The daemon has no properties, so the
+    /// proxy is built without fetching or following them, and the first
+    /// method call is the only other round trip.
+    pub async fn connect() -> Result<Self, Failure> {
+        let connect = async {
+            let connection = Connection::session().await?;
+            RecorderProxy::builder(&connection)
+                .cache_properties(CacheProperties::No)
+                .build()
+                .await
+        };
+        let recorder = connect.await.map_err(Failure::Unreachable)?;
+        Ok(Self { recorder })
+    }
+
+    /// Call StartRecording
+    pub async fn start(&self) -> Result<(), Failure> {
+        let started = self
+            .recorder
+            .start_recording()
+            .await
+            .map_err(Failure::call(t!("cli-action-start")))?;
+        if started {
+            println!("{}", t!("cli-started"));
+        } else {
+            eprintln!("{}", t!("cli-already-recording"));
+        }
+        Ok(())
+    }
+
+    /// Call StartRecordingWindow
+    pub async fn start_window(&self, id: u64) -> Result<(), Failure> {
+        let started = self
+            .recorder
+            .start_recording_window(id)
+            .await
+            .map_err(Failure::call(t!("cli-action-start")))?;
+        if started {
+            println!("{}", t!("cli-started"));
+        } else {
+            eprintln!("{}", t!("cli-already-recording"));
+        }
+        Ok(())
+    }
+
+    /// Call StartRecordingOutputs, with no names for all outputs
+    pub async fn start_outputs(&self, names: &[String]) -> Result<(), Failure> {
+        let started = self
+            .recorder
+            .start_recording_outputs(names)
+            .await
+            .map_err(Failure::call(t!("cli-action-start")))?;
+        if started {
+            println!("{}", t!("cli-started"));
+        } else {
+            eprintln!("{}", t!("cli-already-recording"));
+        }
+        Ok(())
+    }
+
+    /// Call StartRecordingRegion with the region of the last recording
+    pub async fn start_last_region(&self) -> Result<(), Failure> {
+        let region = PersistentState::load()
+            .last_region
+            .ok_or_else(|| Failure::Refused(t!("cli-no-last-region")))?;
+        let started = self
+            .recorder
+            .start_recording_region(&region)
+            .await
+            .map_err(Failure::call(t!("cli-action-start")))?;
+        if started {
+            println!("{}", t!("cli-started"));
+        } else {
+            eprintln!("{}", t!("cli-already-recording"));
+        }
+        Ok(())
+    }
+
+    /// Call StartRecordingToPipe and copy the video to stdout until the
+    /// recording stops. Ctrl+C stops the recording, and what is left of the
+    /// video still goes out, so the reader gets a complete stream. If the
+    /// reader goes away first, the recording is stopped too. The window
+    /// `window_id` is recorded if given, and a region selected otherwise.
+    pub async fn stream(&self, window_id: Option<u64>) -> Result<(), Failure> {
+        let region = match window_id {
+            Some(id) => compositor::window_region(id).await?.to_string(),
+            None => String::new(),
+        };
+        let pipe = self
+            .recorder
+            .start_recording_to_pipe(&region)
+            .await
+            .map_err(Failure::call(t!("cli-action-start")))?;
+        let mut pipe = tokio::fs::File::from_std(std::fs::File::from(OwnedFd::from(pipe)));
+        let mut stdout = tokio::io::stdout();
+        let mut copy = std::pin::pin!(tokio::io::copy(&mut pipe, &mut stdout));
+
+        let copied = tokio::select! {
+            copied = &mut copy => copied,
+            _ = tokio::signal::ctrl_c() => {
+                self.recorder.stop_recording().await.ok();
+                copy.await
+            }
+        };
+        match copied {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.recorder.stop_recording().await.ok();
+                if e.kind() == std::io::ErrorKind::BrokenPipe {
+                    Ok(())
+                } else {
+                    Err(Failure::Local(Box::new(e)))
+                }
+            }
+        }
+    }
+
+    /// Pick what to capture from a menu and start it. The daemon is
+    /// connected to while the menu is open.
+    pub async fn start_menu() -> Result<(), Failure> {
+        let (client, mode) =
+            tokio::join!(Self::connect(), tokio::task::spawn_blocking(menu::choose));
+        let mode = mode.map_err(|e| Failure::Local(Box::new(e)))??;
+        let Some(mode) = mode else {
+            return Ok(());
+        };
+        let client = client?;
+
+        let region = match mode {
+            menu::Mode::Region => return client.start().await,
+            menu::Mode::Replay => return client.replay(ReplayAction::Save).await,
+            menu::Mode::Output => compositor::get()
+                .focused_output()
+                .await?
+                .map(|o| o.region()),
+            menu::Mode::Window => compositor::get()
+                .focused_window()
+                .await?
+                .and_then(|w| w.region),
+        };
+        let region = region.ok_or_else(|| Failure::Refused(t!("cli-nothing-focused")))?;
+
+        let started = client
+            .recorder
+            .start_recording_region(&region.to_string())
+            .await
+            .map_err(Failure::call(t!("cli-action-start")))?;
+        if started {
+            println!("{}", t!("cli-started"));
+        } else {
+            eprintln!("{}", t!("cli-already-recording"));
+        }
+        Ok(())
+    }
+
+    /// Call StopRecording
+    pub async fn stop(&self) -> Result<(), Failure> {
+        let stopped = self
+            .recorder
+            .stop_recording()
+            .await
+            .map_err(Failure::Unreachable)?;
+        if stopped {
+            println!("{}", t!("cli-stopped"));
+        } else {
+            eprintln!("{}", t!("cli-not-recording"));
+        }
+        Ok(())
+    }
+
+    /// Call ToggleRecording
+    pub async fn toggle(&self) -> Result<(), Failure> {
+        self.recorder
+            .toggle_recording()
+            .await
+            .map_err(Failure::call(t!("cli-action-toggle")))?;
+        Ok(())
+    }
+
+    /// Call PauseRecording or ResumeRecording
+    pub async fn pause(&self, pause: bool) -> Result<(), Failure> {
+        let changed = if pause {
+            self.recorder
+                .pause_recording()
+                .await
+                .map_err(Failure::call(t!("cli-action-pause")))?
+        } else {
+            self.recorder
+                .resume_recording()
+                .await
+                .map_err(Failure::call(t!("cli-action-resume")))?
+        };
+        match (pause, changed) {
+            (true, true) => println!("{}", t!("cli-paused")),
+            (false, true) => println!("{}", t!("cli-resumed")),
+            (true, false) => eprintln!("{}", t!("cli-not-pausable")),
+            (false, false) => eprintln!("{}", t!("cli-not-paused")),
+        }
+        Ok(())
+    }
+
+    /// Call AddMarker
+    pub async fn mark(&self, label: Option<String>) -> Result<(), Failure> {
+        let marked = self
+            .recorder
+            .add_marker(label.as_deref().unwrap_or_default())
+            .await
+            .map_err(Failure::call(t!("cli-action-mark")))?;
+        if marked {
+            println!("{}", t!("cli-marked"));
+        } else {
+            eprintln!("{}", t!("cli-not-recording"));
+        }
+        Ok(())
+    }
+
+    /// Start, stop or save the replay buffer
+    pub async fn replay(&self, action: ReplayAction) -> Result<(), Failure> {
+        let result = match action {
+            ReplayAction::Start => self.recorder.start_replay().await,
+            ReplayAction::Stop => self.recorder.stop_replay().await,
+            ReplayAction::Save => self.recorder.save_replay().await,
+        };
+        match (
+            action,
+            result.map_err(Failure::call(t!("cli-action-replay")))?,
+        ) {
+            (ReplayAction::Start, true) => println!("{}", t!("cli-replay-started")),
+            (ReplayAction::Start, false) => eprintln!("{}", t!("cli-replay-running")),
+            (ReplayAction::Stop, true) => println!("{}", t!("cli-replay-stopped")),
+            (ReplayAction::Save, true) => {
+                let dir = recorder::output_dir().map_or(String::new(), |d| d.display().to_string());
+                println!("{}", t!("cli-replay-saved", path = dir));
+            }
+            (_, false) => eprintln!("{}", t!("cli-replay-not-running")),
+        }
+        Ok(())
+    }
+
+    /// Call CancelPendingStart
+    pub async fn cancel(&self) -> Result<(), Failure> {
+        let cancelled = self
+            .recorder
+            .cancel_pending_start()
+            .await
+            .map_err(Failure::Unreachable)?;
+        if cancelled {
+            println!("{}", t!("cli-cancelled"));
+        } else {
+            eprintln!("{}", t!("cli-not-selecting"));
+        }
+        Ok(())
+    }
+
+    /// Call TagRecording
+    pub async fn tag(&self, file: &str, tags: Vec<String>) -> Result<(), Failure> {
+        // The index has absolute paths, so accept paths relative to the current directory
+        let file = std::fs::canonicalize(file)
+            .map_or(file.to_string(), |p| p.to_string_lossy().to_string());
+
+        let tagged = self
+            .recorder
+            .tag_recording(&file, &tags)
+            .await
+            .map_err(Failure::call(t!("cli-action-tag")))?;
+        if !tagged {
+            return Err(Failure::Refused(t!("cli-not-in-history", path = file)));
+        }
+        Ok(())
+    }
+
+    /// Check recording status, optionally with daemon statistics. The
+    /// calls go out together rather than one after another.
+    pub async fn status(&self, show_stats: bool) -> Result<(), Failure> {
+        let stats = async {
+            if show_stats {
+                self.recorder.get_statistics().await.map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+        let (recording, paused, queued, file, stats) = tokio::try_join!(
+            self.recorder.is_recording(),
+            self.recorder.is_paused(),
+            self.recorder.is_start_queued(),
+            self.recorder.get_current_file(),
+            stats,
+        )
+        .map_err(Failure::Unreachable)?;
+
+        let mut table = Table::labels();
+        let mut line = |label: &str, value: String, style: Style| {
+            table.label(&t!(label), value, style);
+        };
+        if recording {
+            line("status-recording", t!("status-yes"), table::GOOD);
+            if paused {
+                line("status-paused", t!("status-yes"), table::WARN);
+            }
+            line("status-file", file, Style::new());
+        } else {
+            line("status-recording", t!("status-no"), table::DIM);
+            if queued {
+                line("status-start-queued", t!("status-yes"), table::WARN);
+            }
+        }
+
+        if let Some(stats) = stats {
+            let uptime = format::duration(Duration::from_secs(stats.uptime));
+            line("status-uptime", uptime, Style::new());
+            line(
+                "status-recordings",
+                stats.recordings.to_string(),
+                Style::new(),
+            );
+            line(
+                "status-bytes-written",
+                format::bytes(stats.bytes_written),
+                Style::new(),
+            );
+            if recording {
+                line(
+                    "status-encode-fps",
+                    stats.encode_fps.to_string(),
+                    Style::new(),
+                );
+                let style = if stats.dropped_frames > 0 {
+                    table::WARN
+                } else {
+                    Style::new()
+                };
+                line(
+                    "status-dropped-frames",
+                    stats.dropped_frames.to_string(),
+                    style,
+                );
+            }
+        }
+        table.print();
+
+        Ok(())
+    }
+
+    /// Print a `state file elapsed` line whenever the recording state
+    /// changes, and every second while recording, for eww and polybar to
+    /// follow. The file is `-` when there is none, and the state is
+    /// `queued` while a start waits for the last recording to be finalized
+    /// and `offline` while the daemon isn't running.
+    pub async fn subscribe_status(&self) -> Result<(), Failure> {
+        let recorder = &self.recorder;
+        let (mut started, mut stopped, mut paused, mut resumed) = tokio::try_join!(
+            recorder.receive_recording_started(),
+            recorder.receive_recording_stopped(),
+            recorder.receive_recording_paused(),
+            recorder.receive_recording_resumed(),
+        )
+        .map_err(Failure::Unreachable)?;
+
+        let mut last = String::new();
+        loop {
+            let status = tokio::try_join!(
+                recorder.is_recording(),
+                recorder.is_paused(),
+                recorder.is_start_queued(),
+                recorder.get_current_file(),
+                recorder.get_statistics(),
+            );
+            let (line, interval) = match status {
+                Ok((true, paused, _, file, stats)) => {
+                    let state = if paused { "paused" } else { "recording" };
+                    let file = if file.is_empty() {
+                        "-".to_string()
+                    } else {
+                        file
+                    };
+                    (format!("{} {} {}", state, file, stats.elapsed), 1)
+                }
+                Ok((false, _, true, ..)) => ("queued - 0".to_string(), 5),
+                Ok((false, ..)) => ("idle - 0".to_string(), 5),
+                Err(_) => ("offline - 0".to_string(), 5),
+            };
+
+            if line != last {
+                println!("{}", line);
+                last = line;
+            }
+
+            tokio::select! {
+                _ = started.next() => {}
+                _ = stopped.next() => {}
+                _ = paused.next() => {}
+                _ = resumed.next() => {}
+                _ = tokio::time::sleep(Duration::from_secs(interval)) => {}
+            }
+        }
+    }
+}
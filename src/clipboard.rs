@@ -0,0 +1,164 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use wl_clipboard_rs::copy::{MimeSource, MimeType, Options, Source};
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::format;
+
+/// How the clipboard is accessed
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The Wayland data-control protocol, falling back to arboard
+    Auto,
+    /// The Wayland data-control protocol, served from a background thread
+    Wayland,
+    /// The `wl-copy` command from wl-clipboard
+    WlCopy,
+    /// The arboard crate
+    Arboard,
+}
+
+impl FromStr for Backend {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Backend::Auto),
+            "wayland" => Ok(Backend::Wayland),
+            "wl-copy" => Ok(Backend::WlCopy),
+            "arboard" => Ok(Backend::Arboard),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Something to put on the clipboard
+enum Content {
+    Text(String),
+    File(PathBuf),
+}
+
+/// Put text on the clipboard
+pub fn copy_text(text: &str) -> Result<()> {
+    copy(Content::Text(text.to_string()))
+}
+
+/// Put a file on the clipboard, so pasting it into a file manager, browser,
+/// or chat app attaches the file instead of its path. On Wayland the file is
+/// also offered in the format GTK file managers use. The video data itself
+/// isn't offered, since it would have to be read into memory up front.
+pub fn copy_file(file_path: &str) -> Result<()> {
+    let path = Path::new(file_path);
+    if !path.exists() {
+        return Err(Error::FileNotFound(file_path.to_string()));
+    }
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    copy(Content::File(path))
+}
+
+fn copy(content: Content) -> Result<()> {
+    match config::get().clipboard {
+        Backend::Wayland => copy_wayland(&content),
+        Backend::WlCopy => copy_wl_copy(&content),
+        Backend::Arboard => copy_arboard(content),
+        Backend::Auto => match copy_wayland(&content) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                tracing::debug!(
+                    "Wayland clipboard unavailable ({}), falling back to arboard",
+                    e
+                );
+                copy_arboard(content)
+            }
+        },
+    }
+}
+
+fn file_uri(path: &Path) -> String {
+    format!("file://{}", format::percent_encode_path(path))
+}
+
+fn copy_wayland(content: &Content) -> Result<()> {
+    let bytes = |mime: MimeType, content: Vec<u8>| MimeSource {
+        source: Source::Bytes(content.into_boxed_slice()),
+        mime_type: mime,
+    };
+    let specific = |mime: &str| MimeType::Specific(mime.to_string());
+
+    let sources = match content {
+        Content::Text(text) => vec![bytes(MimeType::Text, text.clone().into_bytes())],
+        Content::File(path) => {
+            let uri = file_uri(path);
+            vec![
+                bytes(
+                    specific("text/uri-list"),
+                    format!("{}\r\n", uri).into_bytes(),
+                ),
+                bytes(
+                    specific("x-special/gnome-copied-files"),
+                    format!("copy\n{}", uri).into_bytes(),
+                ),
+            ]
+        }
+    };
+
+    // Serves paste requests from a background thread until something else
+    // is copied
+    Options::new().copy_multi(sources)?;
+    Ok(())
+}
+
+fn copy_wl_copy(content: &Content) -> Result<()> {
+    let (args, data): (&[&str], String) = match content {
+        Content::Text(text) => (&[], text.clone()),
+        Content::File(path) => (&["--type", "text/uri-list"], file_uri(path)),
+    };
+
+    let command_failed = |source| Error::CommandFailed {
+        program: "wl-copy".to_string(),
+        source,
+    };
+
+    // wl-copy forks into the background to serve pastes once it has read stdin
+    let mut child = Command::new("wl-copy")
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(command_failed)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data.as_bytes()).map_err(command_failed)?;
+    }
+
+    let status = child.wait().map_err(command_failed)?;
+    if !status.success() {
+        return Err(command_failed(std::io::Error::other(format!(
+            "exited with {}",
+            status
+        ))));
+    }
+    Ok(())
+}
+
+fn copy_arboard(content: Content) -> Result<()> {
+    use arboard::SetExtLinux;
+
+    let mut clipboard = arboard::Clipboard::new()?;
+
+    // arboard only serves pastes while the clipboard is alive, so keep it
+    // on a thread until something else is copied
+    std::thread::spawn(move || {
+        let set = clipboard.set().wait();
+        let result = match content {
+            Content::Text(text) => set.text(text),
+            Content::File(path) => set.file_list(&[path]),
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to serve clipboard: {}", e);
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,485 @@
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::geometry::Region;
+use crate::niri;
+
+/// Which compositor to talk to
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// niri if NIRI_SOCKET is set, otherwise the wlroots fallback
+    Auto,
+    Niri,
+    /// sway's IPC where available, generic desktop settings otherwise
+    Wlroots,
+}
+
+impl FromStr for Kind {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Kind::Auto),
+            "niri" => Ok(Kind::Niri),
+            "wlroots" | "sway" => Ok(Kind::Wlroots),
+            _ => Err(()),
+        }
+    }
+}
+
+/// An enabled output and where it is in the global logical space
+pub struct Output {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Physical pixels per logical pixel
+    pub scale: f64,
+}
+
+impl Output {
+    /// The whole output as a region to record
+    pub fn region(&self) -> Region {
+        Region {
+            x: self.x,
+            y: self.y,
+            w: self.width,
+            h: self.height,
+        }
+    }
+}
+
+/// Cursor settings to run slurp with, where configured
+#[derive(Default)]
+pub struct Cursor {
+    pub theme: Option<String>,
+    pub size: Option<u32>,
+}
+
+/// A window, as far as the recorder cares
+pub struct Window {
+    pub title: Option<String>,
+    pub app_id: Option<String>,
+    /// Where the window is, as a region to record, if known
+    pub region: Option<Region>,
+}
+
+/// What the daemon needs to know from the compositor it runs under
+pub trait Compositor: Send + Sync {
+    /// Name used in the log
+    fn name(&self) -> &'static str;
+
+    /// Cursor theme and size to run slurp with. Blocks while config files
+    /// are read or commands run.
+    fn cursor(&self) -> Cursor;
+
+    /// All enabled outputs
+    fn outputs(&self) -> BoxFuture<'_, Result<Vec<Output>>>;
+
+    /// The output that has focus, if any
+    fn focused_output(&self) -> BoxFuture<'_, Result<Option<Output>>>;
+
+    /// The window that has keyboard focus, if any
+    fn focused_window(&self) -> BoxFuture<'_, Result<Option<Window>>>;
+
+    /// The window with the compositor's own id `id`, if it exists, e.g.
+    /// from `niri msg windows`
+    fn window(&self, id: u64) -> BoxFuture<'_, Result<Option<Window>>>;
+}
+
+/// niri, through its IPC socket and config.kdl
+struct Niri;
+
+impl Compositor for Niri {
+    fn name(&self) -> &'static str {
+        "niri"
+    }
+
+    fn cursor(&self) -> Cursor {
+        let settings = niri::settings();
+        Cursor {
+            theme: settings.cursor_theme,
+            size: settings.cursor_size,
+        }
+    }
+
+    fn outputs(&self) -> BoxFuture<'_, Result<Vec<Output>>> {
+        Box::pin(async { Ok(niri_outputs(niri::outputs().await?).await) })
+    }
+
+    fn focused_output(&self) -> BoxFuture<'_, Result<Option<Output>>> {
+        Box::pin(async {
+            let output = niri::focused_output().await?;
+            Ok(niri_outputs(output.into_iter().collect()).await.pop())
+        })
+    }
+
+    fn focused_window(&self) -> BoxFuture<'_, Result<Option<Window>>> {
+        Box::pin(async {
+            let Some(window) = niri::focused_window().await? else {
+                return Ok(None);
+            };
+            // The window has focus, so it is on the focused output
+            let output = self.focused_output().await?;
+            Ok(Some(niri_window(window, output.as_ref())))
+        })
+    }
+
+    fn window(&self, id: u64) -> BoxFuture<'_, Result<Option<Window>>> {
+        Box::pin(async move {
+            let Some(window) = niri::windows().await?.into_iter().find(|w| w.id == id) else {
+                return Ok(None);
+            };
+            let output_name = niri::workspaces()
+                .await?
+                .into_iter()
+                .find(|w| Some(w.id) == window.workspace_id)
+                .and_then(|w| w.output);
+            let output = match output_name {
+                Some(name) => self.outputs().await?.into_iter().find(|o| o.name == name),
+                None => None,
+            };
+            Ok(Some(niri_window(window, output.as_ref())))
+        })
+    }
+}
+
+/// A window as niri reports it. niri places it relative to the view of its
+/// workspace on `output`, so its region is only known if it is visible
+/// there.
+fn niri_window(window: niri::Window, output: Option<&Output>) -> Window {
+    let mut region = None;
+    if let Some(layout) = &window.layout
+        && let Some((tile_x, tile_y)) = layout.tile_pos_in_workspace_view
+        && let (Ok(w), Ok(h)) = (
+            u32::try_from(layout.window_size.0),
+            u32::try_from(layout.window_size.1),
+        )
+        && let Some(output) = output
+    {
+        let x = output.x + (tile_x + layout.window_offset_in_tile.0).round() as i32;
+        let y = output.y + (tile_y + layout.window_offset_in_tile.1).round() as i32;
+        region = Some(Region { x, y, w, h });
+    }
+
+    Window {
+        title: window.title,
+        app_id: window.app_id,
+        region,
+    }
+}
+
+/// The enabled ones of `outputs` as niri reports them. Versions that don't
+/// report the scale get the one set in the config, which is then read once
+/// for all of them.
+async fn niri_outputs(outputs: Vec<niri::Output>) -> Vec<Output> {
+    let unscaled = outputs
+        .iter()
+        .any(|o| o.logical.is_some_and(|l| l.scale.is_none()));
+    let settings = if unscaled {
+        tokio::task::spawn_blocking(niri::settings)
+            .await
+            .unwrap_or_default()
+    } else {
+        niri::Settings::default()
+    };
+    outputs
+        .into_iter()
+        .filter_map(|o| niri_output(o, &settings))
+        .collect()
+}
+
+fn niri_output(output: niri::Output, settings: &niri::Settings) -> Option<Output> {
+    let logical = output.logical?;
+    let scale = logical
+        .scale
+        .or_else(|| settings.output_scale(&output.name))
+        .unwrap_or(1.0);
+    Some(Output {
+        name: output.name,
+        x: logical.x,
+        y: logical.y,
+        width: logical.width,
+        height: logical.height,
+        scale,
+    })
+}
+
+/// Other wlroots compositors. Outputs and windows come from `swaymsg`, so
+/// they are only known on sway; the cursor theme comes from sway's config
+/// or the desktop settings.
+struct Wlroots;
+
+/// Run `swaymsg -t <kind>` and parse its JSON reply
+async fn swaymsg<T: serde::de::DeserializeOwned>(kind: &str) -> Result<T> {
+    let output = tokio::process::Command::new("swaymsg")
+        .args(["-r", "-t", kind])
+        .output()
+        .await
+        .map_err(|source| Error::CommandFailed {
+            program: "swaymsg".to_string(),
+            source,
+        })?;
+
+    if !output.status.success() {
+        return Err(Error::CompositorIpc(format!(
+            "swaymsg exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| Error::CompositorIpc(format!("unexpected swaymsg reply: {}", e)))
+}
+
+/// Position and size in sway's global layout
+#[derive(Deserialize)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+/// An output as reported by `swaymsg -t get_outputs`
+#[derive(Deserialize)]
+struct SwayOutput {
+    name: String,
+    active: bool,
+    #[serde(default)]
+    focused: bool,
+    rect: Rect,
+    scale: Option<f64>,
+}
+
+impl SwayOutput {
+    fn into_output(self) -> Output {
+        Output {
+            name: self.name,
+            x: self.rect.x,
+            y: self.rect.y,
+            width: self.rect.width,
+            height: self.rect.height,
+            scale: self.scale.unwrap_or(1.0),
+        }
+    }
+}
+
+/// A node of sway's layout tree
+#[derive(Deserialize)]
+struct Node {
+    #[serde(default)]
+    id: u64,
+    name: Option<String>,
+    rect: Option<Rect>,
+    app_id: Option<String>,
+    #[serde(default)]
+    focused: bool,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    floating_nodes: Vec<Node>,
+}
+
+impl Node {
+    /// The first node, depth first, that `pred` holds for
+    fn find(self, pred: &impl Fn(&Node) -> bool) -> Option<Node> {
+        if pred(&self) {
+            return Some(self);
+        }
+        self.nodes
+            .into_iter()
+            .chain(self.floating_nodes)
+            .find_map(|node| node.find(pred))
+    }
+
+    fn into_window(self) -> Window {
+        Window {
+            title: self.name,
+            app_id: self.app_id,
+            region: self.rect.map(|r| Region {
+                x: r.x,
+                y: r.y,
+                w: r.width,
+                h: r.height,
+            }),
+        }
+    }
+}
+
+impl Compositor for Wlroots {
+    fn name(&self) -> &'static str {
+        "wlroots"
+    }
+
+    fn cursor(&self) -> Cursor {
+        // e.g. "seat * xcursor_theme Adwaita 24"
+        let from_sway = dirs::config_dir()
+            .and_then(|dir| std::fs::read_to_string(dir.join("sway/config")).ok())
+            .and_then(|content| {
+                content.lines().find_map(|line| {
+                    let mut words = line.split_whitespace();
+                    if words.next() != Some("seat") {
+                        return None;
+                    }
+                    let mut words = words.skip_while(|w| *w != "xcursor_theme").skip(1);
+                    Some(Cursor {
+                        theme: Some(words.next()?.to_string()),
+                        size: words.next().and_then(|size| size.parse().ok()),
+                    })
+                })
+            });
+        if let Some(cursor) = from_sway {
+            return cursor;
+        }
+
+        let gsettings = |key| {
+            let output = std::process::Command::new("gsettings")
+                .args(["get", "org.gnome.desktop.interface", key])
+                .output()
+                .ok()
+                .filter(|o| o.status.success())?;
+            let value = String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .trim_matches('\'')
+                .to_string();
+            Some(value).filter(|v| !v.is_empty())
+        };
+        Cursor {
+            theme: gsettings("cursor-theme"),
+            size: gsettings("cursor-size").and_then(|size| size.parse().ok()),
+        }
+    }
+
+    fn outputs(&self) -> BoxFuture<'_, Result<Vec<Output>>> {
+        Box::pin(async {
+            let outputs: Vec<SwayOutput> = swaymsg("get_outputs").await?;
+            Ok(outputs
+                .into_iter()
+                .filter(|o| o.active)
+                .map(SwayOutput::into_output)
+                .collect())
+        })
+    }
+
+    fn focused_output(&self) -> BoxFuture<'_, Result<Option<Output>>> {
+        Box::pin(async {
+            let outputs: Vec<SwayOutput> = swaymsg("get_outputs").await?;
+            Ok(outputs
+                .into_iter()
+                .find(|o| o.active && o.focused)
+                .map(SwayOutput::into_output))
+        })
+    }
+
+    fn focused_window(&self) -> BoxFuture<'_, Result<Option<Window>>> {
+        Box::pin(async {
+            let tree: Node = swaymsg("get_tree").await?;
+            Ok(tree.find(&|node| node.focused).map(Node::into_window))
+        })
+    }
+
+    fn window(&self, id: u64) -> BoxFuture<'_, Result<Option<Window>>> {
+        Box::pin(async move {
+            let tree: Node = swaymsg("get_tree").await?;
+            Ok(tree.find(&|node| node.id == id).map(Node::into_window))
+        })
+    }
+}
+
+/// The compositor the daemon runs under, picked on first use
+pub fn get() -> &'static dyn Compositor {
+    static COMPOSITOR: OnceLock<Box<dyn Compositor>> = OnceLock::new();
+    COMPOSITOR
+        .get_or_init(|| {
+            let compositor: Box<dyn Compositor> = match config::get().compositor {
+                Kind::Niri => Box::new(Niri),
+                Kind::Wlroots => Box::new(Wlroots),
+                Kind::Auto if std::env::var_os("NIRI_SOCKET").is_some() => Box::new(Niri),
+                Kind::Auto => Box::new(Wlroots),
+            };
+            tracing::debug!("Using the {} compositor integration", compositor.name());
+            compositor
+        })
+        .as_ref()
+}
+
+/// The outputs `region` overlaps, starting with the one its top-left
+/// corner is on
+pub async fn outputs_under(region: &Region) -> Vec<Output> {
+    let outputs = match get().outputs().await {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            tracing::debug!("Could not look up outputs: {}", e);
+            return Vec::new();
+        }
+    };
+    let mut under: Vec<Output> = outputs
+        .into_iter()
+        .filter(|o| o.region().intersection(region).is_some())
+        .collect();
+    under.sort_by_key(|o| !o.region().contains(region.x, region.y));
+    under
+}
+
+/// Where the window with the compositor's id `id` is, to record it
+pub async fn window_region(id: u64) -> Result<Region> {
+    get()
+        .window(id)
+        .await?
+        .and_then(|w| w.region)
+        .ok_or(Error::WindowNotFound(id))
+}
+
+/// The region spanning the outputs named `names`, or all enabled outputs if
+/// `names` is empty, to record them as one video. The region is in logical
+/// pixels, which don't map onto one grid of physical pixels if the outputs
+/// have different scales, so those are refused.
+pub async fn outputs_region(names: &[String]) -> Result<Region> {
+    let outputs = get().outputs().await?;
+    let selected: Vec<&Output> = if names.is_empty() {
+        outputs.iter().collect()
+    } else {
+        names
+            .iter()
+            .map(|name| {
+                outputs
+                    .iter()
+                    .find(|o| &o.name == name)
+                    .ok_or_else(|| Error::OutputNotFound(name.clone()))
+            })
+            .collect::<Result<_>>()?
+    };
+
+    if let Some(first) = selected.first()
+        && selected
+            .iter()
+            .any(|o| (o.scale - first.scale).abs() > 1e-6)
+    {
+        let scales: Vec<String> = selected
+            .iter()
+            .map(|o| format!("{} at {}", o.name, o.scale))
+            .collect();
+        return Err(Error::MixedScales(scales.join(", ")));
+    }
+
+    let regions: Vec<Region> = selected.iter().map(|o| o.region()).collect();
+    Region::bounding(&regions).ok_or_else(|| Error::OutputNotFound("*".to_string()))
+}
+
+/// The window that has keyboard focus, if it can be found out
+pub async fn focused_window() -> Option<Window> {
+    match get().focused_window().await {
+        Ok(window) => window,
+        Err(e) => {
+            tracing::debug!("Could not look up the focused window: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,471 @@
+use serde_json::{Map, Value, json};
+use std::cell::RefCell;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::clipboard;
+use crate::compositor;
+use crate::dnd;
+use crate::postprocess;
+use crate::retention;
+use crate::session;
+use crate::upload;
+use crate::webcam;
+
+const PREFIX: &str = "NIRI_SCREEN_RECORDER_";
+
+// The config is read while logging is being set up, so problems with it
+// are reported on stderr rather than through tracing.
+
+/// Declare the config struct along with its field docs, which describe the
+/// options in `config schema`
+macro_rules! documented {
+    (
+        $(#[doc = $doc:literal])*
+        pub struct $name:ident {
+            $($(#[doc = $field_doc:literal])+ pub $field:ident: $ty:ty,)*
+        }
+    ) => {
+        $(#[doc = $doc])*
+        pub struct $name {
+            $($(#[doc = $field_doc])+ pub $field: $ty,)*
+        }
+
+        /// Each field's doc comment, by field name
+        const DOCS: &[(&str, &[&str])] = &[$((stringify!($field), &[$($field_doc),+])),*];
+    };
+}
+
+documented! {
+    /// Settings read from `NIRI_SCREEN_RECORDER_*` environment variables
+    pub struct Config {
+        /// Recording framerate
+        pub fps: u32,
+        /// Container format passed to gpu-screen-recorder
+        pub container: String,
+        /// Video codec, or `None` to let gpu-screen-recorder pick
+        pub codec: Option<String>,
+        /// Output directory, or `None` for ~/Videos/Screencasts
+        pub output_dir: Option<PathBuf>,
+        /// Record under a hidden `.partial` name, moved into place once finished
+        pub partial: bool,
+        /// Directory to record into instead, e.g. a tmpfs, moved from once finished
+        pub scratch_dir: Option<PathBuf>,
+        /// Custom command used by the "Open File" action
+        pub open_cmd: Option<String>,
+        /// dmenu-style command that `start --menu` shows the capture modes with
+        pub menu_cmd: Option<String>,
+        /// Where the "Share" action and `upload-last` upload to
+        pub upload: Option<upload::Backend>,
+        /// Endpoint of the HTTP upload provider
+        pub upload_url: Option<String>,
+        /// "PUT" (the default) or "POST" for the HTTP upload provider
+        pub upload_method: Option<String>,
+        /// Header sent with HTTP uploads, e.g. "Authorization: Bearer ..."
+        pub upload_auth: Option<String>,
+        /// Region used to sign S3 uploads
+        pub upload_region: String,
+        /// User (or S3 access key id) for S3 and WebDAV uploads
+        pub upload_user: Option<String>,
+        /// File holding the upload credentials
+        pub upload_secrets: Option<PathBuf>,
+        /// Where uploaded files can be downloaded from, if not the upload URL
+        pub upload_public_url: Option<String>,
+        /// Command that uploads a recording and prints its URL
+        pub upload_cmd: Option<String>,
+        /// How often a failed upload is retried
+        pub upload_retries: u32,
+        /// Which compositor outputs, windows and the cursor theme are looked up in
+        pub compositor: compositor::Kind,
+        /// How notification actions access the clipboard
+        pub clipboard: clipboard::Backend,
+        /// How long region selection may take before it is abandoned
+        pub select_timeout: Option<Duration>,
+        /// How long gpu-screen-recorder may take to finish the file once stopped
+        pub stop_timeout: Option<Duration>,
+        /// Log filter, e.g. "debug" or "niri_screen_recorder=trace"
+        pub log_level: Option<String>,
+        /// Whether the daemon logs to the systemd journal
+        pub log_journal: bool,
+        /// Directory for daily-rotated daemon log files, or `None` to disable
+        pub log_dir: Option<PathBuf>,
+        /// Warn when a recording is started on battery
+        pub battery_warn: bool,
+        /// Framerate to record at on battery, instead of `fps`
+        pub battery_fps: Option<u32>,
+        /// gpu-screen-recorder quality preset to use on battery
+        pub battery_quality: Option<String>,
+        /// Notify when the battery drops below this percentage mid-recording
+        pub battery_threshold: Option<f64>,
+        /// Hold the performance power profile while recording
+        pub performance: bool,
+        /// Run gpu-screen-recorder in its own transient systemd scope
+        pub scope: bool,
+        /// Nice level for gpu-screen-recorder
+        pub nice: Option<i32>,
+        /// systemd CPUWeight= for the recorder scope
+        pub cpu_weight: Option<u32>,
+        /// systemd IOWeight= for the recorder scope
+        pub io_weight: Option<u32>,
+        /// Recordings shorter than this are deleted as accidental
+        pub min_duration: Option<Duration>,
+        /// Ask whether to keep each recording before it is moved to the output directory
+        pub confirm: bool,
+        /// Write a JSON file with capture metadata next to each recording
+        pub sidecar: bool,
+        /// Trash the oldest recordings once they take up more than this many MiB
+        pub retention_size: Option<u64>,
+        /// Trash recordings older than this many days
+        pub retention_days: Option<u64>,
+        /// Keep at most this many recordings, trashing the oldest
+        pub retention_count: Option<u32>,
+        /// Which files in the output directory the retention limits apply to
+        pub retention_scope: retention::Scope,
+        /// Pause the recording while the screen is locked
+        pub pause_on_lock: bool,
+        /// What happens to the recording when the system suspends
+        pub on_suspend: session::OnSuspend,
+        /// How many seconds the replay buffer keeps
+        pub replay_seconds: u32,
+        /// Run the replay buffer while a game has GameMode active
+        pub replay_on_game: bool,
+        /// Pause the recording after this long without input
+        pub idle_pause: Option<Duration>,
+        /// `idle_pause` for recordings started on battery
+        pub battery_idle_pause: Option<Duration>,
+        /// Address to serve Prometheus metrics on
+        pub metrics_addr: Option<SocketAddr>,
+        /// Port on 127.0.0.1 to serve an MJPEG preview of the recording on
+        pub preview_port: Option<u16>,
+        /// Draw a border around the captured region while recording
+        pub indicator: bool,
+        /// When to hold back all but error notifications
+        pub quiet: dnd::Mode,
+        /// Put the notification server in do-not-disturb mode while recording
+        pub dnd: bool,
+        /// Show a StatusNotifierItem tray icon
+        pub tray: bool,
+        /// v4l2 device to composite into a corner of each recording
+        pub webcam: Option<PathBuf>,
+        /// Corner the webcam is shown in
+        pub webcam_corner: webcam::Corner,
+        /// Width of the webcam picture, in percent of the recording's width
+        pub webcam_size: u32,
+        /// Log mouse clicks while recording and highlight them in the video
+        pub clicks: bool,
+        /// Corner the click highlights are shown in
+        pub clicks_corner: webcam::Corner,
+        /// Jobs to run on each saved recording, in order
+        pub post_process: Vec<postprocess::Job>,
+    }
+}
+
+impl Config {
+    fn from_env() -> Self {
+        Self {
+            fps: parse("FPS", 60),
+            container: parse("CONTAINER", "mp4".to_string()),
+            codec: var("CODEC"),
+            output_dir: var("OUTPUT_DIR").map(PathBuf::from),
+            partial: flag("PARTIAL", false),
+            scratch_dir: var("SCRATCH_DIR").map(PathBuf::from),
+            open_cmd: var("OPEN_CMD"),
+            menu_cmd: var("MENU_CMD"),
+            upload: optional("UPLOAD"),
+            upload_url: var("UPLOAD_URL"),
+            upload_method: var("UPLOAD_METHOD"),
+            upload_auth: var("UPLOAD_AUTH"),
+            upload_region: parse("UPLOAD_REGION", "us-east-1".to_string()),
+            upload_user: var("UPLOAD_USER"),
+            upload_secrets: var("UPLOAD_SECRETS").map(PathBuf::from),
+            upload_public_url: var("UPLOAD_PUBLIC_URL"),
+            upload_cmd: var("UPLOAD_CMD"),
+            upload_retries: parse("UPLOAD_RETRIES", 2),
+            compositor: parse("COMPOSITOR", compositor::Kind::Auto),
+            clipboard: parse("CLIPBOARD", clipboard::Backend::Auto),
+            select_timeout: seconds("SELECT_TIMEOUT", 120),
+            stop_timeout: seconds("STOP_TIMEOUT", 60),
+            log_level: var("LOG_LEVEL"),
+            log_journal: flag("LOG_JOURNAL", true),
+            log_dir: var("LOG_DIR").map(PathBuf::from),
+            battery_warn: flag("BATTERY_WARN", true),
+            battery_fps: optional("BATTERY_FPS"),
+            battery_quality: var("BATTERY_QUALITY"),
+            battery_threshold: Some(parse("BATTERY_THRESHOLD", 15.0)).filter(|t| *t > 0.0),
+            performance: flag("PERFORMANCE", false),
+            scope: flag("SCOPE", true),
+            nice: optional("NICE"),
+            cpu_weight: optional("CPU_WEIGHT"),
+            io_weight: optional("IO_WEIGHT"),
+            min_duration: seconds("MIN_DURATION", 0),
+            confirm: flag("CONFIRM", false),
+            sidecar: flag("SIDECAR", false),
+            retention_size: optional("RETENTION_SIZE").filter(|s| *s > 0),
+            retention_days: optional("RETENTION_DAYS").filter(|d| *d > 0),
+            retention_count: optional("RETENTION_COUNT").filter(|c| *c > 0),
+            retention_scope: parse("RETENTION_SCOPE", retention::Scope::All),
+            pause_on_lock: flag("PAUSE_ON_LOCK", true),
+            on_suspend: parse("ON_SUSPEND", session::OnSuspend::Stop),
+            idle_pause: seconds("IDLE_PAUSE", 0),
+            battery_idle_pause: seconds("BATTERY_IDLE_PAUSE", 0),
+            replay_seconds: parse("REPLAY_SECONDS", 30u32).clamp(5, 1200),
+            replay_on_game: flag("REPLAY_ON_GAME", false),
+            metrics_addr: optional("METRICS_ADDR"),
+            preview_port: optional("PREVIEW_PORT"),
+            indicator: flag("INDICATOR", false),
+            quiet: parse("QUIET", dnd::Mode::Auto),
+            dnd: flag("DND", false),
+            tray: flag("TRAY", true),
+            webcam: var("WEBCAM").map(PathBuf::from),
+            webcam_corner: parse("WEBCAM_CORNER", webcam::Corner::BottomRight),
+            webcam_size: parse("WEBCAM_SIZE", 25u32).clamp(5, 100),
+            clicks: flag("CLICKS", false),
+            clicks_corner: parse("CLICKS_CORNER", webcam::Corner::BottomLeft),
+            post_process: list("POST_PROCESS"),
+        }
+    }
+}
+
+/// The daemon configuration, read from the environment on first use
+pub fn get() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(Config::from_env)
+}
+
+/// An option as seen while describing the config
+struct Described {
+    name: &'static str,
+    schema: Map<String, Value>,
+    /// Added to the description
+    unit: Option<&'static str>,
+}
+
+thread_local! {
+    /// Options seen so far while describing the config, rather than reading
+    /// it from the environment
+    static DESCRIBING: RefCell<Option<Vec<Described>>> = const { RefCell::new(None) };
+}
+
+/// While describing the config, add `schema` to what is known about the
+/// option `name`. The helper a field is read with describes it first, so
+/// the more specific helpers it builds on only fill in what is missing.
+/// Returns whether the config is being described.
+fn describe(name: &'static str, schema: Value, unit: Option<&'static str>) -> bool {
+    DESCRIBING.with_borrow_mut(|describing| {
+        let Some(options) = describing else {
+            return false;
+        };
+        let index = match options.iter().position(|o| o.name == name) {
+            Some(index) => index,
+            None => {
+                options.push(Described {
+                    name,
+                    schema: Map::new(),
+                    unit: None,
+                });
+                options.len() - 1
+            }
+        };
+        let option = &mut options[index];
+        if let Value::Object(schema) = schema {
+            for (key, value) in schema {
+                option.schema.entry(key).or_insert(value);
+            }
+        }
+        option.unit = option.unit.or(unit);
+        true
+    })
+}
+
+/// A JSON Schema for the options, with the environment variables as
+/// properties, for editors and for tools that generate the environment.
+/// It follows from reading the config, so it can't fall out of step.
+pub fn schema() -> Value {
+    DESCRIBING.set(Some(Vec::new()));
+    Config::from_env();
+    let options = DESCRIBING.take().unwrap_or_default();
+
+    let mut properties = Map::new();
+    for option in options {
+        let doc = DOCS
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(option.name))
+            .map(|(_, lines)| lines.iter().map(|l| l.trim()).collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        let mut schema = option.schema;
+        let description = match option.unit {
+            Some(unit) => format!("{} ({})", doc, unit),
+            None => doc,
+        };
+        schema.insert("description".to_string(), Value::String(description));
+        properties.insert(format!("{}{}", PREFIX, option.name), Value::Object(schema));
+    }
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "niri-screen-recorder configuration",
+        "description": "Environment variables read by niri-screen-recorder. \
+            Lists are given comma-separated.",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+/// What the values of an option look like, for the schema
+trait Setting: FromStr + PartialEq {
+    /// The JSON Schema type
+    const TYPE: &str = "string";
+    /// The accepted words, for options that take one of a few
+    const CHOICES: &[&str] = &[];
+
+    fn schema() -> Value {
+        let mut schema = json!({ "type": Self::TYPE });
+        if !Self::CHOICES.is_empty() {
+            schema["enum"] = json!(Self::CHOICES);
+        }
+        schema
+    }
+
+    /// The value as JSON, for defaults
+    fn to_json(&self) -> Value {
+        Self::CHOICES
+            .iter()
+            .find(|c| c.parse::<Self>().is_ok_and(|v| v == *self))
+            .map_or(Value::Null, |c| json!(c))
+    }
+}
+
+macro_rules! numeric_setting {
+    ($type:literal: $($ty:ty),*) => {
+        $(impl Setting for $ty {
+            const TYPE: &str = $type;
+
+            fn to_json(&self) -> Value {
+                json!(self)
+            }
+        })*
+    };
+}
+
+numeric_setting!("integer": u16, u32, u64, i32);
+numeric_setting!("number": f64);
+
+impl Setting for String {
+    fn to_json(&self) -> Value {
+        json!(self)
+    }
+}
+
+impl Setting for PathBuf {}
+impl Setting for SocketAddr {}
+
+impl Setting for compositor::Kind {
+    const CHOICES: &[&str] = &["auto", "niri", "wlroots", "sway"];
+}
+
+impl Setting for clipboard::Backend {
+    const CHOICES: &[&str] = &["auto", "wayland", "wl-copy", "arboard"];
+}
+
+impl Setting for dnd::Mode {
+    const CHOICES: &[&str] = &["auto", "always", "never"];
+}
+
+impl Setting for postprocess::Job {
+    const CHOICES: &[&str] = &["remux", "gif", "compress"];
+}
+
+impl Setting for retention::Scope {
+    const CHOICES: &[&str] = &["all", "recordings", "replays"];
+}
+
+impl Setting for session::OnSuspend {
+    const CHOICES: &[&str] = &["pause", "stop", "restart"];
+}
+
+impl Setting for upload::Backend {
+    const CHOICES: &[&str] = &["0x0", "0x0.st", "http", "s3", "webdav", "command"];
+}
+
+impl Setting for webcam::Corner {
+    const CHOICES: &[&str] = &["top-left", "top-right", "bottom-left", "bottom-right"];
+}
+
+/// Read a variable, treating empty values as unset
+fn var(name: &'static str) -> Option<String> {
+    if describe(name, json!({ "type": "string" }), None) {
+        return None;
+    }
+    std::env::var(format!("{}{}", PREFIX, name))
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Read and parse a variable, ignoring (and reporting) invalid values
+fn optional<T: Setting>(name: &'static str) -> Option<T> {
+    describe(name, T::schema(), None);
+    let value = var(name)?;
+    match value.parse() {
+        Ok(v) => Some(v),
+        Err(_) => {
+            eprintln!("Invalid value for {}{}: {}", PREFIX, name, value);
+            None
+        }
+    }
+}
+
+fn parse<T: Setting>(name: &'static str, default: T) -> T {
+    describe(name, json!({ "default": default.to_json() }), None);
+    optional(name).unwrap_or(default)
+}
+
+/// Read a comma-separated list, skipping (and reporting) invalid entries
+fn list<T: Setting>(name: &'static str) -> Vec<T> {
+    describe(name, json!({ "type": "array", "items": T::schema() }), None);
+    let Some(value) = var(name) else {
+        return Vec::new();
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .filter_map(|item| match item.parse() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                eprintln!("Invalid entry in {}{}: {}", PREFIX, name, item);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Read a boolean such as "1", "true", "yes", "0", "false" or "no"
+fn flag(name: &'static str, default: bool) -> bool {
+    describe(name, json!({ "type": "boolean", "default": default }), None);
+    match var(name).map(|v| v.to_ascii_lowercase()).as_deref() {
+        Some("1" | "true" | "yes" | "on") => true,
+        Some("0" | "false" | "no" | "off") => false,
+        Some(other) => {
+            eprintln!("Invalid value for {}{}: {}", PREFIX, name, other);
+            default
+        }
+        None => default,
+    }
+}
+
+/// Read a duration in seconds, where 0 disables it
+fn seconds(name: &'static str, default: u64) -> Option<Duration> {
+    describe(
+        name,
+        json!({ "minimum": 0 }),
+        Some("in seconds, 0 to disable"),
+    );
+    Some(parse(name, default))
+        .filter(|s| *s > 0)
+        .map(Duration::from_secs)
+}
@@ -0,0 +1,93 @@
+use nix::errno::Errno;
+use nix::sys::signal::kill;
+use nix::unistd::{ForkResult, Pid, fork, setsid};
+use std::fs::File;
+use std::path::PathBuf;
+
+use crate::error::{Error, Result};
+
+/// PID file of the running daemon, removed again when dropped
+pub struct PidFile {
+    path: PathBuf,
+}
+
+fn pid_file_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("niri-screen-recorder.pid")
+}
+
+/// Fail if another daemon is already running. A PID file left behind by a
+/// daemon that died without cleaning up is reported and removed.
+pub fn ensure_not_running() -> Result<()> {
+    let path = pid_file_path();
+    let Ok(content) = std::fs::read_to_string(&path) else {
+        return Ok(());
+    };
+
+    if let Ok(pid) = content.trim().parse::<i32>()
+        && pid as u32 != std::process::id()
+    {
+        // Signal 0 only checks whether the process exists
+        match kill(Pid::from_raw(pid), None) {
+            Ok(()) | Err(Errno::EPERM) => return Err(Error::AlreadyRunning(pid)),
+            Err(_) => {}
+        }
+    }
+
+    eprintln!("Removing stale PID file {}", path.display());
+    std::fs::remove_file(&path).map_err(Error::PidFile)
+}
+
+impl PidFile {
+    /// Record the current process as the running daemon
+    pub fn create() -> Result<Self> {
+        ensure_not_running()?;
+        let path = pid_file_path();
+        std::fs::write(&path, format!("{}\n", std::process::id())).map_err(Error::PidFile)?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // Only remove the file if it still names us
+        let ours = std::fs::read_to_string(&self.path)
+            .is_ok_and(|c| c.trim() == std::process::id().to_string());
+        if ours {
+            std::fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+/// Detach from the controlling terminal with the classic double fork.
+/// Only the final grandchild returns. Must be called before any threads
+/// (including the tokio runtime) are started.
+pub fn daemonize() -> Result<()> {
+    // SAFETY: the process is still single-threaded at this point
+    let nix_err = |e: Errno| Error::Daemonize(e.into());
+
+    if let ForkResult::Parent { .. } = unsafe { fork() }.map_err(nix_err)? {
+        std::process::exit(0);
+    }
+
+    setsid().map_err(nix_err)?;
+
+    // SAFETY: as above; the first child is single-threaded too
+    if let ForkResult::Parent { .. } = unsafe { fork() }.map_err(nix_err)? {
+        std::process::exit(0);
+    }
+
+    std::env::set_current_dir("/").map_err(Error::Daemonize)?;
+
+    let devnull = File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .map_err(Error::Daemonize)?;
+    nix::unistd::dup2_stdin(&devnull).map_err(nix_err)?;
+    nix::unistd::dup2_stdout(&devnull).map_err(nix_err)?;
+    nix::unistd::dup2_stderr(&devnull).map_err(nix_err)?;
+
+    Ok(())
+}
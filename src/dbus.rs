@@ -1,130 +1,1625 @@
-use std::process::Child;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::os::fd::OwnedFd;
+use std::path::Path;
+use std::pin::Pin;
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::process::Child;
+use tokio::sync::{Notify, RwLock, RwLockWriteGuard, mpsc, oneshot, watch};
 use zbus::object_server::SignalEmitter;
 use zbus::{Connection, interface};
 
+use crate::chapters::{self, Chapter};
+use crate::clicks;
+use crate::compositor;
+use crate::config;
+use crate::dnd;
+use crate::error::Error;
+use crate::format;
+use crate::gamemode;
+use crate::geometry::Region;
+use crate::history;
+use crate::i18n::t;
+use crate::idle;
+use crate::inhibit::Inhibitor;
+use crate::media;
+use crate::metrics;
+use crate::niri;
 use crate::notifications;
-use crate::recorder;
+use crate::overlay::Overlay;
+use crate::persist::PersistentState;
+use crate::postprocess::RunningJob;
+use crate::power;
+use crate::preview;
+use crate::process;
+use crate::recorder::{self, EncodeSettings, EncodeStats};
+use crate::retention;
+use crate::session;
+use crate::stats::Statistics;
+use crate::systemd;
+use crate::tamper;
+use crate::tray;
+use crate::webcam;
+
+pub const BUS_NAME: &str = "org.matthew_hre.NiriScreenRecorder";
+pub const OBJECT_PATH: &str = "/org/matthew_hre/NiriScreenRecorder";
 
 /// State shared between DBus methods
+#[derive(Default)]
 struct RecorderState {
     recording: bool,
     current_file: Option<String>,
-    child: Option<Child>,
+    child: Option<recorder::Recording>,
+    inhibitor: Option<Inhibitor>,
+    /// Do-not-disturb mode switched on for the recording, to switch off again
+    dnd: Option<dnd::Override>,
+    /// The performance power profile, held while recording
+    profile_hold: Option<power::ProfileHold>,
+    /// Cancels the region selection of a start request that is still pending
+    selecting: Option<oneshot::Sender<()>>,
+    /// Whether a stopped recording is still being verified and processed
+    finalizing: bool,
+    /// A start request that came in while finalizing, to begin once done
+    queued_start: Option<QueuedStart>,
+    /// Encoder figures of the active recording
+    encode_stats: Option<Arc<Mutex<EncodeStats>>>,
+    /// When the active recording started
+    started_at: Option<Instant>,
+    /// When the active recording was paused, if it is
+    paused_since: Option<Instant>,
+    /// Time the active recording spent paused before `paused_since`
+    paused_for: Duration,
+    /// Whether the daemon paused the recording by itself, e.g. for the
+    /// screen lock, and should resume it
+    auto_paused: bool,
+    /// Whether the screen is locked, as far as the lock is followed
+    locked: bool,
+    /// Notices when the user stops and starts using the machine
+    idle: Option<idle::Monitor>,
+    /// Chapters to embed once the recording stops: one per segment between
+    /// pauses, plus any markers
+    chapters: Vec<Chapter>,
+    /// Segments recorded so far, counting from 1 once the recording was paused
+    parts: usize,
+    /// Border shown around the captured region
+    overlay: Option<Overlay>,
+    /// Preview of the captured region served over local HTTP
+    preview: Option<preview::Preview>,
+    /// Notices when the file being recorded to is deleted or moved away
+    tamper: Option<tamper::Watch>,
+    /// Outputs the captured region is on, any of which going away ends the
+    /// recording
+    outputs: Vec<String>,
+    /// History entry of the active recording, completed once it is saved
+    entry: Option<history::Entry>,
+    /// Webcam captured alongside the screen
+    webcam: Option<webcam::Capture>,
+    /// Mouse clicks logged for highlighting
+    clicks: Option<clicks::Listener>,
+    /// gpu-screen-recorder keeping the last seconds of the screen, ready to
+    /// be saved
+    replay: Option<Child>,
+    /// Whether the replay buffer was started for a game, and should stop
+    /// when the last game exits
+    replay_for_game: bool,
+    /// Counters and history kept across daemon restarts
+    persistent: PersistentState,
+    /// The notification of the active recording session
+    bubble: notifications::Bubble,
+}
+
+impl RecorderState {
+    /// How much has been recorded so far, leaving out time spent paused
+    fn recorded(&self) -> Duration {
+        let Some(started_at) = self.started_at else {
+            return Duration::ZERO;
+        };
+        let paused = self.paused_for + self.paused_since.map_or(Duration::ZERO, |t| t.elapsed());
+        started_at.elapsed().saturating_sub(paused)
+    }
+}
+
+/// How a queued recording is to be started
+#[derive(Clone, Copy)]
+enum QueuedStart {
+    /// Select a region first
+    Select,
+    /// Record the given region
+    Region(Region),
 }
 
-impl Default for RecorderState {
-    fn default() -> Self {
+/// What a recording starts on, looked up before the state is locked since
+/// each takes a round trip to another process
+struct Surroundings {
+    /// Whether the machine runs on battery, for the encoder settings
+    on_battery: bool,
+    /// Outputs the region is on, starting with the one its top-left corner
+    /// is on
+    outputs: Vec<compositor::Output>,
+    /// Window in focus, for the history entry
+    window: Option<compositor::Window>,
+}
+
+impl Surroundings {
+    async fn of(region: &Region) -> Self {
+        let (on_battery, outputs, window) = tokio::join!(
+            power::on_battery(),
+            compositor::outputs_under(region),
+            compositor::focused_window()
+        );
         Self {
-            recording: false,
-            current_file: None,
-            child: None,
+            on_battery,
+            outputs,
+            window,
         }
     }
 }
 
+/// A recording that has just ended
+struct Finished {
+    file: String,
+    /// Time the recording ran for, leaving out pauses
+    elapsed: Duration,
+    /// What was recorded, for the history index
+    entry: history::Entry,
+    /// The session's notification, replaced by whatever is shown next
+    bubble: notifications::Bubble,
+    /// Webcam footage to composite into the recording
+    webcam: Option<std::path::PathBuf>,
+    /// Clicks to highlight in the recording
+    clicks: Vec<clicks::Click>,
+    /// Chapters to embed in the recording
+    chapters: Vec<Chapter>,
+}
+
 /// The DBus interface exposed to clients
+#[derive(Clone)]
 struct ScreenRecorder {
     state: Arc<RwLock<RecorderState>>,
     tokio_handle: tokio::runtime::Handle,
+    started_at: Instant,
+    /// Woken whenever a recording is done finalizing
+    finalized: Arc<Notify>,
 }
 
-#[interface(name = "org.matthew_hre.NiriScreenRecorder")]
 impl ScreenRecorder {
-    /// Start a new recording
-    async fn start_recording(&self, #[zbus(signal_context)] ctxt: SignalEmitter<'_>) -> bool {
+    /// Clear the recording state and release anything held for it.
+    /// Returns the recording that was active, which counts as finalizing
+    /// until `finalized` is called for it.
+    async fn reset(state: &mut RecorderState) -> Finished {
+        state.finalizing = true;
+        let file = state.current_file.take().unwrap_or_default();
+        let elapsed = state.recorded();
+        state.started_at = None;
+        state.paused_since = None;
+        state.paused_for = Duration::ZERO;
+        state.auto_paused = false;
+        state.recording = false;
+        state.child = None;
+        state.encode_stats = None;
+        state.overlay = None;
+        state.preview = None;
+        state.tamper = None;
+        state.idle = None;
+        state.outputs.clear();
+        let webcam = state.webcam.take().and_then(webcam::Capture::stop);
+        let clicks = state
+            .clicks
+            .take()
+            .map_or_else(Vec::new, clicks::Listener::stop);
+        if let Some(inhibitor) = state.inhibitor.take() {
+            inhibitor.release().await;
+        }
+        if let Some(hold) = state.profile_hold.take() {
+            hold.release().await;
+        }
+        // Before anything about the recording is shown
+        if let Some(dnd) = state.dnd.take() {
+            dnd.restore().await;
+        }
+        let bubble = std::mem::take(&mut state.bubble);
+        let mut entry = state.entry.take().unwrap_or_default();
+        entry.stopped();
+        let mut chapters = std::mem::take(&mut state.chapters);
+        chapters.sort_by_key(|c| c.start);
+        entry.chapters = chapters.iter().map(history::Chapter::from).collect();
+        state.parts = 0;
+        Finished {
+            file,
+            elapsed,
+            entry,
+            bubble,
+            webcam,
+            clicks,
+            chapters,
+        }
+    }
+
+    /// Announce a finished recording, once its file is final, then begin
+    /// any recording that was queued in the meantime
+    async fn finalize(&self, finished: Finished, ctxt: &SignalEmitter<'_>) {
+        self.announce(finished, ctxt).await;
+        self.finalized(ctxt).await;
+    }
+
+    /// Mark finalizing as done and start the queued recording, if any.
+    /// Region selection can take a while, so it doesn't hold up the caller.
+    async fn finalized(&self, ctxt: &SignalEmitter<'_>) {
+        let queued = {
+            let mut state = self.state.write().await;
+            state.finalizing = false;
+            state.queued_start.take()
+        };
+        self.finalized.notify_waiters();
+        let Some(queued) = queued else {
+            return;
+        };
+
+        tracing::info!("Previous recording finalized, starting the queued one");
+        self.tokio_handle
+            .spawn(self.clone().start_queued(queued, ctxt.to_owned()));
+    }
+
+    /// Start a queued recording. Boxed, since starting one leads back here
+    /// through `finalize` and the future would otherwise contain itself.
+    fn start_queued(
+        self,
+        queued: QueuedStart,
+        ctxt: SignalEmitter<'static>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            let result = match queued {
+                QueuedStart::Select => self.start_recording(ctxt).await,
+                QueuedStart::Region(region) => self.start_region(region, &ctxt).await,
+            };
+            if let Err(e) = result {
+                tracing::warn!("Failed to start the queued recording: {}", e);
+            }
+        })
+    }
+
+    /// Verify, process and move a finished recording, and tell the user
+    async fn announce(&self, finished: Finished, ctxt: &SignalEmitter<'_>) {
+        let file = finished.file.as_str();
+
+        // Whatever read the stream has the video, so there's nothing to keep
+        if recorder::is_stream(file) {
+            Self::recording_stopped(ctxt, "").await.ok();
+            finished.bubble.close().await;
+            return;
+        }
+        let webcam = finished.webcam.as_deref();
+
+        // Make sure the file is actually playable before announcing it
+        let info = match media::probe(file).await {
+            Ok(info) => info,
+            Err(e) => {
+                tracing::error!("Recording verification failed for {}: {}", file, e);
+                if let Some(webcam) = webcam {
+                    std::fs::remove_file(webcam).ok();
+                }
+                Self::recording_stopped(ctxt, file).await.ok();
+                finished.bubble.close().await;
+                notify_error(&e).await;
+                return;
+            }
+        };
+
+        // Too short to be intentional: throw it away without a fuss
+        let duration = info.as_ref().map_or(finished.elapsed, |i| i.duration);
+        if let Some(min) = config::get().min_duration
+            && duration < min
+        {
+            tracing::info!(
+                "Discarding {}: {:.1}s is shorter than the minimum of {}s",
+                file,
+                duration.as_secs_f64(),
+                min.as_secs()
+            );
+            if let Err(e) = std::fs::remove_file(file) {
+                tracing::warn!("Failed to delete {}: {}", file, e);
+            }
+            if let Some(webcam) = webcam {
+                std::fs::remove_file(webcam).ok();
+            }
+            // An empty path tells listeners the recording was discarded
+            Self::recording_stopped(ctxt, "").await.ok();
+            finished.bubble.close().await;
+            return;
+        }
+
+        if let Some(webcam) = webcam {
+            Self::add_webcam(file, webcam, duration, &finished.bubble).await;
+        }
+
+        if !finished.clicks.is_empty() {
+            Self::add_clicks(file, &finished.clicks, duration, &finished.bubble).await;
+        }
+
+        if !finished.chapters.is_empty()
+            && let Err(e) = chapters::embed(file, &finished.chapters, duration).await
+        {
+            tracing::warn!("Failed to add chapters to {}: {}", file, e);
+        }
+
+        // Only now that the file is final does it show up in the output directory
+        let moved;
+        let file = if recorder::is_staged() {
+            match recorder::move_to_output_dir(file) {
+                Ok(path) => {
+                    moved = path;
+                    moved.as_str()
+                }
+                Err(e) => {
+                    tracing::error!("Failed to move {} to the output directory: {}", file, e);
+                    Self::recording_stopped(ctxt, file).await.ok();
+                    finished.bubble.close().await;
+                    notify_error(&e).await;
+                    return;
+                }
+            }
+        } else {
+            file
+        };
+
+        if config::get().confirm {
+            // Waiting for an answer can take a while, so don't hold up the stop call
+            self.tokio_handle.spawn(self.clone().confirm(
+                file.to_string(),
+                duration,
+                finished.entry,
+                finished.bubble,
+                ctxt.to_owned(),
+            ));
+            return;
+        }
+
+        let thumbnail = media::thumbnail(file, Some(duration)).await;
+        self.publish(
+            file,
+            duration,
+            thumbnail.as_deref(),
+            finished.entry,
+            &finished.bubble,
+            ctxt,
+        )
+        .await;
+    }
+
+    /// Composite webcam footage into a corner of the recording. On failure
+    /// the recording is kept as it is.
+    async fn add_webcam(
+        file: &str,
+        webcam: &std::path::Path,
+        duration: Duration,
+        bubble: &notifications::Bubble,
+    ) {
+        tracing::info!("Adding webcam {} to {}", webcam.display(), file);
+        let job = webcam::composite(file, webcam, duration);
+        let result = Self::rewrite(file, job, &t!("webcam-compositing"), bubble).await;
+        std::fs::remove_file(webcam).ok();
+        if let Err(e) = result {
+            tracing::error!("Failed to add webcam to {}: {}", file, e);
+            notify_error(&e).await;
+        }
+    }
+
+    /// Draw the clicks made while recording into a corner of the recording.
+    /// On failure the recording is kept as it is.
+    async fn add_clicks(
+        file: &str,
+        clicks: &[clicks::Click],
+        duration: Duration,
+        bubble: &notifications::Bubble,
+    ) {
+        tracing::info!("Highlighting {} clicks in {}", clicks.len(), file);
+        let job = clicks::highlight(file, clicks, duration);
+        if let Err(e) = Self::rewrite(file, job, &t!("clicks-highlighting"), bubble).await {
+            tracing::error!("Failed to highlight clicks in {}: {}", file, e);
+            notify_error(&e).await;
+        }
+    }
+
+    /// Run `job`, which writes a new version of the recording `file`, and
+    /// move its output over the original, showing the progress as `title`
+    /// in the session's notification
+    async fn rewrite(
+        file: &str,
+        job: Result<RunningJob, Error>,
+        title: &str,
+        bubble: &notifications::Bubble,
+    ) -> Result<(), Error> {
+        let mut progress = if dnd::quiet().await {
+            None
+        } else {
+            notifications::ProgressNotification::show(title, bubble)
+                .await
+                .inspect_err(|e| tracing::debug!("Failed to show progress notification: {}", e))
+                .ok()
+        };
+
+        let mut running = job?;
+        while let Some(fraction) = running.progress().await {
+            if let Some(progress) = progress.as_mut() {
+                progress.update(fraction).await.ok();
+            }
+        }
+        let output = running.finish().await?;
+        std::fs::rename(output, file).map_err(Error::MoveFailed)
+    }
+
+    /// Ask whether to keep a recording made in confirm mode, then move it to
+    /// the output directory or delete it
+    async fn confirm(
+        self,
+        file: String,
+        duration: Duration,
+        entry: history::Entry,
+        bubble: notifications::Bubble,
+        ctxt: SignalEmitter<'static>,
+    ) {
+        let thumbnail = media::thumbnail(&file, Some(duration)).await;
+        let pending = notifications::SavedRecording {
+            file: &file,
+            duration,
+            size: std::fs::metadata(&file).ok().map(|m| m.len()),
+            thumbnail: thumbnail.as_deref(),
+            region: entry.region.as_deref(),
+        };
+
+        let keep = notifications::confirm_keep(&pending, &bubble)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to ask whether to keep {}, keeping it: {}", file, e);
+                true
+            });
+
+        if !keep {
+            tracing::info!("Discarding {} as requested", file);
+            if let Err(e) = std::fs::remove_file(&file) {
+                tracing::warn!("Failed to delete {}: {}", file, e);
+            }
+            Self::recording_stopped(&ctxt, "").await.ok();
+            bubble.close().await;
+            return;
+        }
+
+        match recorder::move_to_output_dir(&file) {
+            Ok(kept) => {
+                self.publish(&kept, duration, thumbnail.as_deref(), entry, &bubble, &ctxt)
+                    .await
+            }
+            Err(e) => {
+                tracing::error!("Failed to keep {}: {}", file, e);
+                Self::recording_stopped(&ctxt, &file).await.ok();
+                bubble.close().await;
+                notify_error(&e).await;
+            }
+        }
+    }
+
+    /// Emit the stop signal, count the recording, and show its notification
+    async fn publish(
+        &self,
+        file: &str,
+        duration: Duration,
+        thumbnail: Option<&std::path::Path>,
+        mut entry: history::Entry,
+        bubble: &notifications::Bubble,
+        ctxt: &SignalEmitter<'_>,
+    ) {
+        // Emit signal with the file path
+        Self::recording_stopped(ctxt, file).await.ok();
+
+        let size = std::fs::metadata(file).ok().map(|m| m.len());
+        if let Some(size) = size {
+            let mut state = self.state.write().await;
+            state.persistent.recordings += 1;
+            state.persistent.bytes_written += size;
+            state.persistent.last_file = Some(file.to_string());
+            state.persistent.save();
+        }
+
+        entry.file = file.to_string();
+        entry.duration = duration.as_secs_f64();
+        entry.size = size;
+        history::record(&entry);
+        if config::get().sidecar {
+            history::write_sidecar(&entry);
+        }
+
+        let region = entry.region.as_deref();
+        let saved = notifications::SavedRecording {
+            file,
+            duration,
+            size,
+            thumbnail,
+            region,
+        };
+        notifications::notify_recording_stopped(&saved, bubble)
+            .await
+            .ok();
+
+        if !config::get().post_process.is_empty() {
+            self.tokio_handle.spawn(self.clone().post_process(
+                file.to_string(),
+                duration,
+                region.map(str::to_string),
+                bubble.clone(),
+            ));
+        }
+
+        self.tokio_handle
+            .spawn(retention::enforce(file.to_string()));
+    }
+
+    /// Run the configured post-processing jobs on a saved recording, one
+    /// after the other, showing their progress in the session's notification
+    async fn post_process(
+        self,
+        file: String,
+        duration: Duration,
+        region: Option<String>,
+        bubble: notifications::Bubble,
+    ) {
+        for &job in &config::get().post_process {
+            tracing::info!("Running {} on {}", job.name(), file);
+
+            let mut progress = if dnd::quiet().await {
+                None
+            } else {
+                notifications::ProgressNotification::show(&job.description(), &bubble)
+                    .await
+                    .inspect_err(|e| tracing::debug!("Failed to show progress notification: {}", e))
+                    .ok()
+            };
+
+            let result = match job.start(&file, duration) {
+                Ok(mut running) => {
+                    while let Some(fraction) = running.progress().await {
+                        if let Some(progress) = progress.as_mut() {
+                            progress.update(fraction).await.ok();
+                        }
+                    }
+                    running.finish().await
+                }
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(output) => {
+                    let output = output.to_string_lossy();
+                    tracing::info!("Finished {}: {}", job.name(), output);
+                    let saved = notifications::SavedRecording {
+                        file: &output,
+                        duration,
+                        size: std::fs::metadata(output.as_ref()).ok().map(|m| m.len()),
+                        thumbnail: None,
+                        region: region.as_deref(),
+                    };
+                    notifications::notify_saved(&job.done(), &saved, &bubble)
+                        .await
+                        .ok();
+                }
+                Err(e) => {
+                    tracing::error!("Job {} failed for {}: {}", job.name(), file, e);
+                    bubble.close().await;
+                    notify_error(&e).await;
+                }
+            }
+        }
+    }
+
+    /// Start recording `region`, or queue it while the last recording is
+    /// being finalized. Returns false if one is already active or pending.
+    async fn start_region(&self, region: Region, ctxt: &SignalEmitter<'_>) -> Result<bool, Error> {
+        let surroundings = Surroundings::of(&region).await;
         let mut state = self.state.write().await;
 
-        if state.recording {
+        if state.recording || state.selecting.is_some() || state.queued_start.is_some() {
             tracing::warn!("Already recording, ignoring start request");
-            return false;
+            return Ok(false);
+        }
+
+        if state.finalizing {
+            tracing::info!("Still finalizing the last recording, queueing start request");
+            state.queued_start = Some(QueuedStart::Region(region));
+            return Ok(true);
+        }
+
+        self.begin_recording(state, region, surroundings, None, ctxt)
+            .await
+    }
+
+    /// Let the user select a region with slurp. The lock is released
+    /// meanwhile, so other calls stay responsive, and the state comes back
+    /// locked again with the region and its surroundings. Returns `None` if
+    /// a selection is already in progress.
+    async fn select<'a>(
+        &'a self,
+        mut state: RwLockWriteGuard<'a, RecorderState>,
+    ) -> Result<Option<(RwLockWriteGuard<'a, RecorderState>, Region, Surroundings)>, Error> {
+        if state.selecting.is_some() {
+            tracing::warn!("Region selection already in progress, ignoring start request");
+            return Ok(None);
+        }
+
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        state.selecting = Some(cancel_tx);
+        drop(state);
+
+        let selection = match recorder::select_region(&process::System, cancel_rx).await {
+            Ok(region) => Ok((Surroundings::of(&region).await, region)),
+            Err(e) => Err(e),
+        };
+
+        let mut state = self.state.write().await;
+
+        // Our receiver is gone now, so a closed sender means the selection
+        // slot is still ours. Anything else means we were cancelled meanwhile.
+        if !state.selecting.as_ref().is_some_and(|tx| tx.is_closed()) {
+            tracing::info!("Region selection was cancelled");
+            return Err(Error::SelectionCancelled);
         }
+        state.selecting = None;
 
-        // Select region
-        let region = match recorder::select_region() {
-            Ok(r) => r,
+        match selection {
+            Ok((surroundings, region)) => Ok(Some((state, region, surroundings))),
             Err(e) => {
                 tracing::error!("Failed to select region: {}", e);
-                notifications::notify_error(&e).await.ok();
-                return false;
+                drop(state);
+                notify_error(&e).await;
+                Err(e)
             }
-        };
+        }
+    }
+
+    /// Start gpu-screen-recorder on a selected region and set up everything
+    /// that accompanies a recording. Given a pipe, the video is written to it
+    /// rather than a file, and nothing is saved afterwards.
+    async fn begin_recording(
+        &self,
+        mut state: RwLockWriteGuard<'_, RecorderState>,
+        region: Region,
+        surroundings: Surroundings,
+        pipe: Option<OwnedFd>,
+        ctxt: &SignalEmitter<'_>,
+    ) -> Result<bool, Error> {
+        let stream = pipe.is_some();
+        let Surroundings {
+            on_battery,
+            outputs,
+            window,
+        } = surroundings;
+        let settings = EncodeSettings::new(on_battery);
 
         // Start recording
-        match recorder::start_recording(&region) {
-            Ok((child, file)) => {
+        match recorder::start_recording(&process::System, &region, &settings, pipe) {
+            Ok((mut child, file)) => {
+                state.encode_stats = Some(recorder::watch_encoder_output(&mut child, settings.fps));
+                let (child, exited) = recorder::Recording::supervise(child);
                 state.recording = true;
                 state.current_file = Some(file.clone());
                 state.child = Some(child);
+                state.started_at = Some(Instant::now());
+                state.outputs = outputs.iter().map(|o| o.name.clone()).collect();
+                let output = outputs.into_iter().next();
+                let mut entry = history::Entry::started(
+                    &file,
+                    &region,
+                    output.as_ref().map(|o| o.name.as_str()),
+                    settings.fps,
+                );
+                entry.codec = config::get().codec.clone();
+                entry.quality = settings.quality.clone();
+                if let Some(window) = window {
+                    entry.window_title = window.title;
+                    entry.app_id = window.app_id;
+                }
+                if on_battery {
+                    entry.profile = "battery".to_string();
+                }
+                state.entry = Some(entry);
+                state.persistent.started += 1;
+                state.persistent.last_region = Some(region.to_string());
+                state.persistent.save();
+
+                if config::get().indicator {
+                    state.overlay = Overlay::show(&region);
+                }
+
+                if let Some(port) = config::get().preview_port {
+                    state.preview = preview::Preview::start(&region, port).await;
+                }
+
+                let idle_pause = if on_battery {
+                    config::get()
+                        .battery_idle_pause
+                        .or(config::get().idle_pause)
+                } else {
+                    config::get().idle_pause
+                };
+                if let Some(timeout) = idle_pause
+                    && let Some(monitor) = idle::Monitor::start(timeout)
+                {
+                    self.tokio_handle.spawn(
+                        self.clone()
+                            .follow_idle(monitor.subscribe(), ctxt.to_owned()),
+                    );
+                    state.idle = Some(monitor);
+                }
+
+                // Both are added to the file afterwards, which a stream doesn't have
+                if let Some(device) = &config::get().webcam
+                    && !stream
+                {
+                    state.webcam = webcam::Capture::start(device, &file);
+                }
+
+                if config::get().clicks && !stream {
+                    state.clicks = clicks::Listener::start();
+                }
 
                 tracing::info!("Recording started: {}", file);
 
+                self.tokio_handle.spawn(self.clone().watch_recorder(
+                    file.clone(),
+                    exited,
+                    ctxt.to_owned(),
+                ));
+
+                if !stream && let Some((watch, removed)) = tamper::Watch::start(Path::new(&file)) {
+                    self.tokio_handle.spawn(self.clone().follow_removal(
+                        file.clone(),
+                        removed,
+                        ctxt.to_owned(),
+                    ));
+                    state.tamper = Some(watch);
+                }
+
+                self.tokio_handle
+                    .spawn(self.clone().show_recording_notification(
+                        file.clone(),
+                        region,
+                        output,
+                        settings.fps,
+                        state.bubble.clone(),
+                        ctxt.to_owned(),
+                    ));
+
+                self.tokio_handle
+                    .spawn(self.clone().watch_battery(file.clone()));
+                drop(state);
+
+                self.hold_for(&file).await;
+
+                if on_battery && config::get().battery_warn {
+                    notifications::notify_warning(
+                        &t!("recording-on-battery"),
+                        &t!("recording-on-battery-body", fps = settings.fps),
+                    )
+                    .await
+                    .ok();
+                }
+
                 // Emit signal
-                Self::recording_started(&ctxt).await.ok();
-                true
+                Self::recording_started(ctxt).await.ok();
+                Ok(true)
             }
             Err(e) => {
                 tracing::error!("Failed to start recording: {}", e);
-                notifications::notify_error(&e).await.ok();
-                false
+                state.persistent.failed += 1;
+                state.persistent.save();
+                drop(state);
+                notify_error(&e).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Keep the screen on and the machine awake while recording `file`, and
+    /// take the performance profile and do-not-disturb if configured. Each
+    /// is a round trip to another service, so the state isn't locked
+    /// meanwhile, and whatever was taken is handed back if the recording
+    /// stopped in the meantime.
+    async fn hold_for(&self, file: &str) {
+        let inhibitor = Inhibitor::acquire().await;
+        let profile_hold = if config::get().performance {
+            power::ProfileHold::acquire().await
+        } else {
+            None
+        };
+        let dnd = if config::get().dnd {
+            dnd::Override::enable().await
+        } else {
+            None
+        };
+
+        let mut state = self.state.write().await;
+        if state.current_file.as_deref() == Some(file) {
+            state.inhibitor = Some(inhibitor);
+            state.profile_hold = profile_hold;
+            state.dnd = dnd;
+            return;
+        }
+        drop(state);
+
+        tracing::debug!("Recording {} already stopped, releasing its holds", file);
+        inhibitor.release().await;
+        if let Some(hold) = profile_hold {
+            hold.release().await;
+        }
+        if let Some(dnd) = dnd {
+            dnd.restore().await;
+        }
+    }
+
+    /// Ping the systemd watchdog for as long as the daemon is healthy. If the
+    /// shared state stays locked for a whole interval, something is wedged and
+    /// the ping is skipped so systemd can restart us.
+    async fn run_watchdog(self, interval: tokio::time::Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match tokio::time::timeout(interval, self.state.read()).await {
+                Ok(_) => systemd::notify("WATCHDOG=1"),
+                Err(_) => tracing::error!("Daemon state is stuck, skipping watchdog ping"),
+            }
+        }
+    }
+
+    /// Warn once if the battery runs low during a recording
+    async fn watch_battery(self, file: String) {
+        let Some(threshold) = config::get().battery_threshold else {
+            return;
+        };
+        let Ok(battery) = power::Battery::connect().await else {
+            return;
+        };
+
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+
+            if self.state.read().await.current_file.as_deref() != Some(file.as_str()) {
+                return;
+            }
+
+            if let Some(percentage) = battery.percentage().await
+                && percentage < threshold
+                && battery.on_battery().await
+            {
+                tracing::warn!("Battery at {:.0}% while recording", percentage);
+                notifications::notify_warning(
+                    &t!("battery-low"),
+                    &t!(
+                        "battery-low-body",
+                        percentage = format!("{:.0}", percentage)
+                    ),
+                )
+                .await
+                .ok();
+                return;
+            }
+        }
+    }
+
+    /// Keep a notification with the elapsed time and a Stop button up for as
+    /// long as the recording runs. Once it ends, the notification is left for
+    /// `finalize` to replace or close.
+    async fn show_recording_notification(
+        self,
+        file: String,
+        region: Region,
+        output: Option<compositor::Output>,
+        fps: u32,
+        bubble: notifications::Bubble,
+        ctxt: SignalEmitter<'static>,
+    ) {
+        if dnd::quiet().await {
+            tracing::info!("Do not disturb, not showing the recording notification");
+            return;
+        }
+
+        let details = capture_details(&region, output.as_ref(), fps);
+        let (mut notification, mut stop) =
+            match notifications::RecordingNotification::show(details, &bubble).await {
+                Ok(shown) => shown,
+                Err(e) => {
+                    tracing::warn!("Failed to show recording notification: {}", e);
+                    return;
+                }
+            };
+
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
+        interval.tick().await;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let state = self.state.read().await;
+                    if state.current_file.as_deref() != Some(file.as_str()) {
+                        break;
+                    }
+                    let (elapsed, paused) = (state.recorded(), state.paused_since.is_some());
+                    drop(state);
+                    notification.update(elapsed, paused).await.ok();
+                }
+                _ = stop.pressed() => {
+                    tracing::info!("Stop pressed in the recording notification");
+                    let current = self.state.read().await.current_file.clone();
+                    if current.as_deref() == Some(file.as_str()) {
+                        self.stop_recording(ctxt).await;
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Stop the recording when the output it captures is unplugged, so the
+    /// file is finalized cleanly instead of the recorder failing on its own
+    async fn follow_outputs(
+        self,
+        mut events: tokio::sync::watch::Receiver<niri::Snapshot>,
+        ctxt: SignalEmitter<'static>,
+    ) {
+        while events.changed().await.is_ok() {
+            let snapshot = events.borrow_and_update().clone();
+            let removed = {
+                let state = self.state.read().await;
+                let present = snapshot.outputs();
+                if state.recording && snapshot.connected {
+                    state
+                        .outputs
+                        .iter()
+                        .find(|o| !present.contains(o.as_str()))
+                        .cloned()
+                } else {
+                    None
+                }
+            };
+
+            if let Some(output) = removed {
+                tracing::warn!(
+                    "Captured output {} was removed, stopping the recording",
+                    output
+                );
+                self.stop_recording(ctxt.clone()).await;
+            }
+        }
+    }
+
+    /// Pause the recording while the screen is locked, and resume it on
+    /// unlock unless it was paused by hand
+    async fn follow_lock(self, mut locked: watch::Receiver<bool>, ctxt: SignalEmitter<'static>) {
+        while locked.changed().await.is_ok() {
+            let is_locked = *locked.borrow_and_update();
+            self.state.write().await.locked = is_locked;
+            if is_locked {
+                match self.pause_recording(ctxt.clone()).await {
+                    Ok(true) => {
+                        tracing::info!("Screen locked, pausing the recording");
+                        self.state.write().await.auto_paused = true;
+                    }
+                    Ok(false) => {}
+                    Err(e) => tracing::warn!("Failed to pause for the screen lock: {}", e),
+                }
+            } else if self.state.read().await.auto_paused {
+                tracing::info!("Screen unlocked, resuming the recording");
+                if let Err(e) = self.resume_recording(ctxt.clone()).await {
+                    tracing::warn!("Failed to resume after the screen lock: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Pause or save the recording before the system suspends, since the
+    /// encoder doesn't survive it, and resume or record again on waking up
+    async fn follow_sleep(
+        self,
+        mut events: mpsc::Receiver<session::Sleep>,
+        ctxt: SignalEmitter<'static>,
+    ) {
+        // The region to record again after waking up
+        let mut restart: Option<Region> = None;
+        while let Some(event) = events.recv().await {
+            match event {
+                session::Sleep::Preparing(delay) => {
+                    let (recording, region) = {
+                        let state = self.state.read().await;
+                        let region = state.entry.as_ref().and_then(|e| e.region.as_deref());
+                        (state.recording, region.and_then(|r| r.parse().ok()))
+                    };
+                    if recording {
+                        match config::get().on_suspend {
+                            session::OnSuspend::Pause => {
+                                match self.pause_recording(ctxt.clone()).await {
+                                    Ok(true) => {
+                                        tracing::info!("Suspending, pausing the recording");
+                                        self.state.write().await.auto_paused = true;
+                                    }
+                                    Ok(false) => {}
+                                    Err(e) => tracing::warn!("Failed to pause for suspend: {}", e),
+                                }
+                            }
+                            action => {
+                                tracing::info!("Suspending, stopping the recording");
+                                self.stop_recording(ctxt.clone()).await;
+                                if action == session::OnSuspend::Restart {
+                                    restart = region;
+                                }
+                            }
+                        }
+                    }
+                    delay.release();
+                }
+                session::Sleep::Woke => {
+                    if let Some(region) = restart.take() {
+                        // Outputs take a moment to come back after resume
+                        tokio::time::sleep(Duration::from_secs(2)).await;
+                        // Like any other start, so one that is already
+                        // running, selecting or queued wins
+                        match self.start_region(region, &ctxt).await {
+                            Ok(true) => tracing::info!("Woke up, recording {} again", region),
+                            Ok(false) => {}
+                            Err(e) => tracing::warn!("Failed to record again after suspend: {}", e),
+                        }
+                        continue;
+                    }
+
+                    // While locked, unlocking resumes it instead
+                    let resume = {
+                        let state = self.state.read().await;
+                        state.auto_paused && !state.locked
+                    };
+                    if resume {
+                        tracing::info!("Woke up, resuming the recording");
+                        if let Err(e) = self.resume_recording(ctxt.clone()).await {
+                            tracing::warn!("Failed to resume after suspend: {}", e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pause the recording after a while without input, and resume it on
+    /// activity unless it was paused by hand. Typing at the lock screen
+    /// doesn't count.
+    async fn follow_idle(self, mut idle: watch::Receiver<bool>, ctxt: SignalEmitter<'static>) {
+        while idle.changed().await.is_ok() {
+            let is_idle = *idle.borrow_and_update();
+            if is_idle {
+                match self.pause_recording(ctxt.clone()).await {
+                    Ok(true) => {
+                        tracing::info!("No input for a while, pausing the recording");
+                        self.state.write().await.auto_paused = true;
+                    }
+                    Ok(false) => {}
+                    Err(e) => tracing::warn!("Failed to pause while idle: {}", e),
+                }
+            } else {
+                let resume = {
+                    let state = self.state.read().await;
+                    state.auto_paused && !state.locked
+                };
+                if resume {
+                    tracing::info!("Input again, resuming the recording");
+                    if let Err(e) = self.resume_recording(ctxt.clone()).await {
+                        tracing::warn!("Failed to resume after being idle: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stop the recording for good, dropping any queued start, and wait
+    /// until it is saved, including one that some other task was already
+    /// finalizing
+    async fn settle(&self, ctxt: &SignalEmitter<'_>) {
+        let recording = {
+            let mut state = self.state.write().await;
+            // Nothing queued should start on the way down
+            state.queued_start = None;
+            state.recording
+        };
+        if recording {
+            self.stop_recording(ctxt.clone()).await;
+        }
+
+        loop {
+            let finalized = self.finalized.notified();
+            let mut finalized = std::pin::pin!(finalized);
+            finalized.as_mut().enable();
+            if !self.state.read().await.finalizing {
+                return;
+            }
+            finalized.await;
+        }
+    }
+
+    /// Save the recording before the system shuts down, holding up the
+    /// shutdown until it is, including one that was already being finalized
+    async fn follow_shutdown(
+        self,
+        mut events: mpsc::Receiver<session::Shutdown>,
+        ctxt: SignalEmitter<'static>,
+    ) {
+        while let Some(event) = events.recv().await {
+            match event {
+                session::Shutdown::Preparing(delay) => {
+                    tracing::info!("System is shutting down, saving any recording");
+                    self.settle(&ctxt).await;
+                    delay.release();
+                }
+                session::Shutdown::Cancelled => tracing::info!("Shutdown was cancelled"),
+            }
+        }
+    }
+
+    /// Start the replay buffer unless it is already running. One started
+    /// by hand is remembered, so the next daemon starts it again; one
+    /// started for a game is not.
+    async fn run_replay(&self, for_game: bool, ctxt: &SignalEmitter<'_>) -> Result<bool, Error> {
+        let mut state = self.state.write().await;
+        if state.replay.is_some() {
+            return Ok(false);
+        }
+
+        let seconds = config::get().replay_seconds;
+        state.replay = Some(recorder::start_replay(&process::System, seconds)?);
+        state.replay_for_game = for_game;
+        if !for_game && !state.persistent.replay {
+            state.persistent.replay = true;
+            state.persistent.save();
+        }
+        tracing::info!("Replay buffer started, keeping the last {}s", seconds);
+        drop(state);
+        Self::replay_started(ctxt).await.ok();
+        Ok(true)
+    }
+
+    /// Stop the replay buffer without saving it or forgetting that it was
+    /// running. Returns false if it isn't running.
+    async fn end_replay(&self, ctxt: &SignalEmitter<'_>) -> bool {
+        let mut state = self.state.write().await;
+        let Some(mut child) = state.replay.take() else {
+            return false;
+        };
+        state.replay_for_game = false;
+        drop(state);
+
+        if let Err(e) = recorder::stop_recording(&mut child).await {
+            tracing::warn!("Failed to stop the replay buffer: {}", e);
+        }
+        tracing::info!("Replay buffer stopped");
+        Self::replay_stopped(ctxt).await.ok();
+        true
+    }
+
+    /// Run the replay buffer while a game has GameMode active, unless it was
+    /// already started by hand
+    async fn follow_games(self, mut playing: watch::Receiver<bool>, ctxt: SignalEmitter<'static>) {
+        while playing.changed().await.is_ok() {
+            let is_playing = *playing.borrow_and_update();
+            if is_playing {
+                match self.run_replay(true, &ctxt).await {
+                    Ok(true) => tracing::info!("Game started, started the replay buffer"),
+                    Ok(false) => {}
+                    Err(e) => tracing::warn!("Failed to start the replay buffer: {}", e),
+                }
+            } else if self.state.read().await.replay_for_game {
+                tracing::info!("Game exited, stopping the replay buffer");
+                self.end_replay(&ctxt).await;
+            }
+        }
+    }
+
+    /// Stop the recording if its file is deleted or moved away, since
+    /// nothing recorded from then on could be saved
+    async fn follow_removal(
+        self,
+        file: String,
+        removed: oneshot::Receiver<()>,
+        ctxt: SignalEmitter<'static>,
+    ) {
+        // The watch is dropped once the recording ends
+        if removed.await.is_err() {
+            return;
+        }
+
+        let mut state = self.state.write().await;
+        if !state.recording || state.current_file.as_deref() != Some(file.as_str()) {
+            return;
+        }
+
+        tracing::error!("{} was deleted or moved while recording, stopping", file);
+        state.persistent.failed += 1;
+        state.persistent.save();
+        let child = state.child.take();
+        let finished = Self::reset(&mut state).await;
+        drop(state);
+
+        if let Some(child) = child
+            && let Err(e) = child.stop().await
+        {
+            tracing::warn!("Failed to stop recording: {}", e);
+        }
+
+        if let Some(webcam) = &finished.webcam {
+            std::fs::remove_file(webcam).ok();
+        }
+        // An empty path tells listeners there is nothing to show for it
+        Self::recording_stopped(&ctxt, "").await.ok();
+        finished.bubble.close().await;
+        notify_error(&Error::FileRemoved(file)).await;
+        self.finalized(&ctxt).await;
+    }
+
+    /// Clean up after the gpu-screen-recorder process of an active
+    /// recording if it exits on its own, e.g. because the captured output
+    /// went away. `exited` is dropped instead if it is stopped.
+    async fn watch_recorder(
+        self,
+        file: String,
+        exited: oneshot::Receiver<ExitStatus>,
+        ctxt: SignalEmitter<'static>,
+    ) {
+        let Ok(status) = exited.await else {
+            return;
+        };
+        let mut state = self.state.write().await;
+
+        // Stopped (and possibly restarted) through the normal path meanwhile
+        if !state.recording || state.current_file.as_deref() != Some(file.as_str()) {
+            return;
+        }
+
+        tracing::warn!("gpu-screen-recorder exited unexpectedly ({})", status);
+        state.persistent.failed += 1;
+        state.persistent.save();
+        let finished = Self::reset(&mut state).await;
+        drop(state);
+
+        notifications::notify_error(&t!("recorder-exited", status = status))
+            .await
+            .ok();
+
+        self.finalize(finished, &ctxt).await;
+    }
+}
+
+#[interface(name = "org.matthew_hre.NiriScreenRecorder")]
+impl ScreenRecorder {
+    /// Start a new recording. While the previous one is still being
+    /// finalized, the start is queued until it is done. Returns false if
+    /// one is already active or pending; failures are returned as DBus
+    /// errors.
+    async fn start_recording(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> Result<bool, Error> {
+        let mut state = self.state.write().await;
+
+        if state.recording || state.queued_start.is_some() {
+            tracing::warn!("Already recording, ignoring start request");
+            return Ok(false);
+        }
+
+        if state.finalizing {
+            tracing::info!("Still finalizing the last recording, queueing start request");
+            state.queued_start = Some(QueuedStart::Select);
+            return Ok(true);
+        }
+
+        match self.select(state).await? {
+            Some((state, region, surroundings)) => {
+                self.begin_recording(state, region, surroundings, None, &ctxt)
+                    .await
             }
+            None => Ok(false),
+        }
+    }
+
+    /// Start recording a region without selecting it, e.g. to record the same
+    /// region again. Queued like StartRecording while finalizing. Returns
+    /// false if a recording is already active or pending.
+    async fn start_recording_region(
+        &self,
+        region: String,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> Result<bool, Error> {
+        let region = checked_region(&region).await?;
+        self.start_region(region, &ctxt).await
+    }
+
+    /// Start recording the window with the compositor's id `id`, e.g. from
+    /// `niri msg windows`, wherever it is right now. Returns false if a
+    /// recording is already active or pending.
+    async fn start_recording_window(
+        &self,
+        id: u64,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> Result<bool, Error> {
+        let region = compositor::window_region(id).await?;
+        tracing::info!("Window {} is at {}", id, region);
+        self.start_region(region, &ctxt).await
+    }
+
+    /// Start recording the outputs named `names`, or all of them if empty,
+    /// as one video of the region spanning them. Gaps between the outputs
+    /// come out black. Returns false if a recording is already active or
+    /// pending.
+    async fn start_recording_outputs(
+        &self,
+        names: Vec<String>,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> Result<bool, Error> {
+        let region = compositor::outputs_region(&names).await?;
+        tracing::info!("Outputs span {}", region);
+        self.start_region(region, &ctxt).await
+    }
+
+    /// Start a recording that is written to a pipe instead of a file, e.g.
+    /// to feed it straight into ffmpeg or an uploader. The region is
+    /// selected first unless one is given. Returns the read end of the pipe,
+    /// which ends once the recording is stopped; nothing is saved.
+    async fn start_recording_to_pipe(
+        &self,
+        region: String,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> Result<zbus::zvariant::OwnedFd, Error> {
+        let given = if region.is_empty() {
+            None
+        } else {
+            let region = checked_region(&region).await?;
+            Some((Surroundings::of(&region).await, region))
+        };
+        let state = self.state.write().await;
+
+        if state.recording || state.selecting.is_some() || state.queued_start.is_some() {
+            tracing::warn!("Already recording, refusing to start a stream");
+            return Err(Error::AlreadyRecording);
         }
+
+        let (state, region, surroundings) = match given {
+            Some((surroundings, region)) => (state, region, surroundings),
+            None => self.select(state).await?.ok_or(Error::AlreadyRecording)?,
+        };
+
+        let (read, write) = std::io::pipe().map_err(Error::RecorderSpawnFailed)?;
+        self.begin_recording(state, region, surroundings, Some(write.into()), &ctxt)
+            .await?;
+        Ok(OwnedFd::from(read).into())
     }
 
-    /// Stop the current recording
+    /// Stop the current recording, or drop a queued start
     async fn stop_recording(&self, #[zbus(signal_context)] ctxt: SignalEmitter<'_>) -> bool {
         let mut state = self.state.write().await;
 
+        if state.selecting.is_some() || state.queued_start.is_some() {
+            drop(state);
+            return self.cancel_pending_start().await;
+        }
+
         if !state.recording {
             tracing::warn!("Not recording, ignoring stop request");
             return false;
         }
 
-        let file = state.current_file.clone().unwrap_or_default();
+        // From here on the recording counts as finalizing, so nothing has
+        // to wait for gpu-screen-recorder to finish the file, and a start
+        // is queued until it has
+        let child = state.child.take();
+        let finished = Self::reset(&mut state).await;
+        drop(state);
 
-        // Stop the recording process
-        if let Some(ref mut child) = state.child {
-            if let Err(e) = recorder::stop_recording(child) {
-                tracing::error!("Failed to stop recording: {}", e);
-            }
+        if let Some(child) = child
+            && let Err(e) = child.stop().await
+        {
+            tracing::error!("Failed to stop recording: {}", e);
         }
 
-        state.recording = false;
-        state.current_file = None;
-        state.child = None;
+        tracing::info!("Recording stopped: {}", finished.file);
 
-        tracing::info!("Recording stopped: {}", file);
+        self.finalize(finished, &ctxt).await;
 
-        // Emit signal with the file path
-        Self::recording_stopped(&ctxt, &file).await.ok();
+        true
+    }
 
-        // Send notification
-        notifications::notify_recording_stopped(&file, &self.tokio_handle)
-            .await
-            .ok();
+    /// Pause the current recording. Returns false if nothing is being
+    /// recorded or it is already paused.
+    async fn pause_recording(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> Result<bool, Error> {
+        let mut state = self.state.write().await;
+        let Some(child) = state
+            .child
+            .as_ref()
+            .filter(|_| state.paused_since.is_none())
+        else {
+            tracing::warn!("Not recording or already paused, ignoring pause request");
+            return Ok(false);
+        };
+
+        child.toggle_pause()?;
+        state.paused_since = Some(Instant::now());
+        if let Some(clicks) = &state.clicks {
+            clicks.pause();
+        }
+        drop(state);
+
+        tracing::info!("Recording paused");
+        Self::recording_paused(&ctxt).await.ok();
+        Ok(true)
+    }
+
+    /// Resume a paused recording, starting a new chapter. Returns false if
+    /// no recording is paused.
+    async fn resume_recording(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> Result<bool, Error> {
+        let mut state = self.state.write().await;
+        let (Some(child), Some(paused_since)) = (state.child.as_ref(), state.paused_since) else {
+            tracing::warn!("Not paused, ignoring resume request");
+            return Ok(false);
+        };
+
+        child.toggle_pause()?;
+        state.paused_for += paused_since.elapsed();
+        state.paused_since = None;
+        state.auto_paused = false;
+        if let Some(clicks) = &state.clicks {
+            clicks.resume();
+        }
 
+        // The first pause splits the recording, so the part before it gets a
+        // chapter too
+        if state.parts == 0 {
+            state.parts = 1;
+            state.chapters.push(Chapter {
+                start: Duration::ZERO,
+                title: t!("chapter-part", number = 1),
+            });
+        }
+        state.parts += 1;
+        let chapter = Chapter {
+            start: state.recorded(),
+            title: t!("chapter-part", number = state.parts),
+        };
+        state.chapters.push(chapter);
+        drop(state);
+
+        tracing::info!("Recording resumed");
+        Self::recording_resumed(&ctxt).await.ok();
+        Ok(true)
+    }
+
+    /// Bookmark the current position of the recording, e.g. "bug reproduced
+    /// here". Markers are embedded as chapters once the recording stops.
+    /// Returns false if nothing is being recorded.
+    async fn add_marker(&self, label: String) -> bool {
+        let mut state = self.state.write().await;
+        if !state.recording {
+            tracing::warn!("Not recording, ignoring marker");
+            return false;
+        }
+
+        let start = state.recorded();
+        let title = if label.trim().is_empty() {
+            t!("chapter-marker", elapsed = format::duration(start))
+        } else {
+            label.trim().to_string()
+        };
+        tracing::info!("Marker at {:.1}s: {}", start.as_secs_f64(), title);
+        state.chapters.push(Chapter { start, title });
         true
     }
 
-    /// Toggle recording on/off
-    async fn toggle_recording(&self, #[zbus(signal_context)] ctxt: SignalEmitter<'_>) -> bool {
+    /// Start keeping the last seconds of the screen in memory, to be saved
+    /// with SaveReplay. Returns false if the replay buffer is already running.
+    async fn start_replay(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> Result<bool, Error> {
+        self.run_replay(false, &ctxt).await
+    }
+
+    /// Stop the replay buffer without saving it. Returns false if it isn't
+    /// running.
+    async fn stop_replay(&self, #[zbus(signal_context)] ctxt: SignalEmitter<'_>) -> bool {
+        let mut state = self.state.write().await;
+        if state.persistent.replay {
+            state.persistent.replay = false;
+            state.persistent.save();
+        }
+        drop(state);
+        self.end_replay(&ctxt).await
+    }
+
+    /// Save the replay buffer to the output directory, e.g. right after
+    /// something worth keeping happened in a game. Returns false if it isn't
+    /// running.
+    async fn save_replay(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> Result<bool, Error> {
+        let mut state = self.state.write().await;
+        let Some(child) = state.replay.as_mut() else {
+            return Ok(false);
+        };
+
+        if let Ok(Some(status)) = recorder::exit_status(child) {
+            state.replay = None;
+            state.replay_for_game = false;
+            drop(state);
+            Self::replay_stopped(&ctxt).await.ok();
+            return Err(Error::ReplayFailed(format!(
+                "gpu-screen-recorder exited with {}",
+                status
+            )));
+        }
+
+        recorder::save_replay(child)?;
+        tracing::info!("Saving the replay buffer");
+        Ok(true)
+    }
+
+    /// Check if the current recording is paused
+    async fn is_paused(&self) -> bool {
+        self.state.read().await.paused_since.is_some()
+    }
+
+    /// Toggle recording on/off. Toggling during region selection cancels it.
+    async fn toggle_recording(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalEmitter<'_>,
+    ) -> Result<bool, Error> {
         let state = self.state.read().await;
 
-        if state.recording {
+        if state.recording || state.selecting.is_some() || state.queued_start.is_some() {
             drop(state);
-            self.stop_recording(ctxt).await
+            Ok(self.stop_recording(ctxt).await)
         } else {
             drop(state);
             self.start_recording(ctxt).await
         }
     }
 
+    /// Cancel a start request that is still waiting for region selection,
+    /// or for the last recording to be finalized
+    async fn cancel_pending_start(&self) -> bool {
+        let mut state = self.state.write().await;
+        if state.queued_start.take().is_some() {
+            tracing::info!("Cancelling queued start request");
+            return true;
+        }
+        match state.selecting.take() {
+            Some(cancel) => {
+                tracing::info!("Cancelling pending region selection");
+                cancel.send(()).ok();
+                true
+            }
+            None => {
+                tracing::warn!("No region selection in progress, ignoring cancel request");
+                false
+            }
+        }
+    }
+
     /// Check if currently recording
     async fn is_recording(&self) -> bool {
         self.state.read().await.recording
     }
 
+    /// Check if a start request is waiting for the last recording to be
+    /// finalized
+    async fn is_start_queued(&self) -> bool {
+        self.state.read().await.queued_start.is_some()
+    }
+
     /// Get the current recording file path
     async fn get_current_file(&self) -> String {
         self.state
@@ -135,24 +1630,133 @@ impl ScreenRecorder {
             .unwrap_or_default()
     }
 
+    /// Get runtime statistics of the daemon
+    async fn get_statistics(&self) -> Statistics {
+        let state = self.state.read().await;
+
+        let current_bytes = state
+            .current_file
+            .as_ref()
+            .and_then(|f| std::fs::metadata(f).ok())
+            .map_or(0, |m| m.len());
+        let encode = state
+            .encode_stats
+            .as_ref()
+            .map(|s| *s.lock().unwrap())
+            .unwrap_or_default();
+
+        Statistics {
+            uptime: self.started_at.elapsed().as_secs(),
+            started: state.persistent.started,
+            failed: state.persistent.failed,
+            recordings: state.persistent.recordings,
+            bytes_written: state.persistent.bytes_written + current_bytes,
+            encode_fps: encode.fps,
+            dropped_frames: encode.dropped_frames,
+            elapsed: state.recorded().as_secs(),
+        }
+    }
+
+    /// List recordings from the history index whose name, date, output or
+    /// tags contain `query`, oldest first. An empty query lists them all.
+    async fn list_recordings(&self, query: String) -> Result<Vec<history::Recording>, Error> {
+        let query = history::Query {
+            search: Some(query).filter(|q| !q.is_empty()),
+            ..Default::default()
+        };
+        Ok(history::search(&query)?
+            .into_iter()
+            .map(history::Recording::from)
+            .collect())
+    }
+
+    /// Add tags to a recording in the history index. Returns false if the
+    /// recording isn't in it.
+    async fn tag_recording(&self, file: String, tags: Vec<String>) -> Result<bool, Error> {
+        history::tag(&file, &tags)
+    }
+
     /// Signal emitted when recording starts
     #[zbus(signal)]
     async fn recording_started(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
 
+    /// Signal emitted when the recording is paused
+    #[zbus(signal)]
+    async fn recording_paused(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    /// Signal emitted when the recording resumes
+    #[zbus(signal)]
+    async fn recording_resumed(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+
     /// Signal emitted when recording stops, includes file path
     #[zbus(signal)]
     async fn recording_stopped(ctxt: &SignalEmitter<'_>, file_path: &str) -> zbus::Result<()>;
+
+    /// Signal emitted when the replay buffer starts
+    #[zbus(signal)]
+    async fn replay_started(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    /// Signal emitted when the replay buffer stops, or is found to have exited
+    #[zbus(signal)]
+    async fn replay_stopped(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+/// Parse a region given by a client, and check that it is on screen
+async fn checked_region(region: &str) -> Result<Region, Error> {
+    let region: Region = region.parse()?;
+    match compositor::get().outputs().await {
+        Ok(outputs) => {
+            let outputs: Vec<Region> = outputs.iter().map(|o| o.region()).collect();
+            region.check_on(&outputs)?;
+        }
+        Err(e) => tracing::debug!("Could not look up outputs to check the region: {}", e),
+    }
+    Ok(region)
+}
+
+/// Describe a capture for the recording notification, e.g.
+/// "1920x1080 on DP-1 · 60 fps · no audio"
+fn capture_details(region: &Region, output: Option<&compositor::Output>, fps: u32) -> String {
+    // The video has the output's physical pixels, not the logical ones
+    let scale = output.map_or(1.0, |o| o.scale);
+    let size = format!(
+        "{}x{}",
+        (f64::from(region.w) * scale).round(),
+        (f64::from(region.h) * scale).round()
+    );
+    match output {
+        Some(output) => t!(
+            "capture-details-output",
+            size = size,
+            output = output.name,
+            fps = fps
+        ),
+        None => t!("capture-details", size = size, fps = fps),
+    }
+}
+
+/// Tell the user about a failure, unless it was their own doing
+async fn notify_error(error: &Error) {
+    if error.should_notify() {
+        notifications::notify_error(&error.to_string()).await.ok();
+    }
 }
 
 /// Run the daemon (server mode)
 pub async fn run_daemon() -> Result<(), Box<dyn std::error::Error>> {
     tracing::info!("Starting niri-screen-recorder daemon");
+    recorder::install_panic_hook();
 
-    let state = Arc::new(RwLock::new(RecorderState::default()));
+    let state = Arc::new(RwLock::new(RecorderState {
+        persistent: PersistentState::load(),
+        ..Default::default()
+    }));
     let tokio_handle = tokio::runtime::Handle::current();
     let recorder = ScreenRecorder {
         state,
         tokio_handle,
+        started_at: Instant::now(),
+        finalized: Arc::new(Notify::new()),
     };
 
     // Connect to the session bus
@@ -161,158 +1765,90 @@ pub async fn run_daemon() -> Result<(), Box<dyn std::error::Error>> {
     // Register our service name
     connection
         .object_server()
-        .at("/org/matthew_hre/NiriScreenRecorder", recorder)
+        .at(OBJECT_PATH, recorder.clone())
         .await?;
 
-    connection
-        .request_name("org.matthew_hre.NiriScreenRecorder")
-        .await?;
+    connection.request_name(BUS_NAME).await?;
 
     tracing::info!("DBus service registered, waiting for requests...");
+    systemd::notify("READY=1");
 
-    // Run forever
-    std::future::pending::<()>().await;
-
-    Ok(())
-}
-
-/// Client: call StartRecording on the daemon
-pub async fn call_start() -> Result<(), Box<dyn std::error::Error>> {
-    let connection = Connection::session().await?;
-
-    let proxy = zbus::Proxy::new(
-        &connection,
-        "org.matthew_hre.NiriScreenRecorder",
-        "/org/matthew_hre/NiriScreenRecorder",
-        "org.matthew_hre.NiriScreenRecorder",
-    )
-    .await?;
-
-    let result: Result<bool, _> = proxy.call("StartRecording", &()).await;
-    match result {
-        Ok(started) => {
-            if started {
-                println!("Recording started");
-            } else {
-                eprintln!(
-                    "Failed to start recording (already recording or region selection failed)"
-                );
-            }
-        }
-        Err(e) => {
-            eprintln!(
-                "Error: Could not connect to daemon. Is it running? (niri-screen-recorder daemon)"
-            );
-            eprintln!("Details: {}", e);
-            std::process::exit(1);
+    tokio::spawn(async {
+        if let Err(e) = notifications::dispatch_actions().await {
+            tracing::error!("Notification actions unavailable: {}", e);
         }
-    }
+    });
 
-    Ok(())
-}
+    tokio::spawn(recorder.clone().follow_outputs(
+        niri::watch_events(),
+        SignalEmitter::new(&connection, OBJECT_PATH)?,
+    ));
 
-/// Client: call StopRecording on the daemon
-pub async fn call_stop() -> Result<(), Box<dyn std::error::Error>> {
-    let connection = Connection::session().await?;
+    tokio::spawn(recorder.clone().follow_sleep(
+        session::watch_sleep(),
+        SignalEmitter::new(&connection, OBJECT_PATH)?,
+    ));
 
-    let proxy = zbus::Proxy::new(
-        &connection,
-        "org.matthew_hre.NiriScreenRecorder",
-        "/org/matthew_hre/NiriScreenRecorder",
-        "org.matthew_hre.NiriScreenRecorder",
-    )
-    .await?;
-
-    let result: Result<bool, _> = proxy.call("StopRecording", &()).await;
-    match result {
-        Ok(stopped) => {
-            if stopped {
-                println!("Recording stopped");
-            } else {
-                eprintln!("No recording in progress");
-            }
-        }
-        Err(e) => {
-            eprintln!(
-                "Error: Could not connect to daemon. Is it running? (niri-screen-recorder daemon)"
-            );
-            eprintln!("Details: {}", e);
-            std::process::exit(1);
-        }
-    }
+    tokio::spawn(recorder.clone().follow_shutdown(
+        session::watch_shutdown(),
+        SignalEmitter::new(&connection, OBJECT_PATH)?,
+    ));
 
-    Ok(())
-}
+    if config::get().pause_on_lock {
+        tokio::spawn(recorder.clone().follow_lock(
+            session::watch_lock(),
+            SignalEmitter::new(&connection, OBJECT_PATH)?,
+        ));
+    }
 
-/// Client: call ToggleRecording on the daemon
-pub async fn call_toggle() -> Result<(), Box<dyn std::error::Error>> {
-    let connection = Connection::session().await?;
+    if let Some(addr) = config::get().metrics_addr {
+        let recorder = recorder.clone();
+        tokio::spawn(metrics::serve(addr, move || {
+            let recorder = recorder.clone();
+            async move { recorder.get_statistics().await }
+        }));
+    }
 
-    let proxy = zbus::Proxy::new(
-        &connection,
-        "org.matthew_hre.NiriScreenRecorder",
-        "/org/matthew_hre/NiriScreenRecorder",
-        "org.matthew_hre.NiriScreenRecorder",
-    )
-    .await?;
-
-    let result: Result<bool, _> = proxy.call("ToggleRecording", &()).await;
-    match result {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!(
-                "Error: Could not connect to daemon. Is it running? (niri-screen-recorder daemon)"
-            );
-            eprintln!("Details: {}", e);
-            std::process::exit(1);
-        }
+    if config::get().replay_on_game {
+        tokio::spawn(recorder.clone().follow_games(
+            gamemode::watch_games(),
+            SignalEmitter::new(&connection, OBJECT_PATH)?,
+        ));
     }
 
-    Ok(())
-}
+    if config::get().tray
+        && let Err(e) = tray::serve(&connection).await
+    {
+        tracing::warn!("Failed to set up the tray icon: {}", e);
+    }
 
-/// Client: check recording status
-pub async fn call_status() -> Result<(), Box<dyn std::error::Error>> {
-    let connection = Connection::session().await?;
+    // Bring back a replay buffer that was running when the last daemon
+    // exited, after the tray so it shows up there
+    if recorder.state.read().await.persistent.replay
+        && let Err(e) = recorder
+            .run_replay(false, &SignalEmitter::new(&connection, OBJECT_PATH)?)
+            .await
+    {
+        tracing::warn!("Failed to restart the replay buffer: {}", e);
+    }
 
-    let proxy = zbus::Proxy::new(
-        &connection,
-        "org.matthew_hre.NiriScreenRecorder",
-        "/org/matthew_hre/NiriScreenRecorder",
-        "org.matthew_hre.NiriScreenRecorder",
-    )
-    .await?;
-
-    let recording: Result<bool, _> = proxy.call("IsRecording", &()).await;
-    let recording = match recording {
-        Ok(v) => v,
-        Err(e) => {
-            eprintln!(
-                "Error: Could not connect to daemon. Is it running? (niri-screen-recorder daemon)"
-            );
-            eprintln!("Details: {}", e);
-            std::process::exit(1);
-        }
-    };
+    if let Some(interval) = systemd::watchdog_interval() {
+        tracing::debug!("Pinging systemd watchdog every {:?}", interval);
+        tokio::spawn(recorder.clone().run_watchdog(interval));
+    }
 
-    let file: Result<String, _> = proxy.call("GetCurrentFile", &()).await;
-    let file = match file {
-        Ok(v) => v,
-        Err(e) => {
-            eprintln!(
-                "Error: Could not connect to daemon. Is it running? (niri-screen-recorder daemon)"
-            );
-            eprintln!("Details: {}", e);
-            std::process::exit(1);
-        }
-    };
+    // Run until the session goes away, then finalize any active recording
+    // so a logout or shutdown doesn't leave a corrupt file behind
+    let reason = session::wait_for_session_end().await;
+    tracing::info!("Shutting down: {}", reason);
+    systemd::notify("STOPPING=1");
 
-    if recording {
-        println!("Recording: yes");
-        println!("File: {}", file);
-    } else {
-        println!("Recording: no");
-    }
+    recorder
+        .settle(&SignalEmitter::new(&connection, OBJECT_PATH)?)
+        .await;
+    recorder
+        .end_replay(&SignalEmitter::new(&connection, OBJECT_PATH)?)
+        .await;
 
     Ok(())
 }
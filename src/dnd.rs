@@ -0,0 +1,165 @@
+use std::str::FromStr;
+use tokio::process::Command;
+use zbus::{Connection, proxy};
+
+use crate::config;
+
+/// DBus proxy for the do-not-disturb state of the notification server
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait NotificationServer {
+    /// Added in version 1.3 of the notification spec; few servers have it
+    #[zbus(property)]
+    fn inhibited(&self) -> zbus::Result<bool>;
+}
+
+/// When non-error notifications are held back
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// While the notification server is in do-not-disturb mode
+    Auto,
+    /// Always
+    Always,
+    /// Never
+    Never,
+}
+
+impl FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Mode::Auto),
+            "always" => Ok(Mode::Always),
+            "never" => Ok(Mode::Never),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Whether success, progress and warning notifications should be held back.
+/// Errors are always shown.
+pub async fn quiet() -> bool {
+    match config::get().quiet {
+        Mode::Always => true,
+        Mode::Never => false,
+        Mode::Auto => do_not_disturb().await,
+    }
+}
+
+/// Whether the notification server is in do-not-disturb mode. Tries the
+/// standard `Inhibited` property first, then the control tools of dunst,
+/// mako and swaync.
+async fn do_not_disturb() -> bool {
+    if let Some(inhibited) = inhibited().await {
+        return inhibited;
+    }
+
+    Server::detect().await.is_some_and(|(_, enabled)| enabled)
+}
+
+async fn inhibited() -> Option<bool> {
+    let connection = Connection::session().await.ok()?;
+    let proxy = NotificationServerProxy::new(&connection).await.ok()?;
+    proxy
+        .inhibited()
+        .await
+        .inspect_err(|e| tracing::trace!("Inhibited property unavailable: {}", e))
+        .ok()
+}
+
+/// Run a notification server's control tool. Returns `None` if it isn't
+/// installed or fails, e.g. because its server isn't the one running.
+async fn query(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().await.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// A notification server whose do-not-disturb mode can be switched
+#[derive(Clone, Copy)]
+enum Server {
+    Dunst,
+    Mako,
+    Swaync,
+}
+
+impl Server {
+    /// The running server and whether it is already in do-not-disturb mode
+    async fn detect() -> Option<(Self, bool)> {
+        if let Some(paused) = query("dunstctl", &["is-paused"]).await {
+            return Some((Server::Dunst, paused.trim() == "true"));
+        }
+        if let Some(modes) = query("makoctl", &["mode"]).await {
+            let enabled = modes.lines().any(|mode| mode.trim() == "do-not-disturb");
+            return Some((Server::Mako, enabled));
+        }
+        if let Some(dnd) = query("swaync-client", &["--get-dnd"]).await {
+            return Some((Server::Swaync, dnd.trim() == "true"));
+        }
+        None
+    }
+
+    async fn set(self, enabled: bool) -> bool {
+        let args: &[&str] = match (self, enabled) {
+            (Server::Dunst, true) => &["set-paused", "true"],
+            (Server::Dunst, false) => &["set-paused", "false"],
+            (Server::Mako, true) => &["mode", "-a", "do-not-disturb"],
+            (Server::Mako, false) => &["mode", "-r", "do-not-disturb"],
+            (Server::Swaync, true) => &["--skip-wait", "--dnd-on"],
+            (Server::Swaync, false) => &["--skip-wait", "--dnd-off"],
+        };
+        query(self.program(), args).await.is_some()
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Server::Dunst => "dunstctl",
+            Server::Mako => "makoctl",
+            Server::Swaync => "swaync-client",
+        }
+    }
+}
+
+/// Do-not-disturb mode, switched on for the duration of a recording so
+/// notification bubbles don't end up in it
+pub struct Override {
+    server: Server,
+}
+
+impl Override {
+    /// Switch do-not-disturb on. Returns `None` if it already was, or the
+    /// notification server can't be controlled, since there is nothing to
+    /// restore then.
+    pub async fn enable() -> Option<Self> {
+        let Some((server, enabled)) = Server::detect().await else {
+            tracing::debug!("No notification server to switch to do-not-disturb");
+            return None;
+        };
+        if enabled {
+            return None;
+        }
+
+        if !server.set(true).await {
+            tracing::warn!("Failed to enable do-not-disturb with {}", server.program());
+            return None;
+        }
+        tracing::debug!("Enabled do-not-disturb with {}", server.program());
+        Some(Self { server })
+    }
+
+    /// Switch do-not-disturb off again
+    pub async fn restore(self) {
+        if !self.server.set(false).await {
+            tracing::warn!(
+                "Failed to disable do-not-disturb with {}",
+                self.server.program()
+            );
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use zbus::message::{Header, Message};
+use zbus::names::ErrorName;
+
+/// Prefix for the DBus error names the daemon replies with
+pub const DBUS_ERROR_PREFIX: &str = "org.matthew_hre.NiriScreenRecorder.Error";
+
+/// Everything that can go wrong while selecting, recording, or announcing a recording
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Region selection cancelled")]
+    SelectionCancelled,
+
+    #[error("Region selection timed out after {0}s")]
+    SelectionTimedOut(u64),
+
+    #[error("No region selected")]
+    NoRegionSelected,
+
+    #[error("Invalid region: {0} (expected WxH+X+Y)")]
+    InvalidRegion(String),
+
+    #[error("Region {0} is not entirely on screen")]
+    RegionOffScreen(String),
+
+    #[error("No window with id {0} is visible")]
+    WindowNotFound(u64),
+
+    #[error("No enabled output matches {0}")]
+    OutputNotFound(String),
+
+    #[error("Outputs with different scales can't be recorded as one: {0}")]
+    MixedScales(String),
+
+    #[error("A recording is already active or pending")]
+    AlreadyRecording,
+
+    #[error("Cannot find home directory")]
+    NoHomeDirectory,
+
+    #[error("Failed to create output directory: {0}")]
+    OutputDirectory(#[source] std::io::Error),
+
+    #[error("{0} was deleted or moved while recording")]
+    FileRemoved(String),
+
+    #[error("Failed to move recording to the output directory: {0}")]
+    MoveFailed(#[source] std::io::Error),
+
+    #[error("Unsupported video codec: {0}")]
+    EncoderUnsupported(String),
+
+    #[error("Failed to start gpu-screen-recorder: {0}")]
+    RecorderSpawnFailed(#[source] std::io::Error),
+
+    #[error("Failed to stop gpu-screen-recorder: {0}")]
+    RecorderStopFailed(String),
+
+    #[error("Failed to pause or resume gpu-screen-recorder: {0}")]
+    RecorderPauseFailed(String),
+
+    #[error("Replay buffer failed: {0}")]
+    ReplayFailed(String),
+
+    #[error("Failed to run {program}: {source}")]
+    CommandFailed {
+        program: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Recording is corrupt: {0}")]
+    CorruptRecording(String),
+
+    #[error("Post-processing failed: {0}")]
+    PostProcessFailed(String),
+
+    #[error("File does not exist: {0}")]
+    FileNotFound(String),
+
+    #[error("Could not find a file opener (tried xdg-open and gio)")]
+    NoFileOpener,
+
+    #[error("Could not find a menu (tried fuzzel, wofi and rofi)")]
+    NoMenu,
+
+    #[error("Failed to move file to the trash: {0}")]
+    Trash(#[source] std::io::Error),
+
+    #[error("Clipboard error: {0}")]
+    Clipboard(#[from] arboard::Error),
+
+    #[error("Wayland clipboard error: {0}")]
+    WaylandClipboard(#[from] wl_clipboard_rs::copy::Error),
+
+    #[error("Daemon is already running (pid {0})")]
+    AlreadyRunning(i32),
+
+    #[error("Failed to write PID file: {0}")]
+    PidFile(#[source] std::io::Error),
+
+    #[error("Failed to daemonize: {0}")]
+    Daemonize(#[source] std::io::Error),
+
+    #[error("Upload failed: {0}")]
+    UploadFailed(String),
+
+    #[error("Upload credentials unavailable: {0}")]
+    Credentials(String),
+
+    #[error("Daemon log unavailable: {0}")]
+    LogUnavailable(String),
+
+    #[error("Recording history unavailable: {0}")]
+    History(String),
+
+    #[error("niri IPC failed: {0}")]
+    NiriIpc(String),
+
+    #[error("Compositor IPC failed: {0}")]
+    CompositorIpc(String),
+
+    #[error("DBus is unavailable: {0}")]
+    DbusUnavailable(#[source] zbus::Error),
+
+    #[error("DBus call failed: {0}")]
+    Dbus(#[from] zbus::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Short, stable name for the error kind, used in DBus error names
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::SelectionCancelled => "SelectionCancelled",
+            Error::SelectionTimedOut(_) => "SelectionTimedOut",
+            Error::NoRegionSelected => "NoRegionSelected",
+            Error::InvalidRegion(_) => "InvalidRegion",
+            Error::RegionOffScreen(_) => "RegionOffScreen",
+            Error::WindowNotFound(_) => "WindowNotFound",
+            Error::OutputNotFound(_) => "OutputNotFound",
+            Error::MixedScales(_) => "MixedScales",
+            Error::AlreadyRecording => "AlreadyRecording",
+            Error::NoHomeDirectory | Error::OutputDirectory(_) => "OutputDirectory",
+            Error::FileRemoved(_) => "FileRemoved",
+            Error::MoveFailed(_) => "MoveFailed",
+            Error::EncoderUnsupported(_) => "EncoderUnsupported",
+            Error::RecorderSpawnFailed(_) => "RecorderSpawnFailed",
+            Error::RecorderStopFailed(_) => "RecorderStopFailed",
+            Error::RecorderPauseFailed(_) => "RecorderPauseFailed",
+            Error::ReplayFailed(_) => "ReplayFailed",
+            Error::CommandFailed { .. } => "CommandFailed",
+            Error::CorruptRecording(_) => "CorruptRecording",
+            Error::PostProcessFailed(_) => "PostProcessFailed",
+            Error::FileNotFound(_) => "FileNotFound",
+            Error::NoFileOpener => "NoFileOpener",
+            Error::NoMenu => "NoMenu",
+            Error::Trash(_) => "Trash",
+            Error::Clipboard(_) | Error::WaylandClipboard(_) => "Clipboard",
+            Error::AlreadyRunning(_) => "AlreadyRunning",
+            Error::PidFile(_) | Error::Daemonize(_) => "Daemonize",
+            Error::UploadFailed(_) => "UploadFailed",
+            Error::Credentials(_) => "Credentials",
+            Error::LogUnavailable(_) => "LogUnavailable",
+            Error::History(_) => "History",
+            Error::NiriIpc(_) => "NiriIpc",
+            Error::CompositorIpc(_) => "CompositorIpc",
+            Error::DbusUnavailable(_) => "DbusUnavailable",
+            Error::Dbus(_) => "Dbus",
+        }
+    }
+
+    /// Whether the user should be told about this error with a notification.
+    /// Cancelling a selection is deliberate, so it isn't worth a bubble.
+    pub fn should_notify(&self) -> bool {
+        !matches!(self, Error::SelectionCancelled)
+    }
+}
+
+/// Map a DBus error name returned by the daemon to a CLI exit code
+pub fn exit_code(error_name: &str) -> i32 {
+    let kind = error_name
+        .strip_prefix(DBUS_ERROR_PREFIX)
+        .and_then(|k| k.strip_prefix('.'));
+
+    match kind {
+        Some("SelectionCancelled" | "SelectionTimedOut" | "NoRegionSelected") => 2,
+        Some("EncoderUnsupported") => 3,
+        Some(
+            "RecorderSpawnFailed" | "RecorderStopFailed" | "RecorderPauseFailed" | "ReplayFailed",
+        ) => 4,
+        _ => 1,
+    }
+}
+
+impl zbus::DBusError for Error {
+    fn create_reply(&self, header: &Header<'_>) -> zbus::Result<Message> {
+        Message::error(header, self.name())?.build(&(self.to_string(),))
+    }
+
+    fn name(&self) -> ErrorName<'_> {
+        ErrorName::try_from(format!("{}.{}", DBUS_ERROR_PREFIX, self.kind()))
+            .expect("error kinds are valid DBus names")
+    }
+
+    fn description(&self) -> Option<&str> {
+        None
+    }
+}
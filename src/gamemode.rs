@@ -0,0 +1,70 @@
+use futures_util::StreamExt;
+use tokio::sync::watch;
+use zbus::zvariant::ObjectPath;
+use zbus::{Connection, proxy};
+
+/// DBus proxy for Feral's GameMode daemon, which games register with while
+/// they run
+#[proxy(
+    interface = "com.feralinteractive.GameMode",
+    default_service = "com.feralinteractive.GameMode",
+    default_path = "/com/feralinteractive/GameMode"
+)]
+trait GameMode {
+    /// Number of games that have GameMode active
+    #[zbus(property)]
+    fn client_count(&self) -> zbus::Result<i32>;
+
+    #[zbus(signal)]
+    fn game_registered(&self, pid: i32, object_path: ObjectPath<'_>);
+
+    #[zbus(signal)]
+    fn game_unregistered(&self, pid: i32, object_path: ObjectPath<'_>);
+}
+
+/// Track whether any game has GameMode active. Without gamemoded running,
+/// no game ever is.
+pub fn watch_games() -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(false);
+
+    tokio::spawn(async move {
+        if let Err(e) = follow_games(&tx).await {
+            tracing::warn!("Not watching for games: {}", e);
+        }
+    });
+
+    rx
+}
+
+async fn follow_games(tx: &watch::Sender<bool>) -> zbus::Result<()> {
+    let connection = Connection::session().await?;
+    // gamemoded may not announce count changes, so don't trust a cached one
+    let gamemode = GameModeProxy::builder(&connection)
+        .cache_properties(zbus::proxy::CacheProperties::No)
+        .build()
+        .await?;
+
+    let mut registered = gamemode.receive_game_registered().await?;
+    let mut unregistered = gamemode.receive_game_unregistered().await?;
+
+    tracing::debug!("Watching for games registering with GameMode");
+
+    loop {
+        // The count is read again on every change, so games that were
+        // already running when the daemon started are counted too
+        let playing = match gamemode.client_count().await {
+            Ok(count) => count > 0,
+            Err(e) => {
+                tracing::debug!("GameMode unavailable: {}", e);
+                false
+            }
+        };
+        tx.send_if_modified(|current| std::mem::replace(current, playing) != playing);
+
+        tokio::select! {
+            Some(_) = registered.next() => {}
+            Some(_) = unregistered.next() => {}
+            else => return Err(zbus::Error::Failure("GameMode signal stream closed".to_string())),
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+/// A rectangle in the compositor's global logical space, written "WxH+X+Y"
+/// as slurp prints it and gpu-screen-recorder takes it for `-w`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Region {
+    /// The first column past the right edge
+    pub fn right(&self) -> i64 {
+        i64::from(self.x) + i64::from(self.w)
+    }
+
+    /// The first row past the bottom edge
+    pub fn bottom(&self) -> i64 {
+        i64::from(self.y) + i64::from(self.h)
+    }
+
+    fn area(&self) -> u64 {
+        u64::from(self.w) * u64::from(self.h)
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && i64::from(x) < self.right() && i64::from(y) < self.bottom()
+    }
+
+    /// The part of `self` inside `other`, if they overlap
+    pub fn intersection(&self, other: &Region) -> Option<Region> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        (right > i64::from(x) && bottom > i64::from(y)).then(|| Region {
+            x,
+            y,
+            w: (right - i64::from(x)) as u32,
+            h: (bottom - i64::from(y)) as u32,
+        })
+    }
+
+    /// The smallest region containing all of `regions`, `None` if there are
+    /// none. Whatever lies between them, like the gap between two outputs
+    /// of different sizes, is part of it.
+    pub fn bounding(regions: &[Region]) -> Option<Region> {
+        let x = regions.iter().map(|r| r.x).min()?;
+        let y = regions.iter().map(|r| r.y).min()?;
+        let right = regions.iter().map(Region::right).max()?;
+        let bottom = regions.iter().map(Region::bottom).max()?;
+        Some(Region {
+            x,
+            y,
+            w: (right - i64::from(x)) as u32,
+            h: (bottom - i64::from(y)) as u32,
+        })
+    }
+
+    /// Make sure the region lies entirely on `outputs`, which may be split
+    /// across several of them
+    pub fn check_on(&self, outputs: &[Region]) -> Result<()> {
+        let parts: Vec<Region> = outputs
+            .iter()
+            .filter_map(|o| self.intersection(o))
+            .collect();
+        if covered_area(&parts) < self.area() {
+            return Err(Error::RegionOffScreen(self.to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// The area `regions` cover together, counting overlaps, like those of
+/// mirrored outputs, only once. The plane is cut along every edge, and each
+/// cell is either covered entirely or not at all.
+fn covered_area(regions: &[Region]) -> u64 {
+    let edges = |edges: fn(&Region) -> [i64; 2]| {
+        let mut edges: Vec<i64> = regions.iter().flat_map(edges).collect();
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    };
+    let xs = edges(|r| [i64::from(r.x), r.right()]);
+    let ys = edges(|r| [i64::from(r.y), r.bottom()]);
+
+    let mut area = 0;
+    for x in xs.windows(2) {
+        for y in ys.windows(2) {
+            let covered = regions.iter().any(|r| {
+                i64::from(r.x) <= x[0]
+                    && x[1] <= r.right()
+                    && i64::from(r.y) <= y[0]
+                    && y[1] <= r.bottom()
+            });
+            if covered {
+                area += ((x[1] - x[0]) * (y[1] - y[0])) as u64;
+            }
+        }
+    }
+    area
+}
+
+impl FromStr for Region {
+    type Err = Error;
+
+    /// Parse "WxH+X+Y". The size must be positive and the region must fit
+    /// in the logical coordinate space.
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = || {
+            let (size, position) = s.split_once('+')?;
+            let (w, h) = size.split_once('x')?;
+            let (x, y) = position.split_once('+')?;
+            let region = Region {
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+                w: w.parse().ok()?,
+                h: h.parse().ok()?,
+            };
+            let fits =
+                region.right() <= i64::from(i32::MAX) && region.bottom() <= i64::from(i32::MAX);
+            (region.w > 0 && region.h > 0 && fits).then_some(region)
+        };
+        parse().ok_or_else(|| Error::InvalidRegion(s.to_string()))
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.w, self.h, self.x, self.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seeded pseudo-random cases, so a failing case comes up again on the
+    /// next run
+    struct Cases(u64);
+
+    impl Cases {
+        fn new() -> Self {
+            Self(0x9e37_79b9_7f4a_7c15)
+        }
+
+        fn next(&mut self) -> u64 {
+            // xorshift64
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn between(&mut self, low: i64, high: i64) -> i64 {
+            low + (self.next() % (high - low + 1) as u64) as i64
+        }
+
+        /// A region around the usual range of desktop coordinates
+        fn region(&mut self) -> Region {
+            Region {
+                x: self.between(-8000, 8000) as i32,
+                y: self.between(-8000, 8000) as i32,
+                w: self.between(1, 8000) as u32,
+                h: self.between(1, 8000) as u32,
+            }
+        }
+
+        /// A region somewhere inside `bounds`
+        fn inside(&mut self, bounds: &Region) -> Region {
+            let w = self.between(1, bounds.w.into()) as u32;
+            let h = self.between(1, bounds.h.into()) as u32;
+            Region {
+                x: bounds.x + self.between(0, (bounds.w - w).into()) as i32,
+                y: bounds.y + self.between(0, (bounds.h - h).into()) as i32,
+                w,
+                h,
+            }
+        }
+    }
+
+    const RUNS: usize = 2000;
+
+    #[test]
+    fn formatting_round_trips() {
+        let mut cases = Cases::new();
+        for _ in 0..RUNS {
+            let region = cases.region();
+            assert_eq!(region.to_string().parse::<Region>().unwrap(), region);
+        }
+    }
+
+    #[test]
+    fn bounding_spans_gaps_between_regions() {
+        let laptop = Region {
+            x: 0,
+            y: 360,
+            w: 1920,
+            h: 1080,
+        };
+        let external = Region {
+            x: 1920,
+            y: 0,
+            w: 2560,
+            h: 1440,
+        };
+        let bounding = Region::bounding(&[laptop, external]).unwrap();
+        assert_eq!(bounding.to_string(), "4480x1440+0+0");
+        assert!(bounding.check_on(&[laptop, external]).is_err());
+        assert_eq!(Region::bounding(&[laptop]), Some(laptop));
+        assert_eq!(Region::bounding(&[]), None);
+    }
+
+    #[test]
+    fn parses_slurp_output() {
+        let region: Region = "1920x1080+-1920+0".parse().unwrap();
+        assert_eq!(
+            region,
+            Region {
+                x: -1920,
+                y: 0,
+                w: 1920,
+                h: 1080
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_regions_are_rejected() {
+        for s in [
+            "",
+            "nonsense",
+            "1920x1080",
+            "1920x1080+0",
+            "0x1080+0+0",
+            "1920x0+0+0",
+            "-5x10+0+0",
+            "1920x1080+0+0+0",
+            "1920 x1080+0+0",
+            "10x10+2147483640+0",
+        ] {
+            assert!(
+                matches!(s.parse::<Region>(), Err(Error::InvalidRegion(_))),
+                "{:?} was accepted",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn intersection_is_inside_both() {
+        let mut cases = Cases::new();
+        for _ in 0..RUNS {
+            let (a, b) = (cases.region(), cases.region());
+            assert_eq!(a.intersection(&b), b.intersection(&a));
+            if let Some(i) = a.intersection(&b) {
+                for region in [a, b] {
+                    assert!(i.x >= region.x && i.y >= region.y);
+                    assert!(i.right() <= region.right() && i.bottom() <= region.bottom());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn intersection_with_an_enclosing_region_is_the_region() {
+        let mut cases = Cases::new();
+        for _ in 0..RUNS {
+            let outer = cases.region();
+            let inner = cases.inside(&outer);
+            assert_eq!(inner.intersection(&outer), Some(inner));
+        }
+    }
+
+    #[test]
+    fn regions_inside_an_output_are_on_screen() {
+        let mut cases = Cases::new();
+        for _ in 0..RUNS {
+            let output = cases.region();
+            let region = cases.inside(&output);
+            assert!(region.check_on(&[output]).is_ok());
+        }
+    }
+
+    #[test]
+    fn regions_sticking_out_are_off_screen() {
+        let mut cases = Cases::new();
+        for _ in 0..RUNS {
+            let output = cases.region();
+            let mut region = cases.inside(&output);
+            region.w += (output.right() - region.right()) as u32 + 1;
+            assert!(matches!(
+                region.check_on(&[output]),
+                Err(Error::RegionOffScreen(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn regions_may_span_outputs() {
+        let left: Region = "1920x1080+0+0".parse().unwrap();
+        let right: Region = "2560x1440+1920+0".parse().unwrap();
+        let across: Region = "1000x500+1500+100".parse().unwrap();
+
+        assert!(across.check_on(&[left, right]).is_ok());
+        assert!(across.check_on(&[left]).is_err());
+        // Below the shorter output
+        let below: Region = "1000x500+1500+1000".parse().unwrap();
+        assert!(below.check_on(&[left, right]).is_err());
+    }
+
+    #[test]
+    fn overlapping_outputs_count_once() {
+        // A mirrored output on top of the left one, and one overlapping the
+        // right half of it
+        let left: Region = "1920x1080+0+0".parse().unwrap();
+        let overlap: Region = "960x1080+960+0".parse().unwrap();
+        let wide: Region = "3840x1080+0+0".parse().unwrap();
+
+        assert!(wide.check_on(&[left, left]).is_err());
+        assert!(wide.check_on(&[left, overlap, left]).is_err());
+        assert!(left.check_on(&[overlap, left, overlap]).is_ok());
+    }
+}
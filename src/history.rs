@@ -0,0 +1,323 @@
+use anstyle::Style;
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use zbus::zvariant::{SerializeDict, Type};
+
+use crate::chapters;
+use crate::error::{Error, Result};
+use crate::geometry::Region;
+use crate::i18n::t;
+use crate::table::{self, Align, Table};
+
+/// A finished recording, as kept in the history index
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Entry {
+    pub file: String,
+    /// When the recording started, as RFC 3339
+    pub started: String,
+    /// When the recording stopped, as RFC 3339
+    pub ended: String,
+    /// Length in seconds
+    pub duration: f64,
+    pub size: Option<u64>,
+    /// The captured region, as "WxH+X+Y"
+    pub region: Option<String>,
+    /// Output the region was on
+    pub output: Option<String>,
+    pub fps: u32,
+    /// Video codec, if one was set
+    pub codec: Option<String>,
+    /// gpu-screen-recorder quality preset, if one was set
+    pub quality: Option<String>,
+    /// "battery" when recorded with the battery settings, otherwise "default"
+    pub profile: String,
+    /// Title of the window that had focus when the recording started
+    pub window_title: Option<String>,
+    /// App ID of that window
+    pub app_id: Option<String>,
+    /// Segments between pauses and markers, in order
+    pub chapters: Vec<Chapter>,
+    pub tags: Vec<String>,
+}
+
+/// A chapter of a recording
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Chapter {
+    /// Seconds from the start, not counting pauses
+    pub start: f64,
+    pub title: String,
+}
+
+impl From<&chapters::Chapter> for Chapter {
+    fn from(chapter: &chapters::Chapter) -> Self {
+        Self {
+            start: chapter.start.as_secs_f64(),
+            title: chapter.title.clone(),
+        }
+    }
+}
+
+impl Entry {
+    /// Start an entry for a recording that begins now
+    pub fn started(file: &str, region: &Region, output: Option<&str>, fps: u32) -> Self {
+        Self {
+            file: file.to_string(),
+            started: Local::now().to_rfc3339(),
+            region: Some(region.to_string()),
+            output: output.map(str::to_string),
+            fps,
+            profile: "default".to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Note that the recording stopped now
+    pub fn stopped(&mut self) {
+        self.ended = Local::now().to_rfc3339();
+    }
+
+    fn started_at(&self) -> Option<DateTime<Local>> {
+        DateTime::parse_from_rfc3339(&self.started)
+            .ok()
+            .map(|t| t.with_timezone(&Local))
+    }
+
+    /// Whether `query` appears in the file name, start date, output or tags,
+    /// ignoring case
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let name = Path::new(&self.file)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        std::iter::once(name.as_ref())
+            .chain(std::iter::once(self.started.as_str()))
+            .chain(self.output.as_deref())
+            .chain(self.tags.iter().map(String::as_str))
+            .any(|field| field.to_lowercase().contains(&query))
+    }
+}
+
+/// Which recordings to list
+#[derive(Default)]
+pub struct Query {
+    /// Text to look for in the name, date, output or tags
+    pub search: Option<String>,
+    pub tag: Option<String>,
+    /// First day to include
+    pub since: Option<NaiveDate>,
+    /// Last day to include
+    pub until: Option<NaiveDate>,
+}
+
+impl Query {
+    fn matches(&self, entry: &Entry) -> bool {
+        let day = entry.started_at().map(|t| t.date_naive());
+        self.search.as_deref().is_none_or(|q| entry.matches(q))
+            && self.tag.as_ref().is_none_or(|t| entry.tags.contains(t))
+            && self
+                .since
+                .is_none_or(|since| day.is_some_and(|d| d >= since))
+            && self
+                .until
+                .is_none_or(|until| day.is_some_and(|d| d <= until))
+    }
+}
+
+/// A recording as returned by `ListRecordings`, sent as an `a{sv}` dict so
+/// fields can be added without breaking existing consumers
+#[derive(SerializeDict, Type)]
+#[zvariant(
+    signature = "a{sv}",
+    rename_all = "kebab-case",
+    crate = "zbus::zvariant"
+)]
+pub struct Recording {
+    pub file: String,
+    pub started: String,
+    pub ended: String,
+    pub duration: f64,
+    pub size: Option<u64>,
+    pub region: Option<String>,
+    pub output: Option<String>,
+    pub fps: u32,
+    pub codec: Option<String>,
+    pub quality: Option<String>,
+    pub profile: String,
+    pub window_title: Option<String>,
+    pub app_id: Option<String>,
+    /// Start (in seconds) and title of each chapter
+    pub chapters: Vec<(f64, String)>,
+    pub tags: Vec<String>,
+}
+
+impl From<Entry> for Recording {
+    fn from(entry: Entry) -> Self {
+        Self {
+            file: entry.file,
+            started: entry.started,
+            ended: entry.ended,
+            duration: entry.duration,
+            size: entry.size,
+            region: entry.region,
+            output: entry.output,
+            fps: entry.fps,
+            codec: entry.codec,
+            quality: entry.quality,
+            profile: entry.profile,
+            window_title: entry.window_title,
+            app_id: entry.app_id,
+            chapters: entry
+                .chapters
+                .into_iter()
+                .map(|c| (c.start, c.title))
+                .collect(),
+            tags: entry.tags,
+        }
+    }
+}
+
+/// Held while the index is written, so tagging can't drop a recording that
+/// is added meanwhile
+static WRITING: Mutex<()> = Mutex::new(());
+
+/// The index, one JSON object per line under XDG_DATA_HOME
+fn index_path() -> Result<PathBuf> {
+    Ok(dirs::data_dir()
+        .ok_or(Error::NoHomeDirectory)?
+        .join("niri-screen-recorder")
+        .join("history.jsonl"))
+}
+
+/// Add a finished recording to the index
+pub fn record(entry: &Entry) {
+    if let Err(e) = append(entry) {
+        tracing::warn!("Failed to add {} to the history: {}", entry.file, e);
+    }
+}
+
+fn append(entry: &Entry) -> Result<()> {
+    let _writing = WRITING.lock().unwrap();
+    let path = index_path()?;
+    let line = serde_json::to_string(entry).map_err(|e| Error::History(e.to_string()))?;
+    let write = || {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        writeln!(file, "{}", line)
+    };
+    write().map_err(|e| Error::History(format!("{}: {}", path.display(), e)))
+}
+
+/// Write the entry as pretty-printed JSON next to its recording, e.g.
+/// `screen-record-<date>.mp4.json`
+pub fn write_sidecar(entry: &Entry) {
+    let path = sidecar_path(&entry.file);
+    let result = serde_json::to_vec_pretty(entry)
+        .map_err(std::io::Error::other)
+        .and_then(|json| std::fs::write(&path, json));
+    if let Err(e) = result {
+        tracing::warn!("Failed to write {}: {}", path.display(), e);
+    }
+}
+
+/// Where the sidecar of a recording goes
+pub fn sidecar_path(file: &str) -> PathBuf {
+    PathBuf::from(format!("{}.json", file))
+}
+
+/// Every recording in the index, oldest first. Unreadable lines are skipped.
+pub fn load() -> Result<Vec<Entry>> {
+    let path = index_path()?;
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::History(format!("{}: {}", path.display(), e))),
+    };
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            serde_json::from_str(line)
+                .inspect_err(|e| tracing::warn!("Skipping unreadable history entry: {}", e))
+                .ok()
+        })
+        .collect())
+}
+
+/// The recordings matching `query`, oldest first
+pub fn search(query: &Query) -> Result<Vec<Entry>> {
+    Ok(load()?.into_iter().filter(|e| query.matches(e)).collect())
+}
+
+/// Add `tags` to the recording at `file`. Returns false if it isn't in the
+/// index.
+pub fn tag(file: &str, tags: &[String]) -> Result<bool> {
+    let _writing = WRITING.lock().unwrap();
+    let mut entries = load()?;
+    let Some(entry) = entries.iter_mut().rev().find(|e| e.file == file) else {
+        return Ok(false);
+    };
+    for tag in tags {
+        if !entry.tags.contains(tag) {
+            entry.tags.push(tag.clone());
+        }
+    }
+
+    // Rewrite the index atomically so a crash can't lose it
+    let path = index_path()?;
+    let mut content = String::new();
+    for entry in &entries {
+        content += &serde_json::to_string(entry).map_err(|e| Error::History(e.to_string()))?;
+        content.push('\n');
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    std::fs::write(&tmp, content)
+        .and_then(|()| std::fs::rename(&tmp, &path))
+        .map_err(|e| Error::History(format!("{}: {}", path.display(), e)))?;
+    Ok(true)
+}
+
+/// Print recordings as a "started  duration  size  file  tags" table
+pub fn print(entries: &[Entry]) {
+    let mut table = Table::new(&[
+        Align::Left,
+        Align::Right,
+        Align::Right,
+        Align::Left,
+        Align::Left,
+    ]);
+    table.header(&[
+        t!("list-started"),
+        t!("list-duration"),
+        t!("list-size"),
+        t!("list-file"),
+        t!("list-tags"),
+    ]);
+    for entry in entries {
+        let started = entry
+            .started_at()
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let duration = crate::format::duration(Duration::from_secs_f64(entry.duration.max(0.0)));
+        let size = entry.size.map_or("-".to_string(), crate::format::bytes);
+        table.row([
+            (started, table::DIM),
+            (duration, Style::new()),
+            (size, Style::new()),
+            (entry.file.clone(), Style::new()),
+            (entry.tags.join(", "), table::TAGS),
+        ]);
+    }
+    table.print();
+}
@@ -0,0 +1,164 @@
+//! Translated user-facing strings.
+//!
+//! Messages live in Fluent (`.ftl`) files under `locales/`, formatted with
+//! fluent-bundle, so translations can use the whole syntax, e.g. selectors
+//! for plural forms.
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+/// Translations built into the binary, by language tag
+const BUNDLED: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// Look up a translated message, e.g. `t!("saved-to", path = file)`
+macro_rules! t {
+    ($id:expr) => {
+        $crate::i18n::message($id, &[])
+    };
+    ($id:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::message($id, &[$((stringify!($name), $value.to_string())),+])
+    };
+}
+pub(crate) use t;
+
+/// Format the message `id` in the user's language, falling back to English
+/// and then to the id itself
+pub fn message(id: &str, args: &[(&str, String)]) -> String {
+    let bundle = bundle();
+    let Some(pattern) = bundle.get_message(id).and_then(|m| m.value()) else {
+        tracing::warn!("Missing translation for {}", id);
+        return id.to_string();
+    };
+
+    // Numbers are passed as text, and become numbers again for selectors
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, FluentValue::try_number(value));
+    }
+    let mut errors = Vec::new();
+    let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+    for e in errors {
+        tracing::warn!("Failed to format {}: {}", id, e);
+    }
+    text.into_owned()
+}
+
+/// The English messages, with those of the user's language over them
+fn bundle() -> &'static FluentBundle<FluentResource> {
+    static BUNDLE: OnceLock<FluentBundle<FluentResource>> = OnceLock::new();
+    BUNDLE.get_or_init(|| {
+        let english: LanguageIdentifier = "en".parse().expect("valid language tag");
+        let preferred = preferred_languages()
+            .into_iter()
+            .find_map(|lang| Some((load(&lang)?, lang)));
+        let mut locales: Vec<LanguageIdentifier> = preferred
+            .iter()
+            .filter_map(|(_, lang)| lang.parse().ok())
+            .collect();
+        locales.push(english);
+
+        let mut bundle = FluentBundle::new_concurrent(locales);
+        // Bidi isolation marks around arguments show up as stray characters
+        // in terminals and notification bubbles
+        bundle.set_use_isolating(false);
+        bundle.add_resource_overriding(resource("built-in en.ftl", BUNDLED[0].1.to_string()));
+        if let Some(((origin, source), _)) = preferred {
+            bundle.add_resource_overriding(resource(&origin, source));
+        }
+        bundle
+    })
+}
+
+/// Parse a translation file. Messages with syntax errors are skipped and
+/// logged, with the line they are on, and the rest are kept.
+fn resource(origin: &str, source: String) -> FluentResource {
+    match FluentResource::try_new(source) {
+        Ok(resource) => resource,
+        Err((resource, errors)) => {
+            for e in errors {
+                let line = resource.source()[..e.pos.start].lines().count().max(1);
+                tracing::warn!("Skipping a message in {} at line {}: {}", origin, line, e);
+            }
+            resource
+        }
+    }
+}
+
+/// Translations for a language and where they come from, preferring the
+/// user's own file in `$XDG_DATA_HOME/niri-screen-recorder/locales/<lang>.ftl`
+fn load(lang: &str) -> Option<(String, String)> {
+    if let Some(user) = dirs::data_dir().map(|dir| {
+        dir.join("niri-screen-recorder/locales")
+            .join(format!("{}.ftl", lang))
+    }) && let Ok(source) = std::fs::read_to_string(&user)
+    {
+        return Some((user.display().to_string(), source));
+    }
+
+    BUNDLED
+        .iter()
+        .find(|(tag, _)| *tag == lang)
+        .map(|(tag, source)| (format!("built-in {}.ftl", tag), source.to_string()))
+}
+
+/// Languages to try, most preferred first, from the same variables gettext
+/// reads. "de_DE.UTF-8" yields "de-DE" and then "de".
+fn preferred_languages() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()));
+
+    // Like gettext, LANGUAGE is ignored when the locale is plain C
+    let mut locales: Vec<String> = Vec::new();
+    if locale.as_deref().is_some_and(|l| l != "C" && l != "POSIX")
+        && let Ok(language) = std::env::var("LANGUAGE")
+    {
+        locales.extend(language.split(':').map(str::to_string));
+    }
+    locales.extend(locale);
+
+    let mut languages = Vec::new();
+    for locale in locales {
+        let tag = locale
+            .split(['.', '@'])
+            .next()
+            .unwrap_or_default()
+            .replace('_', "-");
+        if tag.is_empty() || tag == "C" || tag == "POSIX" {
+            continue;
+        }
+        let base = tag.split('-').next().unwrap_or_default().to_string();
+        for candidate in [tag, base] {
+            if !languages.contains(&candidate) {
+                languages.push(candidate);
+            }
+        }
+    }
+    languages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_translations_parse() {
+        for (tag, source) in BUNDLED {
+            if let Err((_, errors)) = FluentResource::try_new(source.to_string()) {
+                panic!("{}.ftl: {:?}", tag, errors);
+            }
+        }
+    }
+
+    #[test]
+    fn arguments_are_filled_in_without_isolation_marks() {
+        let text = message("cli-uploading", &[("percent", "42".to_string())]);
+        assert!(text.contains("42"), "{}", text);
+        assert!(!text.contains('\u{2068}'), "{:?}", text);
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::watch;
+use wayland_client::backend::WaylandError;
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::{wl_registry::WlRegistry, wl_seat::WlSeat};
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle, delegate_noop};
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Tracks whether the user has been away from the keyboard and mouse for a
+/// while, through the compositor's ext-idle-notify protocol. Stopped when
+/// dropped.
+pub struct Monitor {
+    stop: Arc<AtomicBool>,
+    idle: watch::Receiver<bool>,
+}
+
+impl Monitor {
+    /// Report idle after `timeout` without input.
+    /// Best-effort: problems are logged and nothing is ever reported idle.
+    pub fn start(timeout: Duration) -> Option<Self> {
+        let (tx, idle) = watch::channel(false);
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        std::thread::Builder::new()
+            .name("idle".to_string())
+            .spawn(move || {
+                if let Err(e) = run(timeout, tx, &flag) {
+                    tracing::warn!("Idle detection unavailable: {}", e);
+                }
+            })
+            .inspect_err(|e| tracing::warn!("Failed to start idle detection: {}", e))
+            .ok()?;
+
+        Some(Self { stop, idle })
+    }
+
+    /// Whether the user is idle, updated as that changes. The sender goes
+    /// away once the monitor is dropped.
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.idle.clone()
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+struct State {
+    tx: watch::Sender<bool>,
+}
+
+fn run(timeout: Duration, tx: watch::Sender<bool>, stop: &AtomicBool) -> Result<()> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut queue) = registry_queue_init::<State>(&conn)?;
+    let qh = queue.handle();
+
+    let seat: WlSeat = globals.bind(&qh, 1..=1, ())?;
+    let notifier: ExtIdleNotifierV1 = globals.bind(&qh, 1..=2, ())?;
+
+    // The daemon keeps the screen awake while recording, so prefer the
+    // notification that only looks at input and ignores idle inhibitors
+    let timeout_ms = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+    let notification = if notifier.version() >= 2 {
+        notifier.get_input_idle_notification(timeout_ms, &seat, &qh, ())
+    } else {
+        notifier.get_idle_notification(timeout_ms, &seat, &qh, ())
+    };
+
+    let mut state = State { tx };
+    while !stop.load(Ordering::Relaxed) {
+        queue.dispatch_pending(&mut state)?;
+        conn.flush()?;
+        if let Some(guard) = queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    notification.destroy();
+    notifier.destroy();
+    conn.flush()?;
+    Ok(())
+}
+
+impl Dispatch<ExtIdleNotificationV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let idle = match event {
+            ext_idle_notification_v1::Event::Idled => true,
+            ext_idle_notification_v1::Event::Resumed => false,
+            _ => return,
+        };
+        state.tx.send_replace(idle);
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: wayland_client::protocol::wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(State: ExtIdleNotifierV1);
+delegate_noop!(State: ignore WlSeat);
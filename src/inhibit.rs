@@ -0,0 +1,100 @@
+use zbus::zvariant::OwnedFd;
+use zbus::{Connection, proxy};
+
+use crate::logind;
+
+const WHO: &str = "niri-screen-recorder";
+const WHY: &str = "Screen recording in progress";
+
+/// DBus proxy for the freedesktop screensaver inhibition API
+#[proxy(
+    interface = "org.freedesktop.ScreenSaver",
+    default_service = "org.freedesktop.ScreenSaver",
+    default_path = "/org/freedesktop/ScreenSaver"
+)]
+trait ScreenSaver {
+    fn inhibit(&self, application_name: &str, reason: &str) -> zbus::Result<u32>;
+
+    fn un_inhibit(&self, cookie: u32) -> zbus::Result<()>;
+}
+
+/// Idle inhibitors held for the duration of a recording. Both are
+/// best-effort: whichever services are available get used.
+pub struct Inhibitor {
+    screensaver: Option<(Connection, u32)>,
+    logind_fd: Option<OwnedFd>,
+}
+
+impl Inhibitor {
+    /// Take a screensaver inhibitor and a logind idle inhibitor
+    pub async fn acquire() -> Self {
+        let screensaver = match inhibit_screensaver().await {
+            Ok(v) => Some(v),
+            Err(e) => {
+                tracing::debug!("Screensaver inhibitor unavailable: {}", e);
+                None
+            }
+        };
+
+        let logind_fd = match inhibit_logind().await {
+            Ok(fd) => Some(fd),
+            Err(e) => {
+                tracing::warn!("Failed to take logind inhibitor: {}", e);
+                None
+            }
+        };
+
+        tracing::debug!(
+            "Inhibitors acquired (screensaver: {}, logind: {})",
+            screensaver.is_some(),
+            logind_fd.is_some()
+        );
+
+        Self {
+            screensaver,
+            logind_fd,
+        }
+    }
+
+    /// Release all inhibitors
+    pub async fn release(mut self) {
+        if let Some((connection, cookie)) = self.screensaver.take() {
+            let result = async {
+                ScreenSaverProxy::new(&connection)
+                    .await?
+                    .un_inhibit(cookie)
+                    .await
+            }
+            .await;
+            if let Err(e) = result {
+                tracing::warn!("Failed to release screensaver inhibitor: {}", e);
+            }
+        }
+
+        // Closing the fd releases the logind inhibitor
+        drop(self.logind_fd.take());
+
+        tracing::debug!("Inhibitors released");
+    }
+}
+
+async fn inhibit_screensaver() -> zbus::Result<(Connection, u32)> {
+    // The inhibitor is tied to the connection, so it has to outlive the call
+    let connection = Connection::session().await?;
+    let cookie = ScreenSaverProxy::new(&connection)
+        .await?
+        .inhibit(WHO, WHY)
+        .await?;
+    Ok((connection, cookie))
+}
+
+async fn inhibit_logind() -> zbus::Result<OwnedFd> {
+    let connection = Connection::system().await?;
+    // Sleep is not blocked: a suspend asked for during a recording goes
+    // ahead, held up by the daemon's delay inhibitor until the recording
+    // has been dealt with as NIRI_SCREEN_RECORDER_ON_SUSPEND says
+    logind::ManagerProxy::new(&connection)
+        .await?
+        .inhibit("idle", WHO, WHY, "block")
+        .await
+}
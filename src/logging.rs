@@ -0,0 +1,143 @@
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use crate::error::{Error, Result};
+
+/// How many rotated daily log files to keep
+const MAX_LOG_FILES: usize = 7;
+
+/// How far before a failure the extracted log starts
+const LOG_CONTEXT: Duration = Duration::from_secs(60);
+
+/// How many lines of the log file to extract when the journal is not used
+const LOG_TAIL_LINES: usize = 200;
+
+/// Set up logging. The daemon additionally logs to the systemd journal and,
+/// if configured, to a daily-rotated file, since it usually runs without a
+/// terminal. The level comes from `--log-level`, then the config, then
+/// `RUST_LOG`, defaulting to info.
+///
+/// The returned guard must be kept alive to flush the log file on exit.
+pub fn init(cli_level: Option<&str>, daemon: bool) -> Option<WorkerGuard> {
+    let config = config::get();
+
+    let filter = match cli_level.or(config.log_level.as_deref()) {
+        Some(level) => EnvFilter::try_new(level).unwrap_or_else(|e| {
+            eprintln!("Invalid log level {:?} ({}), using info", level, e);
+            EnvFilter::new("info")
+        }),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+
+    let journald = if daemon && config.log_journal {
+        match tracing_journald::layer() {
+            Ok(layer) => Some(layer.with_syslog_identifier("niri-screen-recorder".to_string())),
+            Err(e) => {
+                eprintln!("Journald logging unavailable: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Under systemd stderr already ends up in the journal, so don't log twice
+    let stderr_is_journal = std::env::var_os("JOURNAL_STREAM").is_some();
+    let stderr = (journald.is_none() || !stderr_is_journal)
+        .then(|| tracing_subscriber::fmt::layer().with_writer(std::io::stderr));
+
+    let mut guard = None;
+    let file = match config.log_dir.as_ref().filter(|_| daemon) {
+        Some(dir) => match Builder::new()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("daemon")
+            .filename_suffix("log")
+            .max_log_files(MAX_LOG_FILES)
+            .build(dir)
+        {
+            Ok(appender) => {
+                let (writer, worker) = tracing_appender::non_blocking(appender);
+                guard = Some(worker);
+                Some(
+                    tracing_subscriber::fmt::layer()
+                        .with_ansi(false)
+                        .with_writer(writer),
+                )
+            }
+            Err(e) => {
+                eprintln!("Failed to open log directory {}: {}", dir.display(), e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(stderr)
+        .with(journald)
+        .with(file)
+        .init();
+
+    guard
+}
+
+/// Extract the daemon log leading up to a failure at `failed_at` into a file
+/// that can be opened in an editor. The log ends with the failure, so it is
+/// in view at the bottom. Uses the journal if the daemon logs there, and the
+/// newest log file otherwise.
+pub fn failure_log(failed_at: SystemTime) -> Result<PathBuf> {
+    let config = config::get();
+
+    let log = if config.log_journal {
+        let since = failed_at
+            .checked_sub(LOG_CONTEXT)
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        let output = std::process::Command::new("journalctl")
+            .args(["--user", "--no-pager", "-t", "niri-screen-recorder"])
+            .arg(format!("--since=@{}", since.as_secs()))
+            .output()
+            .map_err(|source| Error::CommandFailed {
+                program: "journalctl".to_string(),
+                source,
+            })?;
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    } else if let Some(dir) = &config.log_dir {
+        let newest = std::fs::read_dir(dir)
+            .map_err(|e| Error::LogUnavailable(e.to_string()))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("daemon"))
+            })
+            .max()
+            .ok_or_else(|| Error::LogUnavailable(format!("no log files in {}", dir.display())))?;
+        let content =
+            std::fs::read_to_string(&newest).map_err(|e| Error::LogUnavailable(e.to_string()))?;
+        let lines: Vec<&str> = content.lines().collect();
+        lines[lines.len().saturating_sub(LOG_TAIL_LINES)..].join("\n")
+    } else {
+        return Err(Error::LogUnavailable(
+            "neither the journal nor a log directory is enabled".to_string(),
+        ));
+    };
+
+    let path = dirs::cache_dir()
+        .ok_or(Error::NoHomeDirectory)?
+        .join("niri-screen-recorder/failure.log");
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| Error::LogUnavailable(e.to_string()))?;
+    }
+    std::fs::write(&path, log).map_err(|e| Error::LogUnavailable(e.to_string()))?;
+
+    Ok(path)
+}
@@ -0,0 +1,87 @@
+use zbus::zvariant::{ObjectPath, OwnedFd, OwnedObjectPath};
+use zbus::{Connection, proxy};
+
+/// DBus proxy for the logind manager
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+pub trait Manager {
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+
+    fn get_session(&self, session_id: &str) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(name = "GetSessionByPID")]
+    fn get_session_by_pid(&self, pid: u32) -> zbus::Result<OwnedObjectPath>;
+
+    fn get_user(&self, uid: u32) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(signal)]
+    fn session_removed(&self, session_id: &str, object_path: ObjectPath<'_>);
+
+    #[zbus(signal)]
+    fn prepare_for_shutdown(&self, start: bool);
+
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool);
+}
+
+/// DBus proxy for a logind session object
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1"
+)]
+pub trait Session {
+    /// Set by screen lockers while the session is locked
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+
+    /// Asks the screen locker to lock, e.g. from `loginctl lock-session`
+    #[zbus(signal)]
+    fn lock(&self);
+
+    #[zbus(signal)]
+    fn unlock(&self);
+}
+
+/// DBus proxy for a logind user object
+#[proxy(
+    interface = "org.freedesktop.login1.User",
+    default_service = "org.freedesktop.login1"
+)]
+pub trait User {
+    #[zbus(property)]
+    fn display(&self) -> zbus::Result<(String, OwnedObjectPath)>;
+}
+
+/// Find the logind session the daemon belongs to.
+/// A systemd user service is not part of any session itself, so fall back
+/// to the user's graphical ("display") session.
+pub async fn current_session(connection: &Connection) -> zbus::Result<OwnedObjectPath> {
+    let manager = ManagerProxy::new(connection).await?;
+
+    if let Ok(id) = std::env::var("XDG_SESSION_ID") {
+        return manager.get_session(&id).await;
+    }
+
+    if let Ok(path) = manager.get_session_by_pid(std::process::id()).await {
+        return Ok(path);
+    }
+
+    let user_path = manager.get_user(nix::unistd::getuid().as_raw()).await?;
+    let (_, session) = UserProxy::builder(connection)
+        .path(user_path)?
+        .build()
+        .await?
+        .display()
+        .await?;
+
+    if session.as_str() == "/" {
+        return Err(zbus::Error::Failure(
+            "User has no graphical session".to_string(),
+        ));
+    }
+
+    Ok(session)
+}
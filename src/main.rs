@@ -1,54 +1,283 @@
+mod chapters;
+mod clicks;
+mod client;
+mod clipboard;
+mod compositor;
+mod config;
+mod daemonize;
 mod dbus;
+mod dnd;
+mod error;
+mod format;
+mod gamemode;
+mod geometry;
+mod history;
+mod i18n;
+mod idle;
+mod inhibit;
+mod logging;
+mod logind;
+mod media;
+mod menu;
+mod metrics;
+mod niri;
 mod notifications;
+mod overlay;
+mod persist;
+mod postprocess;
+mod power;
+mod preview;
+mod process;
 mod recorder;
+mod retention;
+mod secrets;
+mod session;
+mod stats;
+mod systemd;
+mod table;
+mod tamper;
+mod tray;
+mod upload;
+mod webcam;
 
+use chrono::NaiveDate;
 use clap::{Parser, Subcommand};
 
+use client::{Client, Failure};
+
 #[derive(Parser)]
 #[command(name = "niri-screen-recorder")]
 #[command(about = "Screen recorder daemon for niri", long_about = None)]
 struct Cli {
+    /// Log level or filter directive (e.g. debug, niri_screen_recorder=trace)
+    #[arg(long, global = true, value_name = "LEVEL")]
+    log_level: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Start the daemon in the background
-    Daemon,
+    /// Run the daemon (in the foreground unless --daemonize is given)
+    Daemon {
+        /// Detach from the terminal and run in the background
+        #[arg(long)]
+        daemonize: bool,
+        /// Stay in the foreground (the default, as expected by systemd)
+        #[arg(long, conflicts_with = "daemonize")]
+        foreground: bool,
+    },
     /// Start a recording
-    Start,
+    Start {
+        /// Pick what to capture from a menu: a region, the focused output or
+        /// window, or the replay buffer
+        #[arg(long)]
+        menu: bool,
+        /// Write the video to stdout instead of a file, e.g. to pipe it into
+        /// ffmpeg. Only `-` is supported; the recording runs until stopped.
+        #[arg(long, value_name = "-", value_parser = ["-"], conflicts_with = "menu")]
+        output: Option<String>,
+        /// Record the window with this id (see `niri msg windows`) instead
+        /// of selecting a region
+        #[arg(long, value_name = "ID", conflicts_with = "menu")]
+        window_id: Option<u64>,
+        /// Record all outputs as one video
+        #[arg(long, conflicts_with_all = ["menu", "output", "window_id", "outputs"])]
+        all_outputs: bool,
+        /// Record these outputs, e.g. `DP-1,DP-2`, as one video
+        #[arg(
+            long,
+            value_name = "NAMES",
+            value_delimiter = ',',
+            conflicts_with_all = ["menu", "output", "window_id"]
+        )]
+        outputs: Vec<String>,
+        /// Record the same region as the last recording again
+        #[arg(
+            long,
+            conflicts_with_all = ["menu", "output", "window_id", "all_outputs", "outputs"]
+        )]
+        last_region: bool,
+    },
     /// Stop the current recording
     Stop,
     /// Toggle recording on/off
     Toggle,
+    /// Pause the current recording
+    Pause,
+    /// Resume a paused recording, starting a new chapter
+    Resume,
+    /// Bookmark the current position of the recording as a chapter
+    Mark {
+        /// What happens here, e.g. "bug reproduced"
+        label: Option<String>,
+    },
+    /// Control the replay buffer, which keeps the last seconds of the screen
+    #[command(subcommand)]
+    Replay(ReplayAction),
+    /// Cancel a start that is still waiting for region selection
+    Cancel,
     /// Show recording status
-    Status,
+    Status {
+        /// Also show daemon statistics
+        #[arg(long)]
+        stats: bool,
+        /// Keep running and print "state file elapsed" on every change
+        #[arg(long, conflicts_with = "stats")]
+        subscribe: bool,
+    },
+    /// Upload the most recent recording and print its URL
+    UploadLast,
+    /// List recordings from the history, oldest first
+    List {
+        /// Only list recordings whose name, date, output or tags contain this
+        query: Option<String>,
+        /// Only list recordings with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only list recordings made on or after this day (YYYY-MM-DD)
+        #[arg(long, value_name = "DATE")]
+        since: Option<NaiveDate>,
+        /// Only list recordings made on or before this day (YYYY-MM-DD)
+        #[arg(long, value_name = "DATE")]
+        until: Option<NaiveDate>,
+    },
+    /// Tag a recording in the history
+    Tag {
+        /// The recording to tag
+        file: String,
+        /// Tags to add
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigAction),
+}
+
+#[derive(Subcommand, Clone, Copy)]
+enum ReplayAction {
+    /// Start keeping the last seconds of the screen in memory
+    Start,
+    /// Stop the replay buffer without saving it
+    Stop,
+    /// Save the replay buffer to the output directory
+    Save,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt::init();
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print a JSON Schema describing every option
+    Schema,
+}
 
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    match cli.command {
-        Commands::Daemon => {
-            dbus::run_daemon().await?;
-        }
-        Commands::Start => {
-            dbus::call_start().await?;
-        }
-        Commands::Stop => {
-            dbus::call_stop().await?;
+    // Forking has to happen before logging and the runtime start any threads
+    let _pid_file = match cli.command {
+        Commands::Daemon { daemonize, .. } => match claim_daemon(daemonize) {
+            Ok(pid_file) => Some(pid_file),
+            Err(e) => {
+                eprintln!("{}", i18n::t!("cli-error", error = e));
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+
+    let daemon = matches!(cli.command, Commands::Daemon { .. });
+    let _log_guard = logging::init(cli.log_level.as_deref(), daemon);
+
+    // Client commands make a handful of calls and exit, often from a
+    // keybinding, so they don't wait for a pool of worker threads to start
+    let runtime = if daemon {
+        tokio::runtime::Runtime::new()?
+    } else {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+    };
+    let result = runtime.block_on(run(cli.command));
+    if let Err(failure) = result {
+        let code = failure.report();
+        drop(_log_guard);
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+/// Make sure no other daemon is running, detach if requested, and write the PID file
+fn claim_daemon(daemonize: bool) -> error::Result<daemonize::PidFile> {
+    daemonize::ensure_not_running()?;
+    if daemonize {
+        daemonize::daemonize()?;
+    }
+    daemonize::PidFile::create()
+}
+
+async fn run(command: Commands) -> Result<(), Failure> {
+    match command {
+        Commands::Daemon { .. } => dbus::run_daemon().await.map_err(Failure::Local),
+        Commands::Start { menu: true, .. } => Client::start_menu().await,
+        Commands::Start {
+            last_region: true, ..
+        } => Client::connect().await?.start_last_region().await,
+        Commands::Start {
+            all_outputs: true, ..
+        } => Client::connect().await?.start_outputs(&[]).await,
+        Commands::Start { outputs, .. } if !outputs.is_empty() => {
+            Client::connect().await?.start_outputs(&outputs).await
         }
-        Commands::Toggle => {
-            dbus::call_toggle().await?;
+        Commands::Start {
+            output: Some(_),
+            window_id,
+            ..
+        } => Client::connect().await?.stream(window_id).await,
+        Commands::Start {
+            window_id: Some(id),
+            ..
+        } => Client::connect().await?.start_window(id).await,
+        Commands::Start { .. } => Client::connect().await?.start().await,
+        Commands::Stop => Client::connect().await?.stop().await,
+        Commands::Toggle => Client::connect().await?.toggle().await,
+        Commands::Pause => Client::connect().await?.pause(true).await,
+        Commands::Resume => Client::connect().await?.pause(false).await,
+        Commands::Mark { label } => Client::connect().await?.mark(label).await,
+        Commands::Replay(action) => Client::connect().await?.replay(action).await,
+        Commands::Cancel => Client::connect().await?.cancel().await,
+        Commands::Status {
+            subscribe: true, ..
+        } => Client::connect().await?.subscribe_status().await,
+        Commands::Status { stats, .. } => Client::connect().await?.status(stats).await,
+        Commands::UploadLast => Ok(upload::upload_last().await?),
+        Commands::List {
+            query,
+            tag,
+            since,
+            until,
+        } => {
+            let query = history::Query {
+                search: query,
+                tag,
+                since,
+                until,
+            };
+            let entries = history::search(&query)?;
+            if entries.is_empty() {
+                eprintln!("{}", i18n::t!("cli-no-recordings"));
+            } else {
+                history::print(&entries);
+            }
+            Ok(())
         }
-        Commands::Status => {
-            dbus::call_status().await?;
+        Commands::Tag { file, tags } => Client::connect().await?.tag(&file, tags).await,
+        Commands::Config(ConfigAction::Schema) => {
+            let schema = serde_json::to_string_pretty(&config::schema())
+                .map_err(|e| Failure::Local(Box::new(e)))?;
+            println!("{}", schema);
+            Ok(())
         }
     }
-
-    Ok(())
 }
@@ -0,0 +1,119 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::error::{Error, Result};
+
+/// What ffprobe reported about a finished recording
+pub struct MediaInfo {
+    pub duration: Duration,
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+/// Run ffprobe on a finished recording to confirm it is playable.
+/// Returns `Ok(None)` if ffprobe is not installed, so verification is best-effort.
+pub async fn probe(file_path: &str) -> Result<Option<MediaInfo>> {
+    let output = match Command::new("ffprobe")
+        .args(["-v", "error", "-print_format", "json"])
+        .args(["-show_entries", "format=duration:stream=codec_type"])
+        .arg(file_path)
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            tracing::debug!("ffprobe not found, skipping verification");
+            return Ok(None);
+        }
+        Err(source) => {
+            return Err(Error::CommandFailed {
+                program: "ffprobe".to_string(),
+                source,
+            });
+        }
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::CorruptRecording(stderr.trim().to_string()));
+    }
+
+    let parsed: ProbeOutput = serde_json::from_slice(&output.stdout)
+        .map_err(|e| Error::CorruptRecording(format!("unreadable ffprobe output: {}", e)))?;
+
+    if !parsed
+        .streams
+        .iter()
+        .any(|s| s.codec_type.as_deref() == Some("video"))
+    {
+        return Err(Error::CorruptRecording("no video stream found".to_string()));
+    }
+
+    let duration = parsed
+        .format
+        .and_then(|f| f.duration)
+        .and_then(|d| d.parse::<f64>().ok())
+        .filter(|d| d.is_finite() && *d > 0.0)
+        .ok_or_else(|| Error::CorruptRecording("no valid duration".to_string()))?;
+
+    Ok(Some(MediaInfo {
+        duration: Duration::from_secs_f64(duration),
+    }))
+}
+
+/// Grab a preview frame of a finished recording for its notification.
+/// Best-effort: returns `None` if ffmpeg is missing or fails.
+pub async fn thumbnail(file_path: &str, duration: Option<Duration>) -> Option<PathBuf> {
+    let dir = dirs::cache_dir()?.join("niri-screen-recorder/thumbnails");
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        tracing::debug!("Failed to create thumbnail directory: {}", e);
+        return None;
+    }
+
+    let stem = Path::new(file_path).file_stem()?;
+    let output = dir.join(stem).with_extension("png");
+
+    // A second in skips any black first frame, but short clips need an earlier seek
+    let seek = duration.map_or(Duration::ZERO, |d| (d / 2).min(Duration::from_secs(1)));
+
+    let status = Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-ss"])
+        .arg(format!("{:.3}", seek.as_secs_f64()))
+        .arg("-i")
+        .arg(file_path)
+        .args(["-frames:v", "1", "-vf", "scale=320:-2"])
+        .arg(&output)
+        .stdin(Stdio::null())
+        .status()
+        .await;
+
+    match status {
+        Ok(status) if status.success() => Some(output),
+        Ok(status) => {
+            tracing::debug!("ffmpeg failed to create a thumbnail ({})", status);
+            None
+        }
+        Err(e) => {
+            tracing::debug!("Failed to run ffmpeg for a thumbnail: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::i18n::t;
+
+/// Menus tried when no menu command is configured, with their dmenu mode
+const MENUS: &[(&str, &[&str])] = &[
+    ("fuzzel", &["--dmenu"]),
+    ("wofi", &["--dmenu"]),
+    ("rofi", &["-dmenu"]),
+];
+
+/// What to capture, as picked from the menu
+#[derive(Clone, Copy)]
+pub enum Mode {
+    /// A region selected with slurp
+    Region,
+    /// The focused output
+    Output,
+    /// The focused window
+    Window,
+    /// Save the replay buffer
+    Replay,
+}
+
+impl Mode {
+    const ALL: [Mode; 4] = [Mode::Region, Mode::Output, Mode::Window, Mode::Replay];
+
+    fn label(self) -> String {
+        match self {
+            Mode::Region => t!("menu-region"),
+            Mode::Output => t!("menu-output"),
+            Mode::Window => t!("menu-window"),
+            Mode::Replay => t!("menu-replay"),
+        }
+    }
+}
+
+/// The menu command: the configured one, run with `sh -c`, or the first of
+/// fuzzel, wofi and rofi that is installed
+fn command() -> Result<Command> {
+    if let Some(cmd) = &config::get().menu_cmd {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd);
+        return Ok(command);
+    }
+
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let (program, args) = MENUS
+        .iter()
+        .find(|(program, _)| std::env::split_paths(&path).any(|d| d.join(program).is_file()))
+        .ok_or(Error::NoMenu)?;
+    let mut command = Command::new(program);
+    command.args(*args);
+    Ok(command)
+}
+
+/// Show the capture modes in a dmenu-style menu. Returns `None` if the menu
+/// was dismissed.
+pub fn choose() -> Result<Option<Mode>> {
+    let mut command = command()?;
+    let program = command.get_program().to_string_lossy().to_string();
+    let failed = |source| Error::CommandFailed {
+        program: program.clone(),
+        source,
+    };
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(failed)?;
+
+    let labels: Vec<String> = Mode::ALL.iter().map(|m| m.label()).collect();
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all((labels.join("\n") + "\n").as_bytes())
+            .map_err(failed)?;
+    }
+
+    // Menus exit with an error when dismissed
+    let output = child.wait_with_output().map_err(failed)?;
+    let choice = String::from_utf8_lossy(&output.stdout);
+    let choice = choice.trim();
+    Ok(Mode::ALL
+        .into_iter()
+        .zip(&labels)
+        .find(|(_, label)| label.as_str() == choice)
+        .map(|(mode, _)| mode))
+}
@@ -0,0 +1,134 @@
+use std::fmt::Write;
+use std::future::Future;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::stats::Statistics;
+
+/// Serve the daemon's statistics at `/metrics` on `addr`, in the
+/// Prometheus text format. `statistics` is asked for fresh figures on
+/// every scrape, from the task answering it. Problems are logged and the
+/// daemon goes on without it.
+pub async fn serve<F, Fut>(addr: SocketAddr, statistics: F)
+where
+    F: Fn() -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Statistics> + Send + 'static,
+{
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::warn!("Failed to serve metrics on {}: {}", addr, e);
+            return;
+        }
+    };
+    tracing::info!("Serving metrics at http://{}/metrics", addr);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(respond(stream, statistics.clone()));
+            }
+            Err(e) => tracing::debug!("Failed to accept a metrics client: {}", e),
+        }
+    }
+}
+
+/// Answer one request. The statistics take the daemon's state lock, so
+/// they are only gathered for a request to `/metrics`.
+async fn respond<F, Fut>(mut stream: TcpStream, statistics: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Statistics>,
+{
+    let result = async {
+        let mut request = [0; 1024];
+        let read = stream.read(&mut request).await?;
+        let request = String::from_utf8_lossy(&request[..read]);
+        let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+        let (status, content_type, body) = if path == "/metrics" {
+            let body = render(&statistics().await);
+            ("200 OK", "text/plain; version=0.0.4; charset=utf-8", body)
+        } else {
+            ("404 Not Found", "text/plain", "Not found\n".to_string())
+        };
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            content_type,
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(body.as_bytes()).await?;
+        stream.shutdown().await
+    }
+    .await;
+
+    if let Err(e) = result {
+        tracing::debug!("Failed to answer a metrics request: {}", e);
+    }
+}
+
+/// The statistics in the Prometheus text exposition format
+fn render(stats: &Statistics) -> String {
+    let metrics: [(&str, &str, &str, u64); 8] = [
+        (
+            "recordings_started_total",
+            "counter",
+            "Recordings started",
+            stats.started,
+        ),
+        (
+            "recordings_failed_total",
+            "counter",
+            "Recordings that failed to start or ended because the recorder exited",
+            stats.failed,
+        ),
+        (
+            "recordings_total",
+            "counter",
+            "Recordings finished and saved",
+            stats.recordings,
+        ),
+        (
+            "bytes_written_total",
+            "counter",
+            "Bytes of recordings written to disk",
+            stats.bytes_written,
+        ),
+        (
+            "encode_fps",
+            "gauge",
+            "Frames per second currently encoded, 0 when idle",
+            stats.encode_fps.into(),
+        ),
+        (
+            "dropped_frames",
+            "gauge",
+            "Estimated frames dropped during the current recording",
+            stats.dropped_frames,
+        ),
+        (
+            "recording_seconds",
+            "gauge",
+            "Seconds recorded so far, not counting pauses, 0 when idle",
+            stats.elapsed,
+        ),
+        (
+            "uptime_seconds",
+            "gauge",
+            "Seconds since the daemon started",
+            stats.uptime,
+        ),
+    ];
+
+    let mut body = String::new();
+    for (name, kind, help, value) in metrics {
+        let name = format!("niri_screen_recorder_{}", name);
+        writeln!(body, "# HELP {} {}", name, help).unwrap();
+        writeln!(body, "# TYPE {} {}", name, kind).unwrap();
+        writeln!(body, "{} {}", name, value).unwrap();
+    }
+    body
+}
@@ -0,0 +1,412 @@
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::UnixStream;
+use tokio::sync::watch;
+
+use crate::error::{Error, Result};
+
+/// An output as reported by niri
+#[derive(Deserialize)]
+pub struct Output {
+    pub name: String,
+    /// Position and size in the global logical space, `None` if disabled
+    pub logical: Option<LogicalOutput>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct LogicalOutput {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale: Option<f64>,
+}
+
+/// What the recorder reads from niri's config.kdl
+#[derive(Default, Debug, PartialEq)]
+pub struct Settings {
+    pub cursor_theme: Option<String>,
+    pub cursor_size: Option<u32>,
+    /// Scale set for each output, by name
+    pub output_scales: Vec<(String, f64)>,
+}
+
+impl Settings {
+    fn from_config(source: &str) -> std::result::Result<Self, kdl::KdlError> {
+        fn number(value: &kdl::KdlValue) -> Option<f64> {
+            value
+                .as_float()
+                .or_else(|| value.as_integer().map(|n| n as f64))
+        }
+
+        let mut settings = Settings::default();
+        for node in kdl::KdlDocument::parse(source)?.nodes() {
+            // The first argument of the first child called `name`
+            let value = |name: &str| node.children().and_then(|c| c.get_arg(name));
+            match node.name().value() {
+                "cursor" => {
+                    settings.cursor_theme = value("xcursor-theme")
+                        .and_then(kdl::KdlValue::as_string)
+                        .map(str::to_string);
+                    settings.cursor_size = value("xcursor-size")
+                        .and_then(number)
+                        .map(|size| size as u32);
+                }
+                "output" => {
+                    let name = node.get(0).and_then(kdl::KdlValue::as_string);
+                    let scale = value("scale").and_then(number);
+                    if let (Some(name), Some(scale)) = (name, scale) {
+                        settings.output_scales.push((name.to_string(), scale));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(settings)
+    }
+
+    /// The scale configured for the output `name`, if any
+    pub fn output_scale(&self, name: &str) -> Option<f64> {
+        self.output_scales
+            .iter()
+            .find(|(output, _)| output == name)
+            .map(|(_, scale)| *scale)
+    }
+}
+
+/// niri's config, from `$NIRI_CONFIG` like niri itself, or config.kdl in
+/// the config directory. Read anew each time, since it may be edited while
+/// niri runs, and with blocking file IO. Problems are logged and yield the
+/// defaults.
+pub fn settings() -> Settings {
+    let path = std::env::var_os("NIRI_CONFIG")
+        .map(PathBuf::from)
+        .or_else(|| Some(dirs::config_dir()?.join("niri/config.kdl")));
+    let Some(source) = path.and_then(|path| std::fs::read_to_string(path).ok()) else {
+        return Settings::default();
+    };
+    Settings::from_config(&source).unwrap_or_else(|e| {
+        tracing::warn!("Failed to parse niri's config: {}", e);
+        Settings::default()
+    })
+}
+
+fn ipc_error(e: std::io::Error) -> Error {
+    Error::NiriIpc(e.to_string())
+}
+
+/// Send a request to niri's IPC socket. Returns the lines niri sends back,
+/// starting with the reply.
+async fn send(request: &str) -> Result<Lines<BufReader<UnixStream>>> {
+    let socket = std::env::var_os("NIRI_SOCKET")
+        .ok_or_else(|| Error::NiriIpc("NIRI_SOCKET is not set".to_string()))?;
+
+    let mut stream = UnixStream::connect(socket).await.map_err(ipc_error)?;
+    stream
+        .write_all(format!("{}\n", request).as_bytes())
+        .await
+        .map_err(ipc_error)?;
+
+    Ok(BufReader::new(stream).lines())
+}
+
+/// Read the reply to a request, which is {"Ok": ...} or {"Err": "message"}
+async fn reply<T: serde::de::DeserializeOwned>(
+    lines: &mut Lines<BufReader<UnixStream>>,
+) -> Result<T> {
+    #[derive(Deserialize)]
+    enum Reply<T> {
+        Ok(T),
+        Err(String),
+    }
+
+    let line = lines
+        .next_line()
+        .await
+        .map_err(ipc_error)?
+        .ok_or_else(|| Error::NiriIpc("connection closed".to_string()))?;
+    match serde_json::from_str(&line) {
+        Ok(Reply::Ok(value)) => Ok(value),
+        Ok(Reply::Err(message)) => Err(Error::NiriIpc(message)),
+        Err(e) => Err(Error::NiriIpc(format!("unexpected reply: {}", e))),
+    }
+}
+
+/// Send a request to niri's IPC socket and return its reply
+async fn request<T: serde::de::DeserializeOwned>(request: &str) -> Result<T> {
+    let mut lines = send(request).await?;
+    reply(&mut lines).await
+}
+
+/// All outputs niri knows about
+pub async fn outputs() -> Result<Vec<Output>> {
+    #[derive(Deserialize)]
+    struct Outputs {
+        #[serde(rename = "Outputs")]
+        outputs: HashMap<String, Output>,
+    }
+
+    let reply: Outputs = request("\"Outputs\"").await?;
+    Ok(reply.outputs.into_values().collect())
+}
+
+/// The output that has focus, if any
+pub async fn focused_output() -> Result<Option<Output>> {
+    #[derive(Deserialize)]
+    struct FocusedOutput {
+        #[serde(rename = "FocusedOutput")]
+        output: Option<Output>,
+    }
+
+    let reply: FocusedOutput = request("\"FocusedOutput\"").await?;
+    Ok(reply.output)
+}
+
+/// A window as reported by niri
+#[derive(Deserialize)]
+pub struct Window {
+    pub id: u64,
+    /// Workspace the window is on, `None` while it is being opened
+    pub workspace_id: Option<u64>,
+    pub title: Option<String>,
+    pub app_id: Option<String>,
+    /// Where the window is, reported by niri 25.08 and later
+    pub layout: Option<WindowLayout>,
+}
+
+/// Position and size of a window, in logical pixels
+#[derive(Deserialize)]
+pub struct WindowLayout {
+    /// Position of the window's tile relative to the workspace view,
+    /// `None` if the tile isn't visible
+    pub tile_pos_in_workspace_view: Option<(f64, f64)>,
+    /// Position of the window within its tile, e.g. past the border
+    pub window_offset_in_tile: (f64, f64),
+    pub window_size: (i32, i32),
+}
+
+/// The window that has keyboard focus, if any
+pub async fn focused_window() -> Result<Option<Window>> {
+    #[derive(Deserialize)]
+    struct FocusedWindow {
+        #[serde(rename = "FocusedWindow")]
+        window: Option<Window>,
+    }
+
+    let reply: FocusedWindow = request("\"FocusedWindow\"").await?;
+    Ok(reply.window)
+}
+
+/// Every open window
+pub async fn windows() -> Result<Vec<Window>> {
+    #[derive(Deserialize)]
+    struct Windows {
+        #[serde(rename = "Windows")]
+        windows: Vec<Window>,
+    }
+
+    let reply: Windows = request("\"Windows\"").await?;
+    Ok(reply.windows)
+}
+
+/// Every workspace, on every output
+pub async fn workspaces() -> Result<Vec<Workspace>> {
+    #[derive(Deserialize)]
+    struct Workspaces {
+        #[serde(rename = "Workspaces")]
+        workspaces: Vec<Workspace>,
+    }
+
+    let reply: Workspaces = request("\"Workspaces\"").await?;
+    Ok(reply.workspaces)
+}
+
+/// How long to wait before reconnecting to the event stream
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A workspace as reported by niri's event stream
+#[derive(Deserialize, Clone)]
+pub struct Workspace {
+    pub id: u64,
+    /// Output the workspace is on, `None` if there are no outputs
+    pub output: Option<String>,
+    /// Whether this is the workspace shown on its output
+    pub is_active: bool,
+    pub is_focused: bool,
+}
+
+/// What niri's event stream has told us so far
+#[derive(Default, Clone)]
+pub struct Snapshot {
+    /// Whether the event stream is connected and has sent the workspaces.
+    /// Everything else is stale or incomplete while it isn't.
+    pub connected: bool,
+    pub workspaces: Vec<Workspace>,
+    /// Id of the focused window, if any
+    pub focused_window: Option<u64>,
+}
+
+impl Snapshot {
+    /// Names of the outputs with workspaces, which is every connected output
+    pub fn outputs(&self) -> BTreeSet<&str> {
+        self.workspaces
+            .iter()
+            .filter_map(|w| w.output.as_deref())
+            .collect()
+    }
+
+    /// Update the snapshot with an event line. Events the daemon doesn't use
+    /// are ignored.
+    fn apply(&mut self, line: &str) {
+        // Events are externally tagged: {"EventName": {...}}
+        let Ok(event) = serde_json::from_str::<HashMap<String, serde_json::Value>>(line) else {
+            tracing::debug!("Unexpected niri event: {}", line);
+            return;
+        };
+
+        for (name, body) in event {
+            match name.as_str() {
+                "WorkspacesChanged" => {
+                    if let Some(workspaces) = body
+                        .get("workspaces")
+                        .and_then(|w| serde_json::from_value(w.clone()).ok())
+                    {
+                        self.workspaces = workspaces;
+                        self.connected = true;
+                    }
+                }
+                "WorkspaceActivated" => {
+                    let Some(id) = body.get("id").and_then(|id| id.as_u64()) else {
+                        continue;
+                    };
+                    let focused = body.get("focused").and_then(|f| f.as_bool()) == Some(true);
+                    self.activate_workspace(id, focused);
+                }
+                "WindowFocusChanged" => {
+                    self.focused_window = body.get("id").and_then(|id| id.as_u64());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn activate_workspace(&mut self, id: u64, focused: bool) {
+        let Some(output) = self
+            .workspaces
+            .iter()
+            .find(|w| w.id == id)
+            .map(|w| w.output.clone())
+        else {
+            return;
+        };
+
+        for workspace in &mut self.workspaces {
+            if workspace.output == output {
+                workspace.is_active = workspace.id == id;
+            }
+            if focused {
+                workspace.is_focused = workspace.id == id;
+            }
+        }
+    }
+}
+
+/// Follow niri's event stream in the background for as long as the daemon
+/// runs, reconnecting when niri restarts. Output hotplug shows up as
+/// workspaces moving between outputs.
+pub fn watch_events() -> watch::Receiver<Snapshot> {
+    let (tx, rx) = watch::channel(Snapshot::default());
+
+    tokio::spawn(async move {
+        if std::env::var_os("NIRI_SOCKET").is_none() {
+            tracing::debug!("NIRI_SOCKET is not set, not following niri events");
+            return;
+        }
+
+        loop {
+            if let Err(e) = follow_events(&tx).await {
+                tracing::debug!("niri event stream failed: {}", e);
+            }
+            tx.send_modify(|snapshot| snapshot.connected = false);
+            if tx.is_closed() {
+                return;
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+
+    rx
+}
+
+async fn follow_events(tx: &watch::Sender<Snapshot>) -> Result<()> {
+    let mut lines = send("\"EventStream\"").await?;
+    reply::<serde::de::IgnoredAny>(&mut lines).await?;
+    tracing::debug!("Following niri events");
+
+    let mut snapshot = Snapshot::default();
+    while let Some(line) = lines.next_line().await.map_err(ipc_error)? {
+        snapshot.apply(&line);
+        tx.send_replace(snapshot.clone());
+    }
+
+    Err(Error::NiriIpc("event stream closed".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_from_config() {
+        let settings = Settings::from_config(
+            r#"
+            /* cursor { xcursor-theme "Commented"; } */
+            cursor {
+                xcursor-theme "Adwaita"
+                xcursor-size 32
+                hide-when-typing
+            }
+            output "eDP-1" {
+                scale 1.5
+                position x=0 y=0
+            }
+            output "DP-1" {
+                mode "2560x1440"
+            }
+            /-output "HDMI-A-1" { scale 2; }
+            window-rule { match title="xcursor-theme \"Fake\""; }
+            binds {
+                Mod+Shift+R { spawn "niri-screen-recorder" "toggle"; }
+            }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            settings,
+            Settings {
+                cursor_theme: Some("Adwaita".to_string()),
+                cursor_size: Some(32),
+                output_scales: vec![("eDP-1".to_string(), 1.5)],
+            }
+        );
+        assert_eq!(settings.output_scale("DP-1"), None);
+    }
+
+    #[test]
+    fn settings_from_kdl_v1_config() {
+        // What niri configs written before KDL 2 look like
+        let settings = Settings::from_config(
+            r#"
+            cursor { xcursor-theme "Adwaita"; xcursor-size 24; }
+            output "eDP-1" { scale 2; variable-refresh-rate on-demand=true; }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings.cursor_size, Some(24));
+        assert_eq!(settings.output_scale("eDP-1"), Some(2.0));
+    }
+}
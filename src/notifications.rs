@@ -1,7 +1,23 @@
 use futures_util::StreamExt;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 use zbus::{Connection, proxy};
 
+use crate::clipboard;
+use crate::config;
+use crate::dbus;
+use crate::dnd;
+use crate::error::{Error, Result};
+use crate::format;
+use crate::history;
+use crate::i18n::t;
+use crate::logging;
+use crate::process::{self, CommandRunner};
+use crate::upload;
+
 /// DBus proxy for freedesktop notifications
 #[proxy(
     interface = "org.freedesktop.Notifications",
@@ -9,6 +25,7 @@ use zbus::{Connection, proxy};
     default_path = "/org/freedesktop/Notifications"
 )]
 trait Notifications {
+    #[allow(clippy::too_many_arguments)]
     fn notify(
         &self,
         app_name: &str,
@@ -25,26 +42,273 @@ trait Notifications {
 
     #[zbus(signal)]
     fn action_invoked(&self, id: u32, action_key: &str);
+
+    #[zbus(signal)]
+    fn notification_closed(&self, id: u32, reason: u32);
 }
 
-fn handle_action(action_key: &str, file_path: &str) {
-    match action_key {
-        "copy-path" => match arboard::Clipboard::new() {
-            Ok(mut clipboard) => {
-                if let Err(e) = clipboard.set_text(file_path) {
-                    tracing::error!("Failed to copy to clipboard: {}", e);
-                } else {
-                    tracing::info!("Copied path to clipboard: {}", file_path);
-                }
+/// The recording a notification action acts on
+struct Target {
+    file: String,
+    /// Region it was captured from
+    region: Option<String>,
+}
+
+/// An action offered on the "Recording Saved" notification
+struct Action {
+    key: &'static str,
+    /// Message id of the button label
+    label: &'static str,
+    run: fn(&Target) -> Result<()>,
+    /// What to log once the action succeeded
+    done: &'static str,
+    /// Whether the action is offered for a recording
+    shown: fn(&Target) -> bool,
+}
+
+/// Actions of the "Recording Saved" notification, in the order they are shown
+const ACTIONS: &[Action] = &[
+    Action {
+        key: "open-file",
+        label: "action-open-file",
+        run: |target| open_path(&target.file),
+        done: "Opened file",
+        shown: |_| true,
+    },
+    Action {
+        key: "open-folder",
+        label: "action-open-folder",
+        run: |target| open_folder(&target.file),
+        done: "Opened folder of",
+        shown: |_| true,
+    },
+    Action {
+        key: "copy-path",
+        label: "action-copy-path",
+        run: |target| clipboard::copy_text(&target.file),
+        done: "Copied path to clipboard",
+        shown: |_| true,
+    },
+    Action {
+        key: "copy-file",
+        label: "action-copy-file",
+        run: |target| clipboard::copy_file(&target.file),
+        done: "Copied file to clipboard",
+        shown: |_| true,
+    },
+    Action {
+        key: "delete",
+        label: "action-delete",
+        run: delete_recording,
+        done: "Moved to trash",
+        shown: |_| true,
+    },
+    Action {
+        key: "share",
+        label: "action-share",
+        run: |target| share_file(&target.file),
+        done: "Started upload of",
+        shown: |_| upload::available(),
+    },
+    Action {
+        key: "record-again",
+        label: "action-record-again",
+        run: record_again,
+        done: "Recording again the region of",
+        shown: |target| target.region.is_some(),
+    },
+];
+
+fn handle_action(action_key: &str, target: &Target) {
+    let Some(action) = ACTIONS.iter().find(|a| a.key == action_key) else {
+        tracing::warn!("Unknown action: {}", action_key);
+        return;
+    };
+
+    match (action.run)(target) {
+        Ok(()) => tracing::info!("{}: {}", action.done, target.file),
+        Err(e) => tracing::error!("Action {} failed: {}", action.key, e),
+    }
+}
+
+fn open_folder(file_path: &str) -> Result<()> {
+    let dir = Path::new(file_path)
+        .parent()
+        .ok_or_else(|| Error::FileNotFound(file_path.to_string()))?;
+    open_path(&dir.to_string_lossy())
+}
+
+/// Upload a recording in the background, then copy its URL to the clipboard
+/// and show it in a follow-up notification
+fn share_file(file_path: &str) -> Result<()> {
+    if !Path::new(file_path).exists() {
+        return Err(Error::FileNotFound(file_path.to_string()));
+    }
+
+    let file_path = file_path.to_owned();
+    tokio::spawn(async move {
+        let (progress_tx, mut progress_rx) = tokio::sync::watch::channel(0.0);
+        let upload = upload::upload(&file_path, move |fraction| {
+            progress_tx.send_replace(fraction);
+        });
+        // Follows the upload until it drops its end of the channel
+        let show_progress = async {
+            if dnd::quiet().await {
+                return None;
+            }
+            let mut progress = ProgressNotification::show(&t!("uploading"), &Bubble::default())
+                .await
+                .inspect_err(|e| tracing::debug!("Failed to show upload progress: {}", e))
+                .ok()?;
+            while progress_rx.changed().await.is_ok() {
+                let fraction = *progress_rx.borrow_and_update();
+                progress.update(fraction).await.ok();
+            }
+            Some(progress)
+        };
+        let (result, progress) = tokio::join!(upload, show_progress);
+        if let Some(progress) = progress {
+            progress.bubble.close().await;
+        }
+
+        let url = match result {
+            Ok(url) => url,
+            Err(e) => {
+                tracing::error!("Failed to upload {}: {}", file_path, e);
+                notify_error(&e.to_string()).await.ok();
+                return;
             }
-            Err(e) => tracing::error!("Failed to create clipboard: {}", e),
-        },
-        "open-file" => match open_file(file_path) {
-            Ok(()) => tracing::info!("Opened file: {}", file_path),
-            Err(e) => tracing::error!("Failed to open file: {}", e),
-        },
-        _ => tracing::warn!("Unknown action: {}", action_key),
+        };
+        tracing::info!("Uploaded {} to {}", file_path, url);
+
+        let body = match clipboard::copy_text(&url) {
+            Ok(()) => t!("upload-copied", url = url.as_str()),
+            Err(e) => {
+                tracing::warn!("Failed to copy upload URL: {}", e);
+                url.clone()
+            }
+        };
+        if let Err(e) = notify_simple("emblem-shared", &t!("upload-complete"), &body).await {
+            tracing::error!("Failed to send upload notification: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+/// Start a new recording of the same region. The daemon is called over the
+/// bus like any other client, so the start goes through the usual checks.
+fn record_again(target: &Target) -> Result<()> {
+    let region = target.region.clone().unwrap_or_default();
+    tokio::spawn(async move {
+        let result = async {
+            let connection = Connection::session().await?;
+            connection
+                .call_method(
+                    Some(dbus::BUS_NAME),
+                    dbus::OBJECT_PATH,
+                    Some(dbus::BUS_NAME),
+                    "StartRecordingRegion",
+                    &(region.as_str(),),
+                )
+                .await
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::error!("Failed to record {} again: {}", region, e);
+        }
+    });
+
+    Ok(())
+}
+
+fn delete_recording(target: &Target) -> Result<()> {
+    trash_recording(&target.file)
+}
+
+/// Move a recording to the trash, along with its metadata sidecar if any
+pub fn trash_recording(file: &str) -> Result<()> {
+    trash_file(file)?;
+    let sidecar = history::sidecar_path(file);
+    if sidecar.exists()
+        && let Err(e) = trash_file(&sidecar.to_string_lossy())
+    {
+        tracing::warn!("Failed to move {} to the trash: {}", sidecar.display(), e);
     }
+    Ok(())
+}
+
+/// Move a file to the trash, through gio if it is available and directly
+/// into the home trash otherwise
+fn trash_file(file_path: &str) -> Result<()> {
+    let path = Path::new(file_path);
+    if !path.exists() {
+        return Err(Error::FileNotFound(file_path.to_string()));
+    }
+
+    match std::process::Command::new("gio")
+        .args(["trash", "--"])
+        .arg(path)
+        .status()
+    {
+        Ok(status) if status.success() => return Ok(()),
+        Ok(status) => tracing::debug!("gio trash failed ({}), using the home trash", status),
+        Err(e) => tracing::debug!("Failed to run gio ({}), using the home trash", e),
+    }
+
+    move_to_home_trash(path).map_err(Error::Trash)
+}
+
+/// Move a file into `$XDG_DATA_HOME/Trash` as described by the freedesktop.org
+/// trash specification. Only works if the file is on the same filesystem.
+fn move_to_home_trash(path: &Path) -> std::io::Result<()> {
+    use std::io::{ErrorKind, Write};
+
+    let trash = dirs::data_dir()
+        .ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, "no data directory"))?
+        .join("Trash");
+    let files = trash.join("files");
+    let info = trash.join("info");
+    std::fs::create_dir_all(&files)?;
+    std::fs::create_dir_all(&info)?;
+
+    let path = std::path::absolute(path)?;
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|e| e.to_string_lossy());
+
+    // Claim a unique name by creating its .trashinfo file first
+    let mut n = 1;
+    let (name, mut info_file, info_path) = loop {
+        let name = match (&extension, n) {
+            (Some(ext), 1) => format!("{}.{}", stem, ext),
+            (Some(ext), n) => format!("{}.{}.{}", stem, n, ext),
+            (None, 1) => stem.to_string(),
+            (None, n) => format!("{}.{}", stem, n),
+        };
+        let info_path = info.join(format!("{}.trashinfo", name));
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => break (name, file, info_path),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    };
+
+    let written = write!(
+        info_file,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        format::percent_encode_path(&path),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    )
+    .and_then(|()| std::fs::rename(&path, files.join(&name)));
+
+    if written.is_err() {
+        std::fs::remove_file(&info_path).ok();
+    }
+    written
 }
 
 struct OpenCommand {
@@ -61,18 +325,21 @@ impl OpenCommand {
     }
 }
 
-fn open_file(file_path: &str) -> Result<(), String> {
+/// Open a file or directory with the configured opener, xdg-open, or gio
+pub fn open_path(file_path: &str) -> Result<()> {
+    open_with(&process::System, file_path)
+}
+
+fn open_with(runner: &dyn CommandRunner, file_path: &str) -> Result<()> {
     if !Path::new(file_path).exists() {
-        return Err(format!("File does not exist: {}", file_path));
+        return Err(Error::FileNotFound(file_path.to_string()));
     }
 
     let mut candidates: Vec<OpenCommand> = Vec::new();
     let mut seen = std::collections::HashSet::new();
 
-    if let Ok(custom) = std::env::var("NIRI_SCREEN_RECORDER_OPEN_CMD") {
-        if !custom.trim().is_empty() {
-            candidates.push(OpenCommand::new(custom.trim(), vec![file_path.to_string()]));
-        }
+    if let Some(custom) = &config::get().open_cmd {
+        candidates.push(OpenCommand::new(custom, vec![file_path.to_string()]));
     }
 
     for program in ["xdg-open", "gio"] {
@@ -109,126 +376,609 @@ fn open_file(file_path: &str) -> Result<(), String> {
     }
 
     for candidate in candidates {
-        match std::process::Command::new(&candidate.program)
-            .args(&candidate.args)
-            .spawn()
-        {
+        match runner.spawn(std::process::Command::new(&candidate.program).args(&candidate.args)) {
             Ok(_) => return Ok(()),
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
-            Err(e) => {
-                return Err(format!(
-                    "Failed to run {}: {}",
-                    candidate.program.to_string_lossy(),
-                    e
-                ));
+            Err(source) => {
+                return Err(Error::CommandFailed {
+                    program: candidate.program.to_string_lossy().to_string(),
+                    source,
+                });
             }
         }
     }
 
-    Err("Could not find a file opener (tried xdg-open and gio)".to_string())
+    Err(Error::NoFileOpener)
+}
+
+/// The single notification a recording session shows. Each notification of
+/// the session replaces the previous one through `replaces_id`, so the
+/// recording, confirmation, progress and saved notifications share one
+/// bubble instead of piling up.
+#[derive(Clone, Default)]
+pub struct Bubble(Arc<AtomicU32>);
+
+impl Bubble {
+    /// Id of the notification currently shown, or 0 if there is none
+    fn id(&self) -> u32 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, id: u32) {
+        self.0.store(id, Ordering::Relaxed);
+    }
+
+    /// Dismiss the notification currently shown, if any
+    pub async fn close(&self) {
+        let id = self.0.swap(0, Ordering::Relaxed);
+        if id == 0 {
+            return;
+        }
+        let result = match Connection::session().await {
+            Ok(connection) => match NotificationsProxy::new(&connection).await {
+                Ok(proxy) => proxy.close_notification(id).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::debug!("Failed to close notification {}: {}", id, e);
+        }
+    }
+}
+
+/// A finished recording, as announced to the user
+pub struct SavedRecording<'a> {
+    pub file: &'a str,
+    pub duration: Duration,
+    /// File size in bytes, if it could be read
+    pub size: Option<u64>,
+    /// Preview frame to show in the notification
+    pub thumbnail: Option<&'a Path>,
+    /// Region it was captured from, for "Record Again"
+    pub region: Option<&'a str>,
+}
+
+impl SavedRecording<'_> {
+    /// Summary line such as "1m 32s \u{2014} 48 MB"
+    fn details(&self) -> String {
+        match self.size {
+            Some(size) => format!(
+                "{} \u{2014} {}",
+                format::duration(self.duration),
+                format::bytes(size)
+            ),
+            None => format::duration(self.duration),
+        }
+    }
+
+    /// Details followed by where the file was saved
+    fn body(&self) -> String {
+        format!("{}\n{}", self.details(), t!("saved-to", path = self.file))
+    }
+
+    fn hints(&self) -> std::collections::HashMap<&'static str, zbus::zvariant::Value<'static>> {
+        let mut hints = std::collections::HashMap::new();
+        if let Some(thumbnail) = self.thumbnail {
+            hints.insert(
+                "image-path",
+                zbus::zvariant::Value::from(thumbnail.to_string_lossy().into_owned()),
+            );
+        }
+        hints
+    }
 }
 
 /// Show a notification that recording stopped with action buttons
-pub async fn notify_recording_stopped(
-    file_path: &str,
-    tokio_handle: &tokio::runtime::Handle,
-) -> Result<(), String> {
+pub async fn notify_recording_stopped(saved: &SavedRecording<'_>, bubble: &Bubble) -> Result<()> {
+    notify_saved(&t!("recording-saved"), saved, bubble).await
+}
+
+/// Show a notification for a saved file with action buttons
+pub async fn notify_saved(
+    summary: &str,
+    saved: &SavedRecording<'_>,
+    bubble: &Bubble,
+) -> Result<()> {
+    let result = send_saved(summary, saved, bubble).await;
+    or_fallback(result, "normal", "video-x-generic", summary, &saved.body()).await
+}
+
+async fn send_saved(summary: &str, saved: &SavedRecording<'_>, bubble: &Bubble) -> Result<()> {
+    if dnd::quiet().await {
+        tracing::info!(
+            "Do not disturb, not showing \"{}\" for {}",
+            summary,
+            saved.file
+        );
+        bubble.close().await;
+        return Ok(());
+    }
+
     let connection = Connection::session()
         .await
-        .map_err(|e| format!("Failed to connect to DBus: {}", e))?;
+        .map_err(Error::DbusUnavailable)?;
 
-    let proxy = NotificationsProxy::new(&connection)
-        .await
-        .map_err(|e| format!("Failed to create notification proxy: {}", e))?;
+    let proxy = NotificationsProxy::new(&connection).await?;
+
+    let target = Target {
+        file: saved.file.to_owned(),
+        region: saved.region.map(str::to_string),
+    };
+    let shown: Vec<&Action> = ACTIONS.iter().filter(|a| (a.shown)(&target)).collect();
+    let labels: Vec<String> = shown.iter().map(|a| t!(a.label)).collect();
+    let actions: Vec<&str> = shown
+        .iter()
+        .zip(&labels)
+        .flat_map(|(a, label)| [a.key, label.as_str()])
+        .collect();
 
-    let actions: Vec<&str> = vec!["copy-path", "Copy Path", "open-file", "Open File"];
+    let body = saved.body();
+    let hints = saved.hints();
 
     let notification_id = proxy
         .notify(
             "niri-screen-recorder",
-            0,
+            bubble.id(),
             "video-x-generic",
-            "Recording Saved",
-            &format!("Saved to: {}", file_path),
+            summary,
+            &body,
             &actions,
-            std::collections::HashMap::new(),
+            hints,
             5000,
         )
-        .await
-        .map_err(|e| format!("Failed to send notification: {}", e))?;
+        .await?;
+    bubble.set(notification_id);
 
     tracing::info!("Notification sent with id: {}", notification_id);
 
-    let file_path = file_path.to_owned();
-    tokio_handle.spawn(async move {
-        if let Err(e) = listen_for_action(notification_id, &file_path).await {
-            tracing::error!("Error listening for notification action: {}", e);
-        }
-    });
+    on_action(notification_id, move |key| handle_action(key, &target));
 
     Ok(())
 }
 
-async fn listen_for_action(notification_id: u32, file_path: &str) -> Result<(), String> {
+/// Notification showing the progress of a post-processing job
+pub struct ProgressNotification {
+    proxy: NotificationsProxy<'static>,
+    bubble: Bubble,
+    summary: String,
+    percent: Option<u8>,
+}
+
+impl ProgressNotification {
+    pub async fn show(summary: &str, bubble: &Bubble) -> Result<Self> {
+        let connection = Connection::session()
+            .await
+            .map_err(Error::DbusUnavailable)?;
+
+        let mut notification = Self {
+            proxy: NotificationsProxy::new(&connection).await?,
+            bubble: bubble.clone(),
+            summary: summary.to_string(),
+            percent: None,
+        };
+        notification.update(0.0).await?;
+        Ok(notification)
+    }
+
+    /// Show `fraction` (0 to 1) of the job as done. Only whole percent steps
+    /// are sent, to keep the notification server from flickering.
+    pub async fn update(&mut self, fraction: f64) -> Result<()> {
+        let percent = (fraction.clamp(0.0, 1.0) * 100.0) as u8;
+        if self.percent == Some(percent) {
+            return Ok(());
+        }
+        self.percent = Some(percent);
+
+        // "value" draws a progress bar on servers that support it
+        let mut hints = std::collections::HashMap::new();
+        hints.insert("value", zbus::zvariant::Value::from(i32::from(percent)));
+
+        let id = self
+            .proxy
+            .notify(
+                "niri-screen-recorder",
+                self.bubble.id(),
+                "system-run",
+                &self.summary,
+                &format!("{}%", percent),
+                &[],
+                hints,
+                0,
+            )
+            .await?;
+        self.bubble.set(id);
+
+        Ok(())
+    }
+}
+
+/// Ask whether to keep a recording made in confirm mode. Only Discard
+/// discards it, so dismissing the notification never loses a take. In do
+/// not disturb mode the recording is kept without asking.
+pub async fn confirm_keep(recording: &SavedRecording<'_>, bubble: &Bubble) -> Result<bool> {
+    if dnd::quiet().await {
+        tracing::info!("Do not disturb, keeping {} without asking", recording.file);
+        bubble.close().await;
+        return Ok(true);
+    }
+
     let connection = Connection::session()
         .await
-        .map_err(|e| format!("Failed to connect to DBus: {}", e))?;
+        .map_err(Error::DbusUnavailable)?;
 
-    let proxy = NotificationsProxy::new(&connection)
-        .await
-        .map_err(|e| format!("Failed to create notification proxy: {}", e))?;
+    let proxy = NotificationsProxy::new(&connection).await?;
 
-    let mut stream = proxy
-        .receive_action_invoked()
-        .await
-        .map_err(|e| format!("Failed to listen for ActionInvoked: {}", e))?;
+    let mut actions = proxy.receive_action_invoked().await?;
+    let mut closed = proxy.receive_notification_closed().await?;
+
+    let id = proxy
+        .notify(
+            "niri-screen-recorder",
+            bubble.id(),
+            "video-x-generic",
+            &t!("keep-recording"),
+            &recording.details(),
+            &["keep", &t!("action-keep"), "discard", &t!("action-discard")],
+            recording.hints(),
+            0, // wait for an answer
+        )
+        .await?;
+    bubble.set(id);
 
-    let timeout_duration = tokio::time::Duration::from_secs(6);
     loop {
-        match tokio::time::timeout(timeout_duration, stream.next()).await {
-            Ok(Some(signal)) => {
-                let args = signal
-                    .args()
-                    .map_err(|e| format!("Failed to get signal args: {}", e))?;
-                if args.id == notification_id {
-                    handle_action(args.action_key, file_path);
-                    break;
+        tokio::select! {
+            // Servers may close the notification right after an action, so
+            // the action has to win
+            biased;
+            Some(signal) = actions.next() => {
+                if let Ok(args) = signal.args()
+                    && args.id == id
+                {
+                    return Ok(args.action_key != "discard");
                 }
             }
-            Ok(None) => break,
-            Err(_) => {
-                tracing::debug!("Notification action listener timed out");
-                break;
+            Some(signal) = closed.next() => {
+                if signal.args().is_ok_and(|args| args.id == id) {
+                    return Ok(true);
+                }
             }
+            else => return Ok(true),
         }
     }
+}
 
-    Ok(())
+/// Handles the actions of a notification that is still on screen
+type ActionHandler = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Action handlers of open notifications, by notification id
+fn action_handlers() -> &'static Mutex<HashMap<u32, ActionHandler>> {
+    static HANDLERS: OnceLock<Mutex<HashMap<u32, ActionHandler>>> = OnceLock::new();
+    HANDLERS.get_or_init(Default::default)
 }
 
-/// Show an error notification
-pub async fn notify_error(message: &str) -> Result<(), String> {
+/// Call `handler` whenever an action of notification `id` is invoked, until
+/// the notification is closed. Replaces the handler of a notification that was
+/// shown under the same id before.
+fn on_action(id: u32, handler: impl Fn(&str) + Send + Sync + 'static) {
+    action_handlers()
+        .lock()
+        .unwrap()
+        .insert(id, Arc::new(handler));
+}
+
+/// Dispatch notification actions to their handlers for as long as the daemon
+/// runs, so actions keep working while a notification sits in the history
+pub async fn dispatch_actions() -> Result<()> {
     let connection = Connection::session()
         .await
-        .map_err(|e| format!("Failed to connect to DBus: {}", e))?;
+        .map_err(Error::DbusUnavailable)?;
+
+    let proxy = NotificationsProxy::new(&connection).await?;
+
+    let mut actions = proxy.receive_action_invoked().await?;
+    let mut closed = proxy.receive_notification_closed().await?;
+
+    loop {
+        tokio::select! {
+            // Servers may close the notification right after an action, so
+            // the action has to be handled first
+            biased;
+            Some(signal) = actions.next() => {
+                let Ok(args) = signal.args() else { continue };
+                let handler = action_handlers().lock().unwrap().get(&args.id).cloned();
+                // Handlers may block, e.g. on gio moving a file to the trash
+                if let Some(handler) = handler {
+                    let key = args.action_key.to_string();
+                    tokio::task::spawn_blocking(move || handler(&key));
+                }
+            }
+            Some(signal) = closed.next() => {
+                let Ok(args) = signal.args() else { continue };
+                if action_handlers().lock().unwrap().remove(&args.id).is_some() {
+                    tracing::debug!("Notification {} closed", args.id);
+                }
+            }
+            else => return Ok(()),
+        }
+    }
+}
+
+/// Show an error notification
+///
+/// Offers a "View Log" action that opens the daemon log leading up to the
+/// failure.
+pub async fn notify_error(message: &str) -> Result<()> {
+    let result = send_error(message).await;
+    or_fallback(
+        result,
+        "critical",
+        "dialog-error",
+        &t!("error-summary"),
+        message,
+    )
+    .await
+}
 
-    let proxy = NotificationsProxy::new(&connection)
+async fn send_error(message: &str) -> Result<()> {
+    let failed_at = std::time::SystemTime::now();
+
+    let connection = Connection::session()
         .await
-        .map_err(|e| format!("Failed to create notification proxy: {}", e))?;
+        .map_err(Error::DbusUnavailable)?;
 
-    proxy
+    let proxy = NotificationsProxy::new(&connection).await?;
+
+    let notification_id = proxy
         .notify(
             "niri-screen-recorder",
             0,
             "dialog-error",
-            "Screen Recorder Error",
+            &t!("error-summary"),
             message,
-            &[], // no actions
+            &["view-log", &t!("action-view-log")],
             std::collections::HashMap::new(),
             5000,
         )
+        .await?;
+
+    on_action(notification_id, move |_| {
+        match logging::failure_log(failed_at).and_then(|log| open_path(&log.to_string_lossy())) {
+            Ok(()) => tracing::info!("Opened daemon log"),
+            Err(e) => tracing::error!("Failed to open daemon log: {}", e),
+        }
+    });
+
+    Ok(())
+}
+
+/// Show a warning notification
+pub async fn notify_warning(summary: &str, message: &str) -> Result<()> {
+    notify_simple("dialog-warning", summary, message).await
+}
+
+/// Show a notification about something the daemon did by itself
+pub async fn notify_info(icon: &str, summary: &str, message: &str) -> Result<()> {
+    notify_simple(icon, summary, message).await
+}
+
+/// Show a notification without actions, unless in do not disturb mode
+async fn notify_simple(icon: &str, summary: &str, body: &str) -> Result<()> {
+    if dnd::quiet().await {
+        tracing::info!("Do not disturb, not showing \"{}\"", summary);
+        return Ok(());
+    }
+
+    let result = send_simple(icon, summary, body).await;
+    or_fallback(result, "normal", icon, summary, body).await
+}
+
+async fn send_simple(icon: &str, summary: &str, body: &str) -> Result<()> {
+    let connection = Connection::session()
         .await
-        .map_err(|e| format!("Failed to send notification: {}", e))?;
+        .map_err(Error::DbusUnavailable)?;
+
+    let proxy = NotificationsProxy::new(&connection).await?;
 
+    proxy
+        .notify(
+            "niri-screen-recorder",
+            0,
+            icon,
+            summary,
+            body,
+            &[], // no actions
+            std::collections::HashMap::new(),
+            5000,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Whether a notification failed because no notification server is running
+fn server_missing(error: &Error) -> bool {
+    match error {
+        Error::DbusUnavailable(_) => true,
+        Error::Dbus(zbus::Error::MethodError(name, _, _)) => matches!(
+            name.as_str(),
+            "org.freedesktop.DBus.Error.ServiceUnknown"
+                | "org.freedesktop.DBus.Error.NameHasNoOwner"
+        ),
+        _ => false,
+    }
+}
+
+/// If a notification failed for lack of a notification server, try
+/// notify-send and dunstify instead, and log it as a last resort so the
+/// message still ends up in the journal
+async fn or_fallback(
+    result: Result<()>,
+    urgency: &str,
+    icon: &str,
+    summary: &str,
+    body: &str,
+) -> Result<()> {
+    match result {
+        Err(e) if server_missing(&e) => tracing::debug!("Notifications unavailable: {}", e),
+        result => return result,
+    }
+
+    for program in ["notify-send", "dunstify"] {
+        let status = tokio::process::Command::new(program)
+            .args(["-a", "niri-screen-recorder", "-u", urgency, "-i", icon])
+            .arg(summary)
+            .arg(body)
+            .stdin(std::process::Stdio::null())
+            .status()
+            .await;
+        match status {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => tracing::debug!("{} failed ({})", program, status),
+            Err(e) => tracing::debug!("Failed to run {}: {}", program, e),
+        }
+    }
+
+    tracing::warn!("No notification server for \"{}\": {}", summary, body);
     Ok(())
 }
+
+/// Resident notification shown for as long as a recording is in progress
+pub struct RecordingNotification {
+    proxy: NotificationsProxy<'static>,
+    bubble: Bubble,
+    /// What is being captured, shown above the elapsed time
+    details: String,
+}
+
+/// Presses of the Stop button on a `RecordingNotification`
+pub struct StopButton {
+    actions: ActionInvokedStream,
+    id: u32,
+}
+
+impl RecordingNotification {
+    /// Show the notification along with a handle to its Stop button
+    pub async fn show(details: String, bubble: &Bubble) -> Result<(Self, StopButton)> {
+        let connection = Connection::session()
+            .await
+            .map_err(Error::DbusUnavailable)?;
+
+        let proxy = NotificationsProxy::new(&connection).await?;
+
+        // Subscribe first so a quick click can't be missed
+        let actions = proxy.receive_action_invoked().await?;
+
+        let mut notification = Self {
+            proxy,
+            bubble: bubble.clone(),
+            details,
+        };
+        notification.update(Duration::ZERO, false).await?;
+
+        let id = notification.bubble.id();
+        Ok((notification, StopButton { actions, id }))
+    }
+
+    /// Refresh the elapsed time, replacing the notification in place
+    pub async fn update(&mut self, elapsed: Duration, paused: bool) -> Result<()> {
+        let mut hints = std::collections::HashMap::new();
+        hints.insert("resident", zbus::zvariant::Value::from(true));
+
+        let id = self
+            .proxy
+            .notify(
+                "niri-screen-recorder",
+                self.bubble.id(),
+                if paused {
+                    "media-playback-pause"
+                } else {
+                    "media-record"
+                },
+                &if paused {
+                    t!("recording-paused")
+                } else {
+                    t!("recording")
+                },
+                &format!(
+                    "{}\n{}",
+                    self.details,
+                    t!("recording-elapsed", elapsed = format::duration(elapsed))
+                ),
+                &["stop", &t!("action-stop")],
+                hints,
+                0, // never expire
+            )
+            .await?;
+        self.bubble.set(id);
+
+        Ok(())
+    }
+}
+
+impl StopButton {
+    /// Wait until Stop is pressed. Never returns if the notification server
+    /// goes away.
+    pub async fn pressed(&mut self) {
+        while let Some(signal) = self.actions.next().await {
+            if let Ok(args) = signal.args()
+                && args.id == self.id
+                && args.action_key == "stop"
+            {
+                return;
+            }
+        }
+        std::future::pending().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::fake;
+
+    fn existing_path() -> String {
+        std::env::temp_dir().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn missing_file_is_not_opened() {
+        let runner = fake::Runner::default();
+
+        let result = open_with(&runner, "/nonexistent/recording.mp4");
+
+        assert!(matches!(result, Err(Error::FileNotFound(_))));
+        assert!(runner.commands().is_empty());
+    }
+
+    #[test]
+    fn xdg_open_is_tried_first() {
+        let runner = fake::Runner::default();
+        let path = existing_path();
+
+        open_with(&runner, &path).unwrap();
+
+        assert_eq!(runner.commands(), [["xdg-open".to_string(), path]]);
+    }
+
+    #[test]
+    fn gio_is_used_without_xdg_open() {
+        let runner = fake::Runner::without(&["xdg-open"]);
+        let path = existing_path();
+
+        open_with(&runner, &path).unwrap();
+
+        assert_eq!(
+            runner.commands().last().unwrap(),
+            &["gio".to_string(), "open".to_string(), path]
+        );
+    }
+
+    #[test]
+    fn no_opener_installed() {
+        let runner = fake::Runner::without(&["xdg-open", "gio"]);
+
+        let result = open_with(&runner, &existing_path());
+        assert!(matches!(result, Err(Error::NoFileOpener)));
+    }
+}
@@ -0,0 +1,299 @@
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::os::fd::AsFd;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use wayland_client::backend::WaylandError;
+use wayland_client::globals::{GlobalListContents, registry_queue_init};
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer, wl_compositor::WlCompositor, wl_output::WlOutput, wl_region::WlRegion,
+    wl_registry::WlRegistry, wl_shm, wl_shm::WlShm, wl_shm_pool::WlShmPool, wl_surface::WlSurface,
+};
+use wayland_client::{Connection, Dispatch, QueueHandle, delegate_noop};
+use wayland_protocols::xdg::xdg_output::zv1::client::{
+    zxdg_output_manager_v1::ZxdgOutputManagerV1, zxdg_output_v1, zxdg_output_v1::ZxdgOutputV1,
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, Anchor, KeyboardInteractivity, ZwlrLayerSurfaceV1},
+};
+
+use crate::geometry::Region;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Width of the border, in logical pixels
+const THICKNESS: i32 = 2;
+
+/// Border color as little-endian ARGB8888, i.e. BGRA bytes (#e01b24)
+const COLOR: [u8; 4] = [0x24, 0x1b, 0xe0, 0xff];
+
+/// A border drawn just outside the captured region while recording, so it
+/// is obvious that capture is active and what it covers. The border itself
+/// stays out of the recording. Removed when dropped.
+pub struct Overlay {
+    stop: Arc<AtomicBool>,
+}
+
+impl Overlay {
+    /// Draw a border around `region`.
+    /// Best-effort: problems are logged and the recording goes on without it.
+    pub fn show(region: &Region) -> Option<Self> {
+        let rect = Rect {
+            x: region.x,
+            y: region.y,
+            width: region.w as i32,
+            height: region.h as i32,
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        std::thread::Builder::new()
+            .name("overlay".to_string())
+            .spawn(move || {
+                if let Err(e) = run(rect, &flag) {
+                    tracing::warn!("Recording indicator unavailable: {}", e);
+                }
+            })
+            .inspect_err(|e| tracing::warn!("Failed to start recording indicator: {}", e))
+            .ok()?;
+
+        Some(Self { stop })
+    }
+}
+
+impl Drop for Overlay {
+    fn drop(&mut self) {
+        // The thread notices within one poll interval and disconnects,
+        // which destroys its surfaces
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Rect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Rect {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// The part of `self` inside `bounds`, if any
+    fn clip(&self, bounds: &Rect) -> Option<Rect> {
+        let x = self.x.max(bounds.x);
+        let y = self.y.max(bounds.y);
+        let right = (self.x + self.width).min(bounds.x + bounds.width);
+        let bottom = (self.y + self.height).min(bounds.y + bounds.height);
+        (right > x && bottom > y).then_some(Rect {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        })
+    }
+}
+
+/// One edge of the border
+struct Strip {
+    surface: WlSurface,
+    buffer: WlBuffer,
+}
+
+#[derive(Default)]
+struct State {
+    /// Logical geometry of each output, filled in by xdg-output
+    outputs: Vec<(WlOutput, Rect)>,
+    strips: Vec<Strip>,
+    closed: bool,
+}
+
+fn run(region: Rect, stop: &AtomicBool) -> Result<()> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut queue) = registry_queue_init::<State>(&conn)?;
+    let qh = queue.handle();
+
+    let compositor: WlCompositor = globals.bind(&qh, 4..=6, ())?;
+    let shm: WlShm = globals.bind(&qh, 1..=1, ())?;
+    let layer_shell: ZwlrLayerShellV1 = globals.bind(&qh, 1..=4, ())?;
+    let xdg_output_manager: ZxdgOutputManagerV1 = globals.bind(&qh, 2..=3, ())?;
+
+    let mut state = State::default();
+    let outputs: Vec<(u32, u32)> = globals.contents().with_list(|list| {
+        list.iter()
+            .filter(|g| g.interface == "wl_output")
+            .map(|g| (g.name, g.version))
+            .collect()
+    });
+    for (index, (name, version)) in outputs.into_iter().enumerate() {
+        let output: WlOutput = globals.registry().bind(name, version.min(4), &qh, ());
+        xdg_output_manager.get_xdg_output(&output, &qh, index);
+        state.outputs.push((output, Rect::default()));
+    }
+    queue.roundtrip(&mut state)?;
+
+    let (output, bounds) = state
+        .outputs
+        .iter()
+        .find(|(_, bounds)| bounds.contains(region.x, region.y))
+        .cloned()
+        .ok_or("region is not on any output")?;
+
+    let t = THICKNESS;
+    let edges = [
+        (region.x - t, region.y - t, region.width + 2 * t, t),
+        (
+            region.x - t,
+            region.y + region.height,
+            region.width + 2 * t,
+            t,
+        ),
+        (region.x - t, region.y, t, region.height),
+        (region.x + region.width, region.y, t, region.height),
+    ];
+
+    for (x, y, width, height) in edges {
+        // Edges outside the output can't be shown, e.g. when recording a whole screen
+        let Some(edge) = (Rect {
+            x,
+            y,
+            width,
+            height,
+        })
+        .clip(&bounds) else {
+            continue;
+        };
+
+        let surface = compositor.create_surface(&qh, ());
+
+        // Let clicks through to whatever is underneath
+        let input = compositor.create_region(&qh, ());
+        surface.set_input_region(Some(&input));
+        input.destroy();
+
+        let layer = layer_shell.get_layer_surface(
+            &surface,
+            Some(&output),
+            Layer::Overlay,
+            "niri-screen-recorder-indicator".to_string(),
+            &qh,
+            state.strips.len(),
+        );
+        layer.set_anchor(Anchor::Top | Anchor::Left);
+        layer.set_margin(edge.y - bounds.y, 0, 0, edge.x - bounds.x);
+        layer.set_size(edge.width as u32, edge.height as u32);
+        // Position relative to the output edge, ignoring panels
+        layer.set_exclusive_zone(-1);
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        surface.commit();
+
+        let buffer = solid_buffer(&shm, &qh, edge.width, edge.height)?;
+        state.strips.push(Strip { surface, buffer });
+    }
+
+    while !stop.load(Ordering::Relaxed) && !state.closed {
+        queue.dispatch_pending(&mut state)?;
+        conn.flush()?;
+        if let Some(guard) = queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    Ok(())
+}
+
+/// A buffer filled with the border color
+fn solid_buffer(shm: &WlShm, qh: &QueueHandle<State>, width: i32, height: i32) -> Result<WlBuffer> {
+    let stride = width * 4;
+    let size = stride * height;
+
+    let fd = nix::sys::memfd::memfd_create(
+        "niri-screen-recorder-indicator",
+        nix::sys::memfd::MFdFlags::MFD_CLOEXEC,
+    )?;
+    let mut file = File::from(fd);
+    let pixels: Vec<u8> = COLOR.iter().copied().cycle().take(size as usize).collect();
+    file.write_all(&pixels)?;
+
+    let pool = shm.create_pool(file.as_fd(), size, qh, ());
+    let buffer = pool.create_buffer(0, width, height, stride, wl_shm::Format::Argb8888, qh, ());
+    pool.destroy();
+    Ok(buffer)
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, usize> for State {
+    fn event(
+        state: &mut Self,
+        layer: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_layer_surface_v1::Event::Configure { serial, .. } => {
+                layer.ack_configure(serial);
+                if let Some(strip) = state.strips.get(*index) {
+                    strip.surface.attach(Some(&strip.buffer), 0, 0);
+                    strip.surface.commit();
+                }
+            }
+            zwlr_layer_surface_v1::Event::Closed => state.closed = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZxdgOutputV1, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some((_, bounds)) = state.outputs.get_mut(*index) else {
+            return;
+        };
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => (bounds.x, bounds.y) = (x, y),
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                (bounds.width, bounds.height) = (width, height)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: wayland_client::protocol::wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(State: WlCompositor);
+delegate_noop!(State: WlRegion);
+delegate_noop!(State: ZwlrLayerShellV1);
+delegate_noop!(State: ZxdgOutputManagerV1);
+delegate_noop!(State: WlShmPool);
+delegate_noop!(State: ignore WlShm);
+delegate_noop!(State: ignore WlOutput);
+delegate_noop!(State: ignore WlSurface);
+delegate_noop!(State: ignore WlBuffer);
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Daemon state that survives restarts, stored as JSON under XDG_STATE_HOME
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersistentState {
+    /// Recordings started, across all daemon runs
+    pub started: u64,
+    /// Recordings that failed to start or were cut short by the recorder
+    /// exiting, across all daemon runs
+    pub failed: u64,
+    /// Recordings finished, across all daemon runs
+    pub recordings: u64,
+    /// Bytes of finished recordings, across all daemon runs
+    pub bytes_written: u64,
+    /// Region of the most recent recording, as "WxH+X+Y"
+    pub last_region: Option<String>,
+    /// Path of the most recent finished recording
+    pub last_file: Option<String>,
+    /// Whether the replay buffer was started by hand and not stopped, so
+    /// the next daemon starts it again
+    pub replay: bool,
+}
+
+fn state_path() -> Option<PathBuf> {
+    Some(
+        dirs::state_dir()?
+            .join("niri-screen-recorder")
+            .join("state.json"),
+    )
+}
+
+impl PersistentState {
+    /// Load the saved state, starting fresh if there is none or it is unreadable
+    pub fn load() -> Self {
+        let Some(path) = state_path() else {
+            return Self::default();
+        };
+
+        match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable state file {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                tracing::warn!("Failed to read state file {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Save the state, replacing the file atomically so a crash mid-write
+    /// can't leave it truncated
+    pub fn save(&self) {
+        let Some(path) = state_path() else {
+            return;
+        };
+
+        let result = (|| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let tmp = path.with_extension("json.tmp");
+            std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+            std::fs::rename(&tmp, &path)
+        })();
+
+        if let Err(e) = result {
+            tracing::warn!("Failed to save state file {}: {}", path.display(), e);
+        }
+    }
+}
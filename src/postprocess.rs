@@ -0,0 +1,188 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdout, Command};
+
+use crate::error::{Error, Result};
+use crate::i18n::t;
+
+/// A job run on a recording after it is saved
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Job {
+    /// Rewrite the container with the index up front, for streaming
+    Remux,
+    /// Convert to an animated GIF
+    Gif,
+    /// Re-encode at a lower bitrate for sharing
+    Compress,
+}
+
+impl FromStr for Job {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "remux" => Ok(Job::Remux),
+            "gif" => Ok(Job::Gif),
+            "compress" => Ok(Job::Compress),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Job {
+    /// Name of the job, as used in the config and the log
+    pub fn name(self) -> &'static str {
+        match self {
+            Job::Remux => "remux",
+            Job::Gif => "gif",
+            Job::Compress => "compress",
+        }
+    }
+
+    /// Shown while the job runs, e.g. "Converting to GIF"
+    pub fn description(self) -> String {
+        match self {
+            Job::Remux => t!("job-remux"),
+            Job::Gif => t!("job-gif"),
+            Job::Compress => t!("job-compress"),
+        }
+    }
+
+    /// Shown once the job is done
+    pub fn done(self) -> String {
+        match self {
+            Job::Remux => t!("job-remux-done"),
+            Job::Gif => t!("job-gif-done"),
+            Job::Compress => t!("job-compress-done"),
+        }
+    }
+
+    /// Where the job writes its output, next to the recording
+    fn output(self, input: &Path) -> PathBuf {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self {
+            Job::Remux => format!("{}-remuxed.mp4", stem),
+            Job::Gif => format!("{}.gif", stem),
+            Job::Compress => format!("{}-compressed.mp4", stem),
+        };
+        input.with_file_name(name)
+    }
+
+    fn ffmpeg_args(self) -> &'static [&'static str] {
+        match self {
+            Job::Remux => &["-c", "copy", "-movflags", "+faststart"],
+            Job::Gif => &[
+                "-an",
+                "-vf",
+                "fps=15,scale=640:-2:flags=lanczos,split[a][b];[a]palettegen[p];[b][p]paletteuse",
+            ],
+            Job::Compress => &[
+                "-c:v",
+                "libx264",
+                "-preset",
+                "veryfast",
+                "-crf",
+                "28",
+                "-c:a",
+                "aac",
+                "-b:a",
+                "128k",
+                "-movflags",
+                "+faststart",
+            ],
+        }
+    }
+
+    /// Start the job with ffmpeg
+    pub fn start(self, input: &str, duration: Duration) -> Result<RunningJob> {
+        let output = self.output(Path::new(input));
+        let mut args: Vec<OsString> = vec!["-i".into(), input.into()];
+        args.extend(self.ffmpeg_args().iter().map(OsString::from));
+        ffmpeg(args, output, duration)
+    }
+}
+
+/// Run ffmpeg with `args` (inputs, filters and codecs) writing to `output`,
+/// reporting progress against `duration`
+pub fn ffmpeg(args: Vec<OsString>, output: PathBuf, duration: Duration) -> Result<RunningJob> {
+    let mut child = Command::new("ffmpeg")
+        .args(["-v", "error", "-nostats", "-progress", "pipe:1", "-y"])
+        .args(args)
+        .arg(&output)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|source| Error::CommandFailed {
+            program: "ffmpeg".to_string(),
+            source,
+        })?;
+
+    let progress = child.stdout.take().map(|s| BufReader::new(s).lines());
+
+    Ok(RunningJob {
+        child,
+        progress,
+        output,
+        duration,
+    })
+}
+
+/// A post-processing job in progress
+pub struct RunningJob {
+    child: Child,
+    progress: Option<Lines<BufReader<ChildStdout>>>,
+    output: PathBuf,
+    duration: Duration,
+}
+
+impl RunningJob {
+    /// Wait for the next progress report, as the fraction (0 to 1) done.
+    /// Returns `None` once ffmpeg stops reporting.
+    pub async fn progress(&mut self) -> Option<f64> {
+        let lines = self.progress.as_mut()?;
+
+        // ffmpeg reports how far it got as "out_time_us=<microseconds>" lines
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(us) = line
+                .strip_prefix("out_time_us=")
+                .and_then(|v| v.parse::<u64>().ok())
+                && !self.duration.is_zero()
+            {
+                return Some(Duration::from_micros(us).as_secs_f64() / self.duration.as_secs_f64());
+            }
+        }
+
+        self.progress = None;
+        None
+    }
+
+    /// Wait for the job to finish. Returns the path of the file it produced.
+    pub async fn finish(self) -> Result<PathBuf> {
+        let result =
+            self.child
+                .wait_with_output()
+                .await
+                .map_err(|source| Error::CommandFailed {
+                    program: "ffmpeg".to_string(),
+                    source,
+                })?;
+
+        if !result.status.success() {
+            std::fs::remove_file(&self.output).ok();
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            return Err(Error::PostProcessFailed(format!(
+                "ffmpeg exited with {}: {}",
+                result.status,
+                stderr.trim()
+            )));
+        }
+
+        Ok(self.output)
+    }
+}
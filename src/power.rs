@@ -0,0 +1,127 @@
+use zbus::{Connection, proxy};
+
+/// DBus proxy for the UPower daemon
+#[proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+trait UPower {
+    #[zbus(property)]
+    fn on_battery(&self) -> zbus::Result<bool>;
+}
+
+/// DBus proxy for UPower's aggregate "display device" battery
+#[proxy(
+    interface = "org.freedesktop.UPower.Device",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower/devices/DisplayDevice"
+)]
+trait Device {
+    #[zbus(property)]
+    fn percentage(&self) -> zbus::Result<f64>;
+}
+
+/// Battery state as reported by UPower
+pub struct Battery {
+    upower: UPowerProxy<'static>,
+    device: DeviceProxy<'static>,
+}
+
+impl Battery {
+    pub async fn connect() -> zbus::Result<Self> {
+        let connection = Connection::system().await?;
+        Ok(Self {
+            upower: UPowerProxy::new(&connection).await?,
+            device: DeviceProxy::new(&connection).await?,
+        })
+    }
+
+    /// Whether the machine is running on battery
+    pub async fn on_battery(&self) -> bool {
+        self.upower.on_battery().await.unwrap_or(false)
+    }
+
+    /// Remaining charge in percent, if there is a battery
+    pub async fn percentage(&self) -> Option<f64> {
+        self.device.percentage().await.ok()
+    }
+}
+
+/// Whether the machine is running on battery. Machines without UPower are
+/// assumed to be on AC.
+pub async fn on_battery() -> bool {
+    match Battery::connect().await {
+        Ok(battery) => battery.on_battery().await,
+        Err(e) => {
+            tracing::debug!("UPower unavailable: {}", e);
+            false
+        }
+    }
+}
+
+/// DBus proxy for power-profiles-daemon
+#[proxy(
+    interface = "org.freedesktop.UPower.PowerProfiles",
+    default_service = "org.freedesktop.UPower.PowerProfiles",
+    default_path = "/org/freedesktop/UPower/PowerProfiles"
+)]
+trait PowerProfiles {
+    fn hold_profile(&self, profile: &str, reason: &str, application_id: &str) -> zbus::Result<u32>;
+
+    fn release_profile(&self, cookie: u32) -> zbus::Result<()>;
+}
+
+/// The performance power profile, held for the duration of a recording.
+/// power-profiles-daemon switches back to the previous profile once it is
+/// released.
+pub struct ProfileHold {
+    connection: Connection,
+    cookie: u32,
+}
+
+impl ProfileHold {
+    /// Hold the performance profile. Best-effort: without
+    /// power-profiles-daemon, the profile is left alone.
+    pub async fn acquire() -> Option<Self> {
+        let result = async {
+            // The hold is also dropped when its connection closes
+            let connection = Connection::system().await?;
+            let cookie = PowerProfilesProxy::new(&connection)
+                .await?
+                .hold_profile(
+                    "performance",
+                    "Screen recording in progress",
+                    "niri-screen-recorder",
+                )
+                .await?;
+            zbus::Result::Ok(Self { connection, cookie })
+        }
+        .await;
+
+        match result {
+            Ok(hold) => {
+                tracing::debug!("Holding the performance power profile");
+                Some(hold)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to hold the performance power profile: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Release the hold, restoring the previous profile
+    pub async fn release(self) {
+        let result = async {
+            PowerProfilesProxy::new(&self.connection)
+                .await?
+                .release_profile(self.cookie)
+                .await
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to release the performance power profile: {}", e);
+        }
+    }
+}
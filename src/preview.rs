@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::geometry::Region;
+
+/// How often a new preview frame is grabbed
+const FRAME_INTERVAL: Duration = Duration::from_millis(500);
+
+type Frame = Option<Arc<Vec<u8>>>;
+
+/// A low-bitrate MJPEG preview of the captured region, served on
+/// 127.0.0.1 so a browser on a second window can check what is being
+/// recorded. Frames are grabbed with grim, and only while someone watches.
+/// Stopped when dropped.
+pub struct Preview {
+    tasks: [JoinHandle<()>; 2],
+}
+
+impl Preview {
+    /// Serve a preview of `region` on `port`.
+    /// Best-effort: problems are logged and the recording goes on without it.
+    pub async fn start(region: &Region, port: u16) -> Option<Self> {
+        // grim takes "X,Y WxH"
+        let geometry = format!("{},{} {}x{}", region.x, region.y, region.w, region.h);
+
+        let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::warn!("Failed to serve the preview on port {}: {}", port, e);
+                return None;
+            }
+        };
+        tracing::info!("Serving a preview at http://127.0.0.1:{}/", port);
+
+        let (frames, _) = watch::channel(None);
+        let serve = tokio::spawn(serve(listener, frames.clone()));
+        let grab = tokio::spawn(grab(geometry, frames));
+        Some(Self {
+            tasks: [serve, grab],
+        })
+    }
+}
+
+impl Drop for Preview {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Grab a frame every interval while anyone is watching
+async fn grab(geometry: String, frames: watch::Sender<Frame>) {
+    let mut interval = tokio::time::interval(FRAME_INTERVAL);
+    loop {
+        interval.tick().await;
+        if frames.receiver_count() == 0 {
+            continue;
+        }
+
+        let output = tokio::process::Command::new("grim")
+            .args(["-t", "jpeg", "-q", "50", "-s", "0.5", "-g"])
+            .arg(&geometry)
+            .arg("-")
+            .kill_on_drop(true)
+            .output()
+            .await;
+        match output {
+            Ok(output) if output.status.success() => {
+                frames.send_replace(Some(Arc::new(output.stdout)));
+            }
+            Ok(output) => tracing::debug!(
+                "grim exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Err(e) => {
+                tracing::warn!("Failed to run grim, stopping the preview: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+async fn serve(listener: TcpListener, frames: watch::Sender<Frame>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                tracing::debug!("Preview client connected from {}", peer);
+                tokio::spawn(stream_frames(stream, frames.subscribe()));
+            }
+            Err(e) => tracing::debug!("Failed to accept a preview client: {}", e),
+        }
+    }
+}
+
+/// Send frames to a client as multipart/x-mixed-replace until it goes away
+/// or the preview stops
+async fn stream_frames(mut stream: TcpStream, mut frames: watch::Receiver<Frame>) {
+    // Whatever was asked for, the answer is the stream
+    let mut request = [0; 1024];
+    if stream.read(&mut request).await.is_err() {
+        return;
+    }
+
+    let result = async {
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\n\
+                  Content-Type: multipart/x-mixed-replace; boundary=frame\r\n\
+                  Cache-Control: no-store\r\n\
+                  Connection: close\r\n\r\n",
+            )
+            .await?;
+
+        while frames.changed().await.is_ok() {
+            let Some(frame) = frames.borrow_and_update().clone() else {
+                continue;
+            };
+            let header = format!(
+                "--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                frame.len()
+            );
+            stream.write_all(header.as_bytes()).await?;
+            stream.write_all(&frame).await?;
+            stream.write_all(b"\r\n").await?;
+        }
+        std::io::Result::Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        tracing::debug!("Preview client went away: {}", e);
+    }
+}
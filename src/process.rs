@@ -0,0 +1,134 @@
+use futures_util::future::BoxFuture;
+use std::io;
+use std::process::{Child, Command, Output};
+
+/// Starts external programs. Recording, region selection and opening files
+/// go through this, so tests can check the command lines and fake the
+/// programs' behavior without a GPU or Wayland session.
+pub trait CommandRunner: Send + Sync {
+    /// Start `command` and leave it to run on its own, like a file opener
+    fn spawn(&self, command: &mut Command) -> io::Result<Child>;
+
+    /// Start a program the daemon keeps track of, such as
+    /// gpu-screen-recorder, whose exit is awaited on the runtime
+    fn start(&self, command: &mut tokio::process::Command) -> io::Result<tokio::process::Child>;
+
+    /// Run `command` to completion, collecting its output. Dropping the
+    /// future stops waiting, which kills the program if it was set to be
+    /// killed on drop.
+    fn output(&self, command: tokio::process::Command) -> BoxFuture<'static, io::Result<Output>>;
+}
+
+/// Runs commands for real
+pub struct System;
+
+impl CommandRunner for System {
+    fn spawn(&self, command: &mut Command) -> io::Result<Child> {
+        command.spawn()
+    }
+
+    fn start(&self, command: &mut tokio::process::Command) -> io::Result<tokio::process::Child> {
+        command.spawn()
+    }
+
+    fn output(
+        &self,
+        mut command: tokio::process::Command,
+    ) -> BoxFuture<'static, io::Result<Output>> {
+        Box::pin(async move { command.output().await })
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::Path;
+    use std::process::ExitStatus;
+    use std::sync::Mutex;
+
+    /// Records the command lines it is asked to run and answers with canned
+    /// results instead of running them
+    #[derive(Default)]
+    pub struct Runner {
+        /// Programs that aren't installed, by file name
+        missing: Vec<&'static str>,
+        /// Exit code and stdout `output` answers with, or `None` for a
+        /// program that never exits
+        output: Option<(i32, &'static str)>,
+        commands: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl Runner {
+        /// A runner on which `programs` aren't installed
+        pub fn without(programs: &[&'static str]) -> Self {
+            Self {
+                missing: programs.to_vec(),
+                ..Self::default()
+            }
+        }
+
+        /// A runner whose programs exit with `code` after printing `stdout`
+        pub fn answering(code: i32, stdout: &'static str) -> Self {
+            Self {
+                output: Some((code, stdout)),
+                ..Self::default()
+            }
+        }
+
+        /// Each command run so far, as the program followed by its arguments
+        pub fn commands(&self) -> Vec<Vec<String>> {
+            self.commands.lock().unwrap().clone()
+        }
+
+        /// Note the command line. Fails like spawning would if the program
+        /// is missing.
+        fn record(&self, command: &Command) -> io::Result<()> {
+            let line = std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect();
+            self.commands.lock().unwrap().push(line);
+
+            let name = Path::new(command.get_program()).file_name();
+            if self.missing.iter().any(|m| name == Some(m.as_ref())) {
+                return Err(io::Error::from(io::ErrorKind::NotFound));
+            }
+            Ok(())
+        }
+    }
+
+    impl CommandRunner for Runner {
+        fn spawn(&self, command: &mut Command) -> io::Result<Child> {
+            self.record(command)?;
+            Command::new("true").spawn()
+        }
+
+        fn start(
+            &self,
+            command: &mut tokio::process::Command,
+        ) -> io::Result<tokio::process::Child> {
+            self.record(command.as_std())?;
+            tokio::process::Command::new("true").spawn()
+        }
+
+        fn output(
+            &self,
+            command: tokio::process::Command,
+        ) -> BoxFuture<'static, io::Result<Output>> {
+            let result = self.record(command.as_std());
+            let output = self.output;
+            Box::pin(async move {
+                result?;
+                let Some((code, stdout)) = output else {
+                    return std::future::pending().await;
+                };
+                Ok(Output {
+                    status: ExitStatus::from_raw(code << 8),
+                    stdout: stdout.as_bytes().to_vec(),
+                    stderr: Vec::new(),
+                })
+            })
+        }
+    }
+}
@@ -1,121 +1,809 @@
 use chrono::Local;
 use nix::sys::signal::{Signal, kill};
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
 use nix::unistd::Pid;
-use std::path::PathBuf;
-use std::process::{Child, Command};
+use std::os::fd::OwnedFd;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::oneshot;
 
-fn ensure_screencasts_dir() -> Result<PathBuf, String> {
-    let dir = if let Ok(custom) = std::env::var("NIRI_SCREEN_RECORDER_OUTPUT_DIR") {
-        PathBuf::from(custom)
+use crate::compositor;
+use crate::config;
+use crate::error::{Error, Result};
+use crate::geometry::Region;
+use crate::process::CommandRunner;
+
+/// gpu-screen-recorder processes that may still be running, for the panic
+/// hook to stop
+static RUNNING: Mutex<Vec<Pid>> = Mutex::new(Vec::new());
+
+/// Codecs gpu-screen-recorder accepts for `-k`
+const SUPPORTED_CODECS: &[&str] = &[
+    "auto",
+    "h264",
+    "hevc",
+    "av1",
+    "vp8",
+    "vp9",
+    "hevc_hdr",
+    "av1_hdr",
+    "hevc_10bit",
+    "av1_10bit",
+];
+
+/// Containers that can be written without seeking back, as a pipe needs
+const STREAMABLE_CONTAINERS: &[&str] = &["mkv", "flv", "webm", "mpegts"];
+
+/// What stands in for the file of a recording written to a pipe, e.g. in
+/// GetCurrentFile
+const STREAM_PREFIX: &str = "pipe:";
+
+fn ensure_screencasts_dir() -> Result<PathBuf> {
+    let dir = if let Some(custom) = &config::get().output_dir {
+        custom.clone()
     } else {
         let home = dirs::video_dir()
-            .or_else(|| dirs::home_dir())
-            .ok_or("Cannot find home directory")?;
+            .or_else(dirs::home_dir)
+            .ok_or(Error::NoHomeDirectory)?;
         home.join("Screencasts")
     };
 
-    std::fs::create_dir_all(&dir)
-        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+    std::fs::create_dir_all(&dir).map_err(Error::OutputDirectory)?;
+
+    Ok(dir)
+}
+
+/// The directory recordings are saved to, created if needed
+pub fn output_dir() -> Result<PathBuf> {
+    ensure_screencasts_dir()
+}
+
+/// Where recordings wait for Keep or Discard in confirm mode
+fn pending_dir() -> Result<PathBuf> {
+    let dir = dirs::cache_dir()
+        .ok_or(Error::NoHomeDirectory)?
+        .join("niri-screen-recorder/pending");
+
+    std::fs::create_dir_all(&dir).map_err(Error::OutputDirectory)?;
 
     Ok(dir)
 }
 
-fn generate_filename() -> Result<String, String> {
+/// A recording's name while it is written, hidden and marked as partial so
+/// that file managers and sync tools leave it alone, e.g.
+/// ".screen-record-2025-01-01_12-00-00.partial.mp4". The extension stays
+/// last for ffmpeg to go by.
+fn partial_name(name: &str) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) => format!(".{}.partial.{}", stem, extension),
+        None => format!(".{}.partial", name),
+    }
+}
+
+/// The name a recording is saved under, without any partial marking
+fn final_name(name: &str) -> String {
+    let Some(hidden) = name.strip_prefix('.') else {
+        return name.to_string();
+    };
+    match hidden.rsplit_once(".partial") {
+        Some((stem, extension)) if extension.is_empty() || extension.starts_with('.') => {
+            format!("{}{}", stem, extension)
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Whether recordings are written somewhere else first, and only moved to
+/// the output directory once finished
+pub fn is_staged() -> bool {
+    let config = config::get();
+    !config.confirm && (config.partial || config.scratch_dir.is_some())
+}
+
+/// Move a finished recording, e.g. one kept in confirm mode or written to
+/// the scratch directory, to the output directory under its final name.
+/// The move is atomic: across file systems, the file is copied under its
+/// partial name first and renamed once complete. Returns its new path.
+pub fn move_to_output_dir(file: &str) -> Result<String> {
+    let source = Path::new(file);
+    let name = source.file_name().unwrap_or_default().to_string_lossy();
     let dir = ensure_screencasts_dir()?;
-    let container =
-        std::env::var("NIRI_SCREEN_RECORDER_CONTAINER").unwrap_or_else(|_| "mp4".to_string());
-    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
-    let filename = format!("screen-record-{}.{}", timestamp, container);
-    Ok(dir.join(filename).to_string_lossy().to_string())
-}
-
-fn detect_cursor_theme() -> Option<String> {
-    let config_path = dirs::config_dir()?.join("niri/config.kdl");
-    let content = std::fs::read_to_string(config_path).ok()?;
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.starts_with("xcursor-theme") {
-            return trimmed
-                .strip_prefix("xcursor-theme")
-                .and_then(|s| s.trim().strip_prefix('"'))
-                .and_then(|s| s.strip_suffix('"'))
-                .map(|s| s.to_string());
+    let dest = dir.join(final_name(&name));
+
+    if let Err(e) = std::fs::rename(source, &dest) {
+        if e.kind() != std::io::ErrorKind::CrossesDevices {
+            return Err(Error::MoveFailed(e));
+        }
+        let partial = dir.join(partial_name(&final_name(&name)));
+        let copied = std::fs::copy(source, &partial)
+            .and_then(|_| std::fs::rename(&partial, &dest))
+            .map_err(Error::MoveFailed);
+        if copied.is_err() {
+            std::fs::remove_file(&partial).ok();
         }
+        copied?;
+        std::fs::remove_file(source).map_err(Error::MoveFailed)?;
     }
-    None
+
+    Ok(dest.to_string_lossy().to_string())
 }
 
-/// Use slurp to select a screen region (blocking)
-/// Returns a string in the format "WxH+X+Y" for gpu-screen-recorder
-pub fn select_region() -> Result<String, String> {
+fn generate_filename() -> Result<String> {
+    let config = config::get();
+    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
+    let filename = format!("screen-record-{}.{}", timestamp, config.container);
+    let path = if config.confirm {
+        pending_dir()?.join(filename)
+    } else if let Some(dir) = &config.scratch_dir {
+        std::fs::create_dir_all(dir).map_err(Error::OutputDirectory)?;
+        dir.join(partial_name(&filename))
+    } else if config.partial {
+        ensure_screencasts_dir()?.join(partial_name(&filename))
+    } else {
+        ensure_screencasts_dir()?.join(filename)
+    };
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Use slurp to select a screen region.
+/// Returns an error if the selection fails, times out, or `cancel` fires first.
+pub async fn select_region(
+    runner: &dyn CommandRunner,
+    cancel: oneshot::Receiver<()>,
+) -> Result<Region> {
     let mut cmd = Command::new("slurp");
-    cmd.arg("-f").arg("%wx%h+%x+%y");
+    cmd.arg("-f").arg("%wx%h+%x+%y").kill_on_drop(true);
 
-    if std::env::var("XCURSOR_THEME").is_err() {
-        if let Some(theme) = detect_cursor_theme() {
-            cmd.env("XCURSOR_THEME", theme);
+    let (theme, size) = (
+        std::env::var("XCURSOR_THEME"),
+        std::env::var("XCURSOR_SIZE"),
+    );
+    if theme.is_err() || size.is_err() {
+        // Looking it up reads config files, and may run gsettings
+        let cursor = tokio::task::spawn_blocking(|| compositor::get().cursor())
+            .await
+            .unwrap_or_default();
+        if theme.is_err()
+            && let Some(name) = cursor.theme
+        {
+            cmd.env("XCURSOR_THEME", name);
+        }
+        if size.is_err() {
+            cmd.env("XCURSOR_SIZE", cursor.size.unwrap_or(24).to_string());
         }
-    }
-    if std::env::var("XCURSOR_SIZE").is_err() {
-        cmd.env("XCURSOR_SIZE", "24");
     }
 
-    let output = cmd
-        .output()
-        .map_err(|e| format!("Failed to run slurp: {}", e))?;
+    let timeout = config::get().select_timeout;
+    let expired = async {
+        match timeout {
+            Some(t) => tokio::time::sleep(t).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    // Dropping the output future on cancellation or timeout kills slurp
+    let output = tokio::select! {
+        output = runner.output(cmd) => output.map_err(|source| Error::CommandFailed {
+            program: "slurp".to_string(),
+            source,
+        })?,
+        _ = cancel => return Err(Error::SelectionCancelled),
+        _ = expired => {
+            return Err(Error::SelectionTimedOut(timeout.unwrap_or_default().as_secs()));
+        }
+    };
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Region selection cancelled: {}", stderr.trim()));
+        tracing::debug!("slurp exited with {}: {}", output.status, stderr.trim());
+        return Err(Error::SelectionCancelled);
     }
 
-    let region = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let region = String::from_utf8_lossy(&output.stdout);
+    let region = region.trim();
 
     if region.is_empty() {
-        return Err("No region selected".to_string());
+        return Err(Error::NoRegionSelected);
+    }
+
+    region.parse()
+}
+
+/// Whether transient scopes can be created in the user's systemd instance
+fn scope_available() -> bool {
+    let manager_running = dirs::runtime_dir().is_some_and(|d| d.join("systemd/private").exists());
+    let systemd_run_found = std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|d| d.join("systemd-run").is_file()));
+    manager_running && systemd_run_found
+}
+
+/// Command that runs gpu-screen-recorder. If possible it is wrapped in its own
+/// transient systemd scope, so the configured resource limits apply to it
+/// alone and it isn't torn down with the daemon's cgroup when the daemon
+/// restarts. `systemd-run --scope` execs the recorder in place, so the child
+/// PID is still the recorder itself.
+fn encoder_command() -> Command {
+    let config = config::get();
+
+    if !config.scope || !scope_available() {
+        return match config.nice {
+            Some(nice) => {
+                let mut cmd = Command::new("nice");
+                cmd.arg("-n")
+                    .arg(nice.to_string())
+                    .arg("gpu-screen-recorder");
+                cmd
+            }
+            None => Command::new("gpu-screen-recorder"),
+        };
+    }
+
+    let mut cmd = Command::new("systemd-run");
+    cmd.args(["--user", "--scope", "--quiet", "--collect"])
+        .arg("--description=niri-screen-recorder capture");
+    if let Some(nice) = config.nice {
+        cmd.arg(format!("--nice={}", nice));
+    }
+    if let Some(weight) = config.cpu_weight {
+        cmd.arg("-p").arg(format!("CPUWeight={}", weight));
+    }
+    if let Some(weight) = config.io_weight {
+        cmd.arg("-p").arg(format!("IOWeight={}", weight));
+    }
+    cmd.arg("--")
+        .arg("gpu-screen-recorder")
+        .stdin(Stdio::null());
+    cmd
+}
+
+/// Encoder settings for a single recording
+pub struct EncodeSettings {
+    pub fps: u32,
+    pub quality: Option<String>,
+}
+
+impl EncodeSettings {
+    /// Settings from the config, switching to the battery profile if requested
+    pub fn new(on_battery: bool) -> Self {
+        let config = config::get();
+        if on_battery {
+            Self {
+                fps: config.battery_fps.unwrap_or(config.fps),
+                quality: config.battery_quality.clone(),
+            }
+        } else {
+            Self {
+                fps: config.fps,
+                quality: None,
+            }
+        }
     }
+}
+
+/// Where gpu-screen-recorder writes the encoded video
+pub enum Destination<'a> {
+    File(&'a str),
+    /// The write end of a pipe, given to gpu-screen-recorder as its stdout
+    Pipe(OwnedFd),
+}
+
+/// Start recording `region`, to a new file in the output directory unless
+/// `pipe` is given. Returns the recorder and the file, or for a pipe a name
+/// standing in for it.
+pub fn start_recording(
+    runner: &dyn CommandRunner,
+    region: &Region,
+    settings: &EncodeSettings,
+    pipe: Option<OwnedFd>,
+) -> Result<(Child, String)> {
+    let config = config::get();
+
+    if let Some(codec) = &config.codec
+        && !SUPPORTED_CODECS.contains(&codec.as_str())
+    {
+        return Err(Error::EncoderUnsupported(codec.clone()));
+    }
+
+    let Some(pipe) = pipe else {
+        let output_file = generate_filename()?;
+        let child = spawn_recorder(runner, region, settings, Destination::File(&output_file))?;
+        return Ok((child, output_file));
+    };
+
+    let child = spawn_recorder(runner, region, settings, Destination::Pipe(pipe))?;
+    let name = format!("{}{}", STREAM_PREFIX, child.id().unwrap_or_default());
+    Ok((child, name))
+}
 
-    Ok(region)
+/// Whether `file` is the stand-in name of a recording written to a pipe
+pub fn is_stream(file: &str) -> bool {
+    file.starts_with(STREAM_PREFIX)
 }
 
-pub fn start_recording(region: &str) -> Result<(Child, String), String> {
-    let output_file = generate_filename()?;
-    let fps = std::env::var("NIRI_SCREEN_RECORDER_FPS").unwrap_or_else(|_| "60".to_string());
-    let container =
-        std::env::var("NIRI_SCREEN_RECORDER_CONTAINER").unwrap_or_else(|_| "mp4".to_string());
+/// Start gpu-screen-recorder, recording `region` to `destination`
+fn spawn_recorder(
+    runner: &dyn CommandRunner,
+    region: &Region,
+    settings: &EncodeSettings,
+    destination: Destination,
+) -> Result<Child> {
+    let config = config::get();
 
-    let mut cmd = Command::new("gpu-screen-recorder");
+    // An mp4 can't be written to a pipe, so streams fall back to mkv
+    let container = match destination {
+        Destination::Pipe(_) if !STREAMABLE_CONTAINERS.contains(&config.container.as_str()) => {
+            "mkv"
+        }
+        _ => config.container.as_str(),
+    };
+
+    let mut cmd = encoder_command();
     cmd.arg("-w")
-        .arg(region)
+        .arg(region.to_string())
         .arg("-c")
-        .arg(&container)
+        .arg(container)
         .arg("-f")
-        .arg(&fps)
-        .arg("-o")
-        .arg(&output_file);
+        .arg(settings.fps.to_string());
+    match destination {
+        Destination::File(output_file) => {
+            cmd.arg("-o").arg(output_file);
+        }
+        // Without -o, gpu-screen-recorder writes to stdout
+        Destination::Pipe(pipe) => {
+            cmd.stdout(pipe);
+        }
+    }
+
+    if let Some(codec) = &config.codec {
+        cmd.arg("-k").arg(codec);
+    }
+
+    if let Some(quality) = &settings.quality {
+        cmd.arg("-q").arg(quality);
+    }
+
+    let child = runner
+        .start(cmd.stderr(Stdio::piped()))
+        .map_err(Error::RecorderSpawnFailed)?;
+    track(&child);
+    Ok(child)
+}
+
+/// Live encoder figures parsed from gpu-screen-recorder's output
+#[derive(Default, Clone, Copy)]
+pub struct EncodeStats {
+    pub fps: u32,
+    pub dropped_frames: u64,
+}
+
+/// Follow gpu-screen-recorder's stderr: forward it to the log and track the
+/// "update fps" it reports every second. Frames missing from the target
+/// framerate are counted as dropped, which is an estimate at best.
+pub fn watch_encoder_output(child: &mut Child, target_fps: u32) -> Arc<Mutex<EncodeStats>> {
+    let stats = Arc::new(Mutex::new(EncodeStats::default()));
+    let Some(stderr) = child.stderr.take() else {
+        return stats;
+    };
+
+    let shared = stats.clone();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let fps = line
+                .strip_prefix("update fps:")
+                .and_then(|rest| rest.split(',').next())
+                .and_then(|n| n.trim().parse::<u32>().ok());
+
+            match fps {
+                Some(fps) => {
+                    tracing::trace!(target: "gpu_screen_recorder", "{}", line);
+                    let mut stats = shared.lock().unwrap();
+                    stats.fps = fps;
+                    stats.dropped_frames += u64::from(target_fps.saturating_sub(fps));
+                }
+                None => tracing::info!(target: "gpu_screen_recorder", "{}", line),
+            }
+        }
+    });
+
+    stats
+}
+
+/// The recorder's PID, or `None` once it has been reaped
+fn pid(child: &Child) -> Option<Pid> {
+    child.id().map(|id| Pid::from_raw(id as i32))
+}
 
-    if let Ok(codec) = std::env::var("NIRI_SCREEN_RECORDER_CODEC") {
-        cmd.arg("-k").arg(&codec);
+fn track(child: &Child) {
+    if let Some(pid) = pid(child) {
+        RUNNING.lock().unwrap().push(pid);
     }
+}
 
-    let child = cmd
-        .spawn()
-        .map_err(|e| format!("Failed to start gpu-screen-recorder: {}", e))?;
+fn untrack(pid: Pid) {
+    RUNNING.lock().unwrap().retain(|p| *p != pid);
+}
 
-    Ok((child, output_file))
+/// Whether the recorder has exited, without waiting for it
+pub fn exit_status(child: &mut Child) -> std::io::Result<Option<ExitStatus>> {
+    let pid = pid(child);
+    let status = child.try_wait()?;
+    if status.is_some()
+        && let Some(pid) = pid
+    {
+        untrack(pid);
+    }
+    Ok(status)
 }
 
-/// Stop the recording by sending SIGINT for clean shutdown
-pub fn stop_recording(child: &mut Child) -> Result<(), String> {
-    let pid = Pid::from_raw(child.id() as i32);
+/// Stop the recording by sending SIGINT for clean shutdown, then wait for
+/// gpu-screen-recorder to finish writing the file. If it takes longer than
+/// the stop timeout it is killed.
+pub async fn stop_recording(child: &mut Child) -> Result<()> {
+    // Already exited on its own
+    let Some(pid) = pid(child) else {
+        return Ok(());
+    };
 
     // Send SIGINT for graceful shutdown (lets gpu-screen-recorder finalize the file)
-    kill(pid, Signal::SIGINT).map_err(|e| format!("Failed to send SIGINT: {}", e))?;
+    kill(pid, Signal::SIGINT)
+        .map_err(|e| Error::RecorderStopFailed(format!("failed to send SIGINT: {}", e)))?;
+
+    let exited = match config::get().stop_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, child.wait()).await.ok(),
+        None => Some(child.wait().await),
+    };
+    if exited.is_some() {
+        untrack(pid);
+    }
+
+    match exited {
+        Some(status) => status
+            .map(|_| ())
+            .map_err(|e| Error::RecorderStopFailed(format!("failed to wait for process: {}", e))),
+        None => {
+            child.kill().await.ok();
+            untrack(pid);
+            Err(Error::RecorderStopFailed(format!(
+                "did not exit within {}s and was killed",
+                config::get().stop_timeout.unwrap_or_default().as_secs()
+            )))
+        }
+    }
+}
+
+/// Make a panic anywhere in the daemon fatal, but only once every running
+/// gpu-screen-recorder has been stopped with SIGINT and has finished its
+/// file, so a daemon bug doesn't leave corrupted recordings behind. A task
+/// that panics would otherwise die on its own and leave the daemon in an
+/// unknown state.
+pub fn install_panic_hook() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default(info);
+        tracing::error!(
+            "Daemon panicked, stopping recordings before exiting: {}",
+            info
+        );
+        stop_all_blocking();
+        std::process::abort();
+    }));
+}
+
+/// Stop every tracked recorder and wait for them to exit, up to the stop
+/// timeout. Only for the panic hook: it reaps the processes behind the
+/// runtime's back.
+fn stop_all_blocking() {
+    // The panic may have happened while the list was locked
+    let pids = match RUNNING.try_lock() {
+        Ok(running) => running.clone(),
+        Err(_) => return,
+    };
+
+    // Only signal processes that are still our running children, in case a
+    // PID was reused after its recorder was reaped
+    let alive = |pid: Pid| {
+        matches!(
+            waitpid(pid, Some(WaitPidFlag::WNOHANG)),
+            Ok(WaitStatus::StillAlive)
+        )
+    };
+    let mut stopping: Vec<Pid> = pids.into_iter().filter(|pid| alive(*pid)).collect();
+    for pid in &stopping {
+        kill(*pid, Signal::SIGINT).ok();
+    }
+
+    let deadline = config::get().stop_timeout.map(|t| Instant::now() + t);
+    while !stopping.is_empty() && deadline.is_none_or(|d| Instant::now() < d) {
+        stopping.retain(|pid| alive(*pid));
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// The gpu-screen-recorder process of a recording, waited on by a task of
+/// its own, so that nothing has to wait for it while holding the daemon's
+/// state
+pub struct Recording {
+    /// Cleared once the process is reaped, so signals can't reach a reused PID
+    pid: Arc<Mutex<Option<Pid>>>,
+    /// Asks the task to stop the process, and gets the outcome back
+    stop: oneshot::Sender<oneshot::Sender<Result<()>>>,
+}
+
+impl Recording {
+    /// Hand `child` over to a task that waits for it. The receiver gets the
+    /// exit status if the process exits on its own, and is dropped without
+    /// one if it is stopped through `stop`.
+    pub fn supervise(mut child: Child) -> (Self, oneshot::Receiver<ExitStatus>) {
+        let pid = Arc::new(Mutex::new(self::pid(&child)));
+        let (stop_tx, stop_rx) = oneshot::channel::<oneshot::Sender<Result<()>>>();
+        let (exited_tx, exited_rx) = oneshot::channel();
+
+        let shared = pid.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                status = child.wait() => {
+                    if let Some(pid) = shared.lock().unwrap().take() {
+                        untrack(pid);
+                    }
+                    match status {
+                        Ok(status) => {
+                            exited_tx.send(status).ok();
+                        }
+                        Err(e) => tracing::warn!("Failed to wait for gpu-screen-recorder: {}", e),
+                    }
+                }
+                Ok(reply) = stop_rx => {
+                    let result = stop_recording(&mut child).await;
+                    shared.lock().unwrap().take();
+                    reply.send(result).ok();
+                }
+            }
+        });
+
+        (Self { pid, stop: stop_tx }, exited_rx)
+    }
+
+    /// Pause or resume the recording. gpu-screen-recorder toggles between
+    /// the two on SIGUSR2.
+    pub fn toggle_pause(&self) -> Result<()> {
+        let pid = self
+            .pid
+            .lock()
+            .unwrap()
+            .ok_or_else(|| Error::RecorderPauseFailed("it has exited".to_string()))?;
+        kill(pid, Signal::SIGUSR2)
+            .map_err(|e| Error::RecorderPauseFailed(format!("failed to send SIGUSR2: {}", e)))
+    }
+
+    /// Stop the recording like `stop_recording`, returning once the file is
+    /// written. Does nothing if the process has already exited.
+    pub async fn stop(self) -> Result<()> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if self.stop.send(reply_tx).is_err() {
+            return Ok(());
+        }
+        reply_rx.await.unwrap_or(Ok(()))
+    }
+}
+
+/// Start gpu-screen-recorder in replay mode, keeping the last `seconds` of
+/// the screen in memory. Nothing is written until the replay is saved.
+pub fn start_replay(runner: &dyn CommandRunner, seconds: u32) -> Result<Child> {
+    let config = config::get();
+
+    let mut cmd = encoder_command();
+    cmd.arg("-w")
+        .arg("screen")
+        .arg("-c")
+        .arg(&config.container)
+        .arg("-f")
+        .arg(config.fps.to_string())
+        .arg("-r")
+        .arg(seconds.to_string())
+        .arg("-o")
+        .arg(output_dir()?);
+
+    if let Some(codec) = &config.codec {
+        cmd.arg("-k").arg(codec);
+    }
+
+    let mut child = runner
+        .start(cmd.stderr(Stdio::piped()))
+        .map_err(Error::RecorderSpawnFailed)?;
+    track(&child);
+    watch_encoder_output(&mut child, config.fps);
+
+    Ok(child)
+}
+
+/// Save the replay buffer to the output directory. gpu-screen-recorder
+/// writes it out on SIGUSR1 and keeps buffering.
+pub fn save_replay(child: &Child) -> Result<()> {
+    let pid = pid(child)
+        .ok_or_else(|| Error::ReplayFailed("gpu-screen-recorder has exited".to_string()))?;
+    kill(pid, Signal::SIGUSR1)
+        .map_err(|e| Error::ReplayFailed(format!("failed to send SIGUSR1: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::fake;
+
+    /// The arguments gpu-screen-recorder itself gets, past any nice or
+    /// systemd-run wrapper
+    fn recorder_args(command: &[String]) -> &[String] {
+        let start = command
+            .iter()
+            .position(|arg| arg == "gpu-screen-recorder")
+            .expect("gpu-screen-recorder is run");
+        &command[start + 1..]
+    }
+
+    fn has_pair(args: &[String], flag: &str, value: &str) -> bool {
+        args.windows(2).any(|w| w[0] == flag && w[1] == value)
+    }
 
-    // Wait for the process to actually exit
-    child
+    #[test]
+    fn partial_names_are_hidden_and_keep_the_extension() {
+        let name = "screen-record-2025-01-01_12-00-00.mp4";
+        let partial = partial_name(name);
+        assert_eq!(partial, ".screen-record-2025-01-01_12-00-00.partial.mp4");
+        assert_eq!(final_name(&partial), name);
+        assert_eq!(final_name(name), name);
+        // Only names marked as partial lose their dot
+        assert_eq!(final_name(".hidden.mp4"), ".hidden.mp4");
+        assert_eq!(final_name(&partial_name("noext")), "noext");
+    }
+
+    #[tokio::test]
+    async fn recorder_gets_region_framerate_and_quality() {
+        let runner = fake::Runner::default();
+        let settings = EncodeSettings {
+            fps: 30,
+            quality: Some("high".to_string()),
+        };
+
+        let region = "1280x720+10+20".parse().unwrap();
+        spawn_recorder(
+            &runner,
+            &region,
+            &settings,
+            Destination::File("/tmp/out.mp4"),
+        )
+        .unwrap()
         .wait()
-        .map_err(|e| format!("Failed to wait for process: {}", e))?;
+        .await
+        .unwrap();
+
+        let commands = runner.commands();
+        assert_eq!(commands.len(), 1);
+        let args = recorder_args(&commands[0]);
+        assert!(has_pair(args, "-w", "1280x720+10+20"));
+        assert!(has_pair(args, "-f", "30"));
+        assert!(has_pair(args, "-q", "high"));
+        assert!(has_pair(args, "-o", "/tmp/out.mp4"));
+    }
+
+    #[tokio::test]
+    async fn recorder_without_quality_leaves_it_to_gpu_screen_recorder() {
+        let runner = fake::Runner::default();
+        let settings = EncodeSettings {
+            fps: 60,
+            quality: None,
+        };
+
+        let region = "100x100+0+0".parse().unwrap();
+        spawn_recorder(
+            &runner,
+            &region,
+            &settings,
+            Destination::File("/tmp/out.mp4"),
+        )
+        .unwrap()
+        .wait()
+        .await
+        .unwrap();
+
+        let commands = runner.commands();
+        assert!(!recorder_args(&commands[0]).contains(&"-q".to_string()));
+    }
+
+    #[tokio::test]
+    async fn streams_go_to_stdout_in_a_streamable_container() {
+        let runner = fake::Runner::default();
+        let settings = EncodeSettings {
+            fps: 60,
+            quality: None,
+        };
 
-    Ok(())
+        let (_, write) = std::io::pipe().unwrap();
+        let region = "100x100+0+0".parse().unwrap();
+        spawn_recorder(&runner, &region, &settings, Destination::Pipe(write.into()))
+            .unwrap()
+            .wait()
+            .await
+            .unwrap();
+
+        let commands = runner.commands();
+        let args = recorder_args(&commands[0]);
+        assert!(!args.contains(&"-o".to_string()));
+        // The default container, mp4, needs to seek back
+        assert!(has_pair(args, "-c", "mkv"));
+    }
+
+    #[tokio::test]
+    async fn missing_recorder_is_a_spawn_failure() {
+        let runner = fake::Runner::without(&["gpu-screen-recorder", "nice", "systemd-run"]);
+        let settings = EncodeSettings {
+            fps: 60,
+            quality: None,
+        };
+
+        let region = "100x100+0+0".parse().unwrap();
+        let result = spawn_recorder(
+            &runner,
+            &region,
+            &settings,
+            Destination::File("/tmp/out.mp4"),
+        );
+        assert!(matches!(result, Err(Error::RecorderSpawnFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn selection_is_read_from_slurp() {
+        let runner = fake::Runner::answering(0, "640x480+5+6\n");
+        let (_cancel, cancelled) = oneshot::channel();
+
+        let region = select_region(&runner, cancelled).await.unwrap();
+
+        assert_eq!(region.to_string(), "640x480+5+6");
+        assert_eq!(runner.commands(), [["slurp", "-f", "%wx%h+%x+%y"]]);
+    }
+
+    #[tokio::test]
+    async fn slurp_failing_cancels_the_selection() {
+        let runner = fake::Runner::answering(1, "");
+        let (_cancel, cancelled) = oneshot::channel();
+
+        let result = select_region(&runner, cancelled).await;
+        assert!(matches!(result, Err(Error::SelectionCancelled)));
+    }
+
+    #[tokio::test]
+    async fn empty_selection_is_no_region() {
+        let runner = fake::Runner::answering(0, "\n");
+        let (_cancel, cancelled) = oneshot::channel();
+
+        let result = select_region(&runner, cancelled).await;
+        assert!(matches!(result, Err(Error::NoRegionSelected)));
+    }
+
+    #[tokio::test]
+    async fn missing_slurp_is_reported() {
+        let runner = fake::Runner::without(&["slurp"]);
+        let (_cancel, cancelled) = oneshot::channel();
+
+        let result = select_region(&runner, cancelled).await;
+        assert!(matches!(
+            result,
+            Err(Error::CommandFailed { ref program, .. }) if program == "slurp"
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancelling_stops_waiting_for_slurp() {
+        // slurp never exits on its own here
+        let runner = fake::Runner::default();
+        let (cancel, cancelled) = oneshot::channel();
+        cancel.send(()).unwrap();
+
+        let result = select_region(&runner, cancelled).await;
+        assert!(matches!(result, Err(Error::SelectionCancelled)));
+    }
 }
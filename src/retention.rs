@@ -0,0 +1,269 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use crate::config;
+use crate::format;
+use crate::i18n::t;
+use crate::notifications;
+use crate::recorder;
+
+/// Which files in the output directory the retention limits apply to
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Recordings and saved replays
+    All,
+    Recordings,
+    /// Replays saved from the replay buffer, i.e. clips
+    Replays,
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "all" => Ok(Scope::All),
+            "recordings" => Ok(Scope::Recordings),
+            "replays" | "clips" => Ok(Scope::Replays),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Scope {
+    /// Whether the file `name` is covered, going by the names recordings
+    /// and replays get. Sidecars go along with their recording instead.
+    fn covers(self, name: &str) -> bool {
+        if name.ends_with(".json") {
+            return false;
+        }
+        let recording = name.starts_with("screen-record-");
+        let replay = name.starts_with("Replay_");
+        match self {
+            Scope::All => recording || replay,
+            Scope::Recordings => recording,
+            Scope::Replays => replay,
+        }
+    }
+}
+
+/// How much may be kept, where `None` means no limit
+#[derive(Clone, Copy, Default)]
+struct Limits {
+    size: Option<u64>,
+    age: Option<Duration>,
+    count: Option<usize>,
+}
+
+impl Limits {
+    fn from_config() -> Self {
+        let config = config::get();
+        Self {
+            size: config.retention_size.map(|mib| mib * 1024 * 1024),
+            age: config
+                .retention_days
+                .map(|days| Duration::from_secs(days * 86400)),
+            count: config.retention_count.map(|count| count as usize),
+        }
+    }
+
+    fn is_set(&self) -> bool {
+        self.size.is_some() || self.age.is_some() || self.count.is_some()
+    }
+}
+
+/// A file the limits apply to
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Trash the oldest files in the output directory until the rest is within
+/// the configured limits, and say so in a notification. `keep`, the
+/// recording just saved, counts towards the limits but is never trashed.
+pub async fn enforce(keep: String) {
+    let limits = Limits::from_config();
+    if !limits.is_set() {
+        return;
+    }
+
+    let trashed = tokio::task::spawn_blocking(move || trash_expired(Path::new(&keep), limits))
+        .await
+        .unwrap_or_default();
+    if trashed.is_empty() {
+        return;
+    }
+
+    let size: u64 = trashed.iter().map(|c| c.size).sum();
+    tracing::info!(
+        "Moved {} old recordings ({}) to the trash",
+        trashed.len(),
+        format::bytes(size)
+    );
+    notifications::notify_info(
+        "user-trash",
+        &t!("retention-summary"),
+        &t!(
+            "retention-body",
+            count = trashed.len(),
+            size = format::bytes(size)
+        ),
+    )
+    .await
+    .ok();
+}
+
+/// Trash what exceeds `limits`, returning what was trashed
+fn trash_expired(keep: &Path, limits: Limits) -> Vec<Candidate> {
+    let dir = match recorder::output_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            tracing::warn!("Not applying the retention policy: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let expired = select(candidates(&dir), keep, limits, SystemTime::now());
+    expired
+        .into_iter()
+        .filter(|candidate| {
+            let file = candidate.path.to_string_lossy();
+            match notifications::trash_recording(&file) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("Failed to move {} to the trash: {}", file, e);
+                    false
+                }
+            }
+        })
+        .collect()
+}
+
+/// The files in `dir` the configured scope covers
+fn candidates(dir: &Path) -> Vec<Candidate> {
+    let scope = config::get().retention_scope;
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| scope.covers(&entry.file_name().to_string_lossy()))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some(Candidate {
+                path: entry.path(),
+                size: metadata.len(),
+                modified: metadata.modified().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Pick the files to remove, oldest first, until the rest is within
+/// `limits`. `keep` counts towards them but is never picked.
+fn select(
+    mut files: Vec<Candidate>,
+    keep: &Path,
+    limits: Limits,
+    now: SystemTime,
+) -> Vec<Candidate> {
+    files.sort_by_key(|c| c.modified);
+    let mut total: u64 = files.iter().map(|c| c.size).sum();
+    let mut count = files.len();
+
+    let mut expired = Vec::new();
+    for candidate in files {
+        if candidate.path == keep {
+            continue;
+        }
+        let age = now.duration_since(candidate.modified).unwrap_or_default();
+        let too_old = limits.age.is_some_and(|max| age > max);
+        let too_many = limits.count.is_some_and(|max| count > max);
+        let too_big = limits.size.is_some_and(|max| total > max);
+        // Everything after this one is newer
+        if !(too_old || too_many || too_big) {
+            break;
+        }
+        total -= candidate.size;
+        count -= 1;
+        expired.push(candidate);
+    }
+    expired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(86400);
+
+    /// Files named after their age in days, each `size` bytes
+    fn files(ages: &[u64], size: u64, now: SystemTime) -> Vec<Candidate> {
+        ages.iter()
+            .map(|&days| Candidate {
+                path: PathBuf::from(format!("{}", days)),
+                size,
+                modified: now - DAY * days as u32,
+            })
+            .collect()
+    }
+
+    fn names(picked: &[Candidate]) -> Vec<String> {
+        picked
+            .iter()
+            .map(|c| c.path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn oldest_go_first_until_within_count_and_size() {
+        let now = SystemTime::now();
+        let by_count = Limits {
+            count: Some(2),
+            ..Default::default()
+        };
+        let picked = select(files(&[1, 5, 3, 4], 10, now), Path::new("0"), by_count, now);
+        assert_eq!(names(&picked), ["5", "4"]);
+
+        let by_size = Limits {
+            size: Some(25),
+            ..Default::default()
+        };
+        let picked = select(files(&[1, 2, 3], 10, now), Path::new("0"), by_size, now);
+        assert_eq!(names(&picked), ["3"]);
+    }
+
+    #[test]
+    fn files_past_the_age_limit_go() {
+        let now = SystemTime::now();
+        let limits = Limits {
+            age: Some(DAY * 7),
+            ..Default::default()
+        };
+        let picked = select(files(&[1, 8, 30, 6], 10, now), Path::new("0"), limits, now);
+        assert_eq!(names(&picked), ["30", "8"]);
+    }
+
+    #[test]
+    fn the_new_recording_is_kept_even_if_over_the_limit() {
+        let now = SystemTime::now();
+        let limits = Limits {
+            size: Some(5),
+            ..Default::default()
+        };
+        let picked = select(files(&[0, 1, 2], 10, now), Path::new("0"), limits, now);
+        assert_eq!(names(&picked), ["2", "1"]);
+    }
+
+    #[test]
+    fn the_scope_goes_by_file_name() {
+        assert!(Scope::All.covers("screen-record-2025-01-01_12-00-00.mp4"));
+        assert!(Scope::All.covers("Replay_2025-01-01_12-00-00.mp4"));
+        assert!(!Scope::All.covers("screen-record-2025-01-01_12-00-00.mp4.json"));
+        assert!(!Scope::All.covers("holiday.mp4"));
+        assert!(!Scope::Recordings.covers("Replay_2025-01-01_12-00-00.mp4"));
+        assert!(!Scope::Replays.covers("screen-record-2025-01-01_12-00-00.mp4"));
+    }
+}
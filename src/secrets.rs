@@ -0,0 +1,122 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::config;
+use crate::error::{Error, Result};
+
+/// Login for an upload provider
+pub struct Credentials {
+    /// User name, or the access key id for S3
+    pub user: String,
+    /// Password, or the secret access key for S3
+    pub secret: String,
+}
+
+/// Where the secrets file is looked for unless configured otherwise
+fn default_secrets_file() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("niri-screen-recorder/secrets"))
+}
+
+/// Load upload credentials. The user comes from the config or the secrets
+/// file, the secret from the secrets file or, failing that, the keyring
+/// (through `secret-tool`).
+///
+/// The secrets file holds `user = ...` and `secret = ...` lines.
+pub async fn credentials() -> Result<Credentials> {
+    let config = config::get();
+    let file = config
+        .upload_secrets
+        .clone()
+        .or_else(default_secrets_file)
+        .map(|path| read_secrets_file(&path))
+        .transpose()?
+        .unwrap_or_default();
+
+    let user = config
+        .upload_user
+        .clone()
+        .or(file.user)
+        .ok_or_else(|| Error::Credentials("no upload user configured".to_string()))?;
+
+    let secret = match file.secret {
+        Some(secret) => secret,
+        None => keyring_lookup(&user).await?,
+    };
+
+    Ok(Credentials { user, secret })
+}
+
+#[derive(Default)]
+struct SecretsFile {
+    user: Option<String>,
+    secret: Option<String>,
+}
+
+/// Read a secrets file, treating a missing one as empty
+fn read_secrets_file(path: &std::path::Path) -> Result<SecretsFile> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(SecretsFile::default()),
+        Err(e) => {
+            return Err(Error::Credentials(format!(
+                "failed to read {}: {}",
+                path.display(),
+                e
+            )));
+        }
+    };
+
+    if let Ok(metadata) = std::fs::metadata(path)
+        && metadata.permissions().mode() & 0o077 != 0
+    {
+        tracing::warn!(
+            "{} is readable by other users, consider chmod 600",
+            path.display()
+        );
+    }
+
+    let mut secrets = SecretsFile::default();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            tracing::warn!("Ignoring malformed line in {}", path.display());
+            continue;
+        };
+        let value = Some(value.trim().to_string());
+        match key.trim() {
+            "user" => secrets.user = value,
+            "secret" => secrets.secret = value,
+            other => tracing::warn!("Unknown key {:?} in {}", other, path.display()),
+        }
+    }
+    Ok(secrets)
+}
+
+/// Look the secret up in the keyring, where it is stored with
+/// `secret-tool store --label=niri-screen-recorder service niri-screen-recorder user <user>`
+async fn keyring_lookup(user: &str) -> Result<String> {
+    let output = Command::new("secret-tool")
+        .args(["lookup", "service", "niri-screen-recorder", "user", user])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|source| Error::CommandFailed {
+            program: "secret-tool".to_string(),
+            source,
+        })?;
+
+    let secret = String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches('\n')
+        .to_string();
+    if !output.status.success() || secret.is_empty() {
+        return Err(Error::Credentials(format!(
+            "no secret for {} in the secrets file or the keyring",
+            user
+        )));
+    }
+    Ok(secret)
+}
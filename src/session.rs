@@ -0,0 +1,276 @@
+use futures_util::StreamExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::{mpsc, watch};
+use zbus::Connection;
+use zbus::zvariant::OwnedFd;
+
+use crate::logind;
+
+/// Wait until the graphical session is going away: the daemon is asked to
+/// terminate, the logind session ends, or the Wayland socket disappears.
+/// Returns a human-readable reason. A shutdown is followed with
+/// `watch_shutdown` instead, which can hold it up until the recording is
+/// saved.
+pub async fn wait_for_session_end() -> String {
+    tokio::select! {
+        reason = wait_for_signal() => reason,
+        reason = wait_for_logind() => reason,
+        reason = wait_for_wayland_socket() => reason,
+    }
+}
+
+async fn wait_for_signal() -> String {
+    let (Ok(mut term), Ok(mut hup), Ok(mut int)) = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::hangup()),
+        signal(SignalKind::interrupt()),
+    ) else {
+        tracing::warn!("Failed to install signal handlers");
+        return std::future::pending().await;
+    };
+
+    tokio::select! {
+        _ = term.recv() => "Received SIGTERM".to_string(),
+        _ = hup.recv() => "Received SIGHUP".to_string(),
+        _ = int.recv() => "Received SIGINT".to_string(),
+    }
+}
+
+async fn wait_for_logind() -> String {
+    match watch_logind().await {
+        Ok(reason) => reason,
+        Err(e) => {
+            tracing::warn!("Not watching logind for session end: {}", e);
+            std::future::pending().await
+        }
+    }
+}
+
+async fn watch_logind() -> zbus::Result<String> {
+    let connection = Connection::system().await?;
+    let session = logind::current_session(&connection).await?;
+    let manager = logind::ManagerProxy::new(&connection).await?;
+
+    let mut removed = manager.receive_session_removed().await?;
+
+    tracing::debug!("Watching logind session {}", session.as_str());
+
+    while let Some(signal) = removed.next().await {
+        if signal.args()?.object_path == *session {
+            return Ok("Session ended".to_string());
+        }
+    }
+    Err(zbus::Error::Failure(
+        "logind signal stream closed".to_string(),
+    ))
+}
+
+/// Track whether the session is locked. Lockers that set logind's
+/// LockedHint are followed, as well as logind's Lock and Unlock requests.
+pub fn watch_lock() -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(false);
+
+    tokio::spawn(async move {
+        if let Err(e) = follow_lock(&tx).await {
+            tracing::warn!("Not watching for the screen lock: {}", e);
+        }
+    });
+
+    rx
+}
+
+async fn follow_lock(tx: &watch::Sender<bool>) -> zbus::Result<()> {
+    let connection = Connection::system().await?;
+    let path = logind::current_session(&connection).await?;
+    let session = logind::SessionProxy::builder(&connection)
+        .path(path)?
+        .build()
+        .await?;
+
+    let mut hint = session.receive_locked_hint_changed().await;
+    let mut lock = session.receive_lock().await?;
+    let mut unlock = session.receive_unlock().await?;
+
+    tracing::debug!("Watching for the screen lock");
+
+    loop {
+        let locked = tokio::select! {
+            Some(change) = hint.next() => change.get().await?,
+            Some(_) = lock.next() => true,
+            Some(_) = unlock.next() => false,
+            else => return Err(zbus::Error::Failure("logind signal stream closed".to_string())),
+        };
+        tx.send_if_modified(|current| std::mem::replace(current, locked) != locked);
+    }
+}
+
+/// What to do with a recording when the system suspends
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OnSuspend {
+    /// Pause it, and resume after waking up
+    Pause,
+    /// Stop and save it
+    Stop,
+    /// Stop and save it, and record the same region again after waking up
+    Restart,
+}
+
+impl FromStr for OnSuspend {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "pause" => Ok(OnSuspend::Pause),
+            "stop" => Ok(OnSuspend::Stop),
+            "restart" => Ok(OnSuspend::Restart),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A change in the system's sleep state
+pub enum Sleep {
+    /// The system is about to sleep, and waits until the lock is dropped
+    /// or logind's InhibitDelayMaxSec runs out
+    Preparing(Delay),
+    Woke,
+}
+
+/// A system shutdown, or reboot, coming up
+pub enum Shutdown {
+    /// The system is about to shut down, and waits like for `Sleep`
+    Preparing(Delay),
+    /// The shutdown was called off
+    Cancelled,
+}
+
+/// logind's delay inhibitor, handed over while the system waits to sleep
+/// or shut down
+pub struct Delay(Option<OwnedFd>);
+
+impl Delay {
+    /// Let the system go ahead
+    pub fn release(self) {
+        // Closing the fd releases the inhibitor
+        drop(self.0);
+    }
+}
+
+/// Follow suspend and resume. A delay inhibitor is held throughout, so the
+/// recording can be saved before the system actually sleeps.
+pub fn watch_sleep() -> mpsc::Receiver<Sleep> {
+    let (tx, rx) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        if let Err(e) = follow_sleep(&tx).await {
+            tracing::warn!("Not watching for suspend: {}", e);
+        }
+    });
+
+    rx
+}
+
+/// Take logind's delay inhibitor for `what`, "sleep" or "shutdown"
+async fn delay_inhibitor(manager: &logind::ManagerProxy<'_>, what: &str) -> Option<OwnedFd> {
+    manager
+        .inhibit(
+            what,
+            "niri-screen-recorder",
+            "Saving the recording",
+            "delay",
+        )
+        .await
+        .inspect_err(|e| tracing::warn!("Failed to take logind {} delay inhibitor: {}", what, e))
+        .ok()
+}
+
+async fn follow_sleep(tx: &mpsc::Sender<Sleep>) -> zbus::Result<()> {
+    let connection = Connection::system().await?;
+    let manager = logind::ManagerProxy::new(&connection).await?;
+    let mut sleep = manager.receive_prepare_for_sleep().await?;
+    let mut delay = delay_inhibitor(&manager, "sleep").await;
+
+    tracing::debug!("Watching for suspend");
+
+    while let Some(signal) = sleep.next().await {
+        let event = if signal.args()?.start {
+            Sleep::Preparing(Delay(delay.take()))
+        } else {
+            // Taken again for the next time
+            delay = delay_inhibitor(&manager, "sleep").await;
+            Sleep::Woke
+        };
+        if tx.send(event).await.is_err() {
+            return Ok(());
+        }
+    }
+    Err(zbus::Error::Failure(
+        "logind signal stream closed".to_string(),
+    ))
+}
+
+/// Follow shutdowns and reboots. Like for suspend, a delay inhibitor is held
+/// so the recording can be saved first, and taken again if the shutdown is
+/// called off.
+pub fn watch_shutdown() -> mpsc::Receiver<Shutdown> {
+    let (tx, rx) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        if let Err(e) = follow_shutdown(&tx).await {
+            tracing::warn!("Not watching for shutdown: {}", e);
+        }
+    });
+
+    rx
+}
+
+async fn follow_shutdown(tx: &mpsc::Sender<Shutdown>) -> zbus::Result<()> {
+    let connection = Connection::system().await?;
+    let manager = logind::ManagerProxy::new(&connection).await?;
+    let mut shutdown = manager.receive_prepare_for_shutdown().await?;
+    let mut delay = delay_inhibitor(&manager, "shutdown").await;
+
+    tracing::debug!("Watching for shutdown");
+
+    while let Some(signal) = shutdown.next().await {
+        let event = if signal.args()?.start {
+            Shutdown::Preparing(Delay(delay.take()))
+        } else {
+            delay = delay_inhibitor(&manager, "shutdown").await;
+            Shutdown::Cancelled
+        };
+        if tx.send(event).await.is_err() {
+            return Ok(());
+        }
+    }
+    Err(zbus::Error::Failure(
+        "logind signal stream closed".to_string(),
+    ))
+}
+
+fn wayland_socket_path() -> Option<PathBuf> {
+    let display = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string());
+    let display = PathBuf::from(display);
+    if display.is_absolute() {
+        return Some(display);
+    }
+    let runtime_dir = std::env::var("XDG_RUNTIME_DIR").ok()?;
+    Some(PathBuf::from(runtime_dir).join(display))
+}
+
+async fn wait_for_wayland_socket() -> String {
+    let Some(path) = wayland_socket_path().filter(|p| p.exists()) else {
+        tracing::warn!("Wayland socket not found, not watching for compositor exit");
+        return std::future::pending().await;
+    };
+
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(2));
+    loop {
+        interval.tick().await;
+        if !path.exists() {
+            return format!("Wayland socket {} disappeared", path.display());
+        }
+    }
+}
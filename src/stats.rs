@@ -0,0 +1,29 @@
+use zbus::zvariant::{DeserializeDict, SerializeDict, Type};
+
+/// Runtime metrics reported by `GetStatistics`, sent as an `a{sv}` dict so
+/// fields can be added without breaking existing consumers
+#[derive(Debug, Default, SerializeDict, DeserializeDict, Type)]
+#[zvariant(
+    signature = "a{sv}",
+    rename_all = "kebab-case",
+    crate = "zbus::zvariant"
+)]
+pub struct Statistics {
+    /// Seconds since the daemon started
+    pub uptime: u64,
+    /// Recordings started, across daemon restarts
+    pub started: u64,
+    /// Recordings that failed to start or ended because the recorder
+    /// exited, across daemon restarts
+    pub failed: u64,
+    /// Recordings finished, across daemon restarts
+    pub recordings: u64,
+    /// Bytes written to disk across daemon restarts, including the recording in progress
+    pub bytes_written: u64,
+    /// Frames per second gpu-screen-recorder is currently encoding, 0 when idle
+    pub encode_fps: u32,
+    /// Estimated frames dropped during the current recording
+    pub dropped_frames: u64,
+    /// Seconds the current recording has been running, 0 when idle
+    pub elapsed: u64,
+}
@@ -0,0 +1,38 @@
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+/// Send a state update (e.g. "READY=1") to the service manager.
+/// Does nothing when not running under systemd with `Type=notify`.
+pub fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let result = (|| {
+        let path = path.to_string_lossy();
+        // A leading '@' denotes a socket in the abstract namespace
+        let addr = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(path.as_ref())?,
+        };
+        UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)
+    })();
+
+    if let Err(e) = result {
+        tracing::warn!("Failed to notify systemd ({}): {}", state, e);
+    }
+}
+
+/// How often to ping the watchdog, if systemd enabled one for this process.
+/// Pings are sent at half the configured `WatchdogSec` to leave some slack.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
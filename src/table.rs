@@ -0,0 +1,95 @@
+use anstyle::{AnsiColor, Style};
+use std::io::IsTerminal;
+
+/// Labels and column headers
+pub const LABEL: Style = Style::new().bold();
+/// Secondary details, like dates
+pub const DIM: Style = Style::new().dimmed();
+pub const GOOD: Style = AnsiColor::Green.on_default().bold();
+pub const WARN: Style = AnsiColor::Yellow.on_default().bold();
+pub const TAGS: Style = AnsiColor::Cyan.on_default();
+
+#[derive(Clone, Copy)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Column-aligned CLI output. Cells are styled after they are padded, so
+/// escape codes don't throw off the widths, and anstream strips the styles
+/// again when stdout isn't a terminal or NO_COLOR is set.
+pub struct Table {
+    align: &'static [Align],
+    /// What goes between columns
+    gap: &'static str,
+    rows: Vec<Vec<(String, Style)>>,
+}
+
+impl Table {
+    /// A table with one column per entry of `align`
+    pub fn new(align: &'static [Align]) -> Self {
+        Self {
+            align,
+            gap: "  ",
+            rows: Vec::new(),
+        }
+    }
+
+    /// "Label: value" lines, with the labels right-aligned on the colon
+    pub fn labels() -> Self {
+        Self {
+            align: &[Align::Right, Align::Left],
+            gap: " ",
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add a "Label: value" line to a table made with `labels`
+    pub fn label(&mut self, label: &str, value: String, style: Style) {
+        self.row([(format!("{}:", label), LABEL), (value, style)]);
+    }
+
+    /// Add a header row, only when printing to a terminal so piped output
+    /// stays one line per item
+    pub fn header(&mut self, titles: &[String]) {
+        if std::io::stdout().is_terminal() {
+            self.row(titles.iter().map(|t| (t.clone(), LABEL.underline())));
+        }
+    }
+
+    pub fn row(&mut self, cells: impl IntoIterator<Item = (String, Style)>) {
+        self.rows.push(cells.into_iter().collect());
+    }
+
+    pub fn print(&self) {
+        let mut widths = vec![0; self.align.len()];
+        for row in &self.rows {
+            for (width, (text, _)) in widths.iter_mut().zip(row) {
+                *width = (*width).max(text.chars().count());
+            }
+        }
+
+        for row in &self.rows {
+            let mut line = String::new();
+            // Empty cells at the end are left off, and the last column isn't
+            // padded, so lines don't end in spaces
+            let used = row
+                .iter()
+                .rposition(|(text, _)| !text.is_empty())
+                .map_or(0, |i| i + 1);
+            for (i, (text, style)) in row[..used].iter().enumerate() {
+                if i > 0 {
+                    line.push_str(self.gap);
+                }
+                let pad = " ".repeat(widths[i].saturating_sub(text.chars().count()));
+                let last = i + 1 == used;
+                match self.align[i] {
+                    Align::Right => line += &format!("{}{style}{text}{style:#}", pad),
+                    Align::Left if last => line += &format!("{style}{text}{style:#}"),
+                    Align::Left => line += &format!("{style}{text}{style:#}{}", pad),
+                }
+            }
+            anstream::println!("{}", line);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use std::os::fd::{AsFd, AsRawFd, RawFd};
+use std::path::Path;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Notices when the file being recorded to is deleted or moved away, e.g.
+/// by a cleanup script. gpu-screen-recorder would otherwise go on writing
+/// into an unlinked file that vanishes once it is closed. Stops watching
+/// when dropped.
+pub struct Watch {
+    task: JoinHandle<()>,
+}
+
+/// The inotify instance, pollable by tokio
+struct Fd(Inotify);
+
+impl AsRawFd for Fd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_fd().as_raw_fd()
+    }
+}
+
+impl Watch {
+    /// Watch `file`. The receiver fires once it is deleted or moved out of
+    /// its directory, or the directory itself goes away. Best-effort:
+    /// problems are logged and the recording goes on unwatched.
+    pub fn start(file: &Path) -> Option<(Self, oneshot::Receiver<()>)> {
+        let (Some(dir), Some(name)) = (file.parent(), file.file_name()) else {
+            return None;
+        };
+        // A file deleted while open only loses its name, so the directory
+        // is watched rather than the file itself
+        let watch = || -> std::io::Result<AsyncFd<Fd>> {
+            let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+            inotify.add_watch(
+                dir,
+                AddWatchFlags::IN_DELETE
+                    | AddWatchFlags::IN_MOVED_FROM
+                    | AddWatchFlags::IN_DELETE_SELF
+                    | AddWatchFlags::IN_MOVE_SELF,
+            )?;
+            AsyncFd::new(Fd(inotify))
+        };
+        let fd = match watch() {
+            Ok(fd) => fd,
+            Err(e) => {
+                tracing::warn!("Not watching {} for removal: {}", file.display(), e);
+                return None;
+            }
+        };
+
+        let name = name.to_os_string();
+        let (removed, receiver) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            loop {
+                let Ok(mut ready) = fd.readable().await else {
+                    return;
+                };
+                let events = match ready.get_inner().0.read_events() {
+                    Ok(events) => events,
+                    Err(nix::Error::EAGAIN) => {
+                        ready.clear_ready();
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Stopped watching for removal: {}", e);
+                        return;
+                    }
+                };
+
+                let gone = events.iter().any(|event| {
+                    let dir_gone = event
+                        .mask
+                        .intersects(AddWatchFlags::IN_DELETE_SELF | AddWatchFlags::IN_MOVE_SELF);
+                    dir_gone || event.name.as_ref() == Some(&name)
+                });
+                if gone {
+                    removed.send(()).ok();
+                    return;
+                }
+            }
+        });
+
+        Some((Self { task }, receiver))
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,451 @@
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::{Connection, MatchRule, MessageStream, fdo, interface, proxy};
+
+use crate::compositor;
+use crate::dbus::{BUS_NAME, OBJECT_PATH};
+use crate::i18n::t;
+use crate::notifications;
+use crate::recorder;
+
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+
+#[proxy(
+    interface = "org.kde.StatusNotifierWatcher",
+    default_service = "org.kde.StatusNotifierWatcher",
+    default_path = "/StatusNotifierWatcher"
+)]
+trait StatusNotifierWatcher {
+    fn register_status_notifier_item(&self, service: &str) -> zbus::Result<()>;
+}
+
+/// Icon name, icon pixmaps (width, height, ARGB data), title, and description
+type ToolTip = (String, Vec<(i32, i32, Vec<u8>)>, String, String);
+
+/// The tray icon, as seen by status bars
+struct StatusNotifierItem {
+    state: Arc<State>,
+    connection: Connection,
+}
+
+#[interface(name = "org.kde.StatusNotifierItem")]
+impl StatusNotifierItem {
+    /// Left click toggles recording
+    async fn activate(&self, _x: i32, _y: i32) {
+        call_recorder(&self.connection, "ToggleRecording");
+    }
+
+    async fn secondary_activate(&self, _x: i32, _y: i32) {}
+
+    /// The menu is exported over dbusmenu instead
+    async fn context_menu(&self, _x: i32, _y: i32) {}
+
+    async fn scroll(&self, _delta: i32, _orientation: &str) {}
+
+    #[zbus(property)]
+    fn category(&self) -> &str {
+        "ApplicationStatus"
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> &str {
+        "niri-screen-recorder"
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> String {
+        t!("tray-title")
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        if self.state.recording.load(Ordering::Relaxed) {
+            "NeedsAttention"
+        } else {
+            "Active"
+        }
+    }
+
+    #[zbus(property)]
+    fn icon_name(&self) -> &str {
+        if self.state.recording.load(Ordering::Relaxed) {
+            "media-record"
+        } else {
+            "camera-video"
+        }
+    }
+
+    #[zbus(property)]
+    fn attention_icon_name(&self) -> &str {
+        "media-record"
+    }
+
+    #[zbus(property)]
+    fn tool_tip(&self) -> ToolTip {
+        let description = if self.state.recording.load(Ordering::Relaxed) {
+            t!("tray-recording")
+        } else {
+            t!("tray-idle")
+        };
+        (String::new(), Vec::new(), t!("tray-title"), description)
+    }
+
+    #[zbus(property)]
+    fn item_is_menu(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn menu(&self) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(MENU_PATH).expect("menu path is valid")
+    }
+
+    #[zbus(signal)]
+    async fn new_icon(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_attention_icon(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_tool_tip(ctxt: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_status(ctxt: &SignalEmitter<'_>, status: &str) -> zbus::Result<()>;
+}
+
+/// Entries of the tray menu. The ids stay put when entries are added, so
+/// hosts holding on to an old layout don't click the wrong one.
+#[derive(Clone, Copy)]
+enum MenuItem {
+    Start = 1,
+    StartOutput = 5,
+    Stop = 2,
+    Replay = 6,
+    Separator = 3,
+    OpenFolder = 4,
+}
+
+const MENU_ITEMS: &[MenuItem] = &[
+    MenuItem::Start,
+    MenuItem::StartOutput,
+    MenuItem::Stop,
+    MenuItem::Replay,
+    MenuItem::Separator,
+    MenuItem::OpenFolder,
+];
+
+/// What the menu entries depend on, kept up to date from the recorder's
+/// signals
+#[derive(Default)]
+struct State {
+    recording: AtomicBool,
+    replaying: AtomicBool,
+}
+
+impl MenuItem {
+    fn from_id(id: i32) -> Option<Self> {
+        MENU_ITEMS.iter().copied().find(|item| *item as i32 == id)
+    }
+
+    fn properties(self, state: &State) -> HashMap<String, OwnedValue> {
+        let recording = state.recording.load(Ordering::Relaxed);
+        let (label, enabled) = match self {
+            MenuItem::Start => (t!("tray-start"), !recording),
+            MenuItem::StartOutput => (t!("tray-start-output"), !recording),
+            MenuItem::Stop => (t!("tray-stop"), recording),
+            MenuItem::Replay if state.replaying.load(Ordering::Relaxed) => {
+                (t!("tray-replay-stop"), true)
+            }
+            MenuItem::Replay => (t!("tray-replay-start"), true),
+            MenuItem::Separator => {
+                return HashMap::from([("type".to_string(), owned("separator"))]);
+            }
+            MenuItem::OpenFolder => (t!("tray-open-folder"), true),
+        };
+        HashMap::from([
+            ("label".to_string(), owned(label)),
+            ("enabled".to_string(), owned(enabled)),
+        ])
+    }
+}
+
+fn owned<'a>(value: impl Into<Value<'a>>) -> OwnedValue {
+    value
+        .into()
+        .try_into()
+        .expect("menu properties hold no file descriptors")
+}
+
+/// A dbusmenu layout node: id, properties, and children
+type Layout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+/// The tray icon's context menu, exported over dbusmenu
+struct Menu {
+    state: Arc<State>,
+    revision: Arc<AtomicU32>,
+    connection: Connection,
+}
+
+#[interface(name = "com.canonical.dbusmenu")]
+impl Menu {
+    async fn get_layout(
+        &self,
+        _parent_id: i32,
+        _recursion_depth: i32,
+        _property_names: Vec<String>,
+    ) -> (u32, Layout) {
+        let children = MENU_ITEMS
+            .iter()
+            .map(|item| {
+                let node: Layout = (*item as i32, item.properties(&self.state), Vec::new());
+                owned(node)
+            })
+            .collect();
+        let root = HashMap::from([("children-display".to_string(), owned("submenu"))]);
+        (self.revision.load(Ordering::Relaxed), (0, root, children))
+    }
+
+    async fn get_group_properties(
+        &self,
+        ids: Vec<i32>,
+        _property_names: Vec<String>,
+    ) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+        let wanted = |item: &MenuItem| ids.is_empty() || ids.contains(&(*item as i32));
+        MENU_ITEMS
+            .iter()
+            .filter(|item| wanted(item))
+            .map(|item| (*item as i32, item.properties(&self.state)))
+            .collect()
+    }
+
+    async fn get_property(&self, id: i32, name: String) -> fdo::Result<OwnedValue> {
+        MenuItem::from_id(id)
+            .and_then(|item| item.properties(&self.state).remove(&name))
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("no property {} on {}", name, id)))
+    }
+
+    async fn event(&self, id: i32, event_id: String, _data: OwnedValue, _timestamp: u32) {
+        if event_id != "clicked" {
+            return;
+        }
+        match MenuItem::from_id(id) {
+            Some(MenuItem::Start) => call_recorder(&self.connection, "StartRecording"),
+            Some(MenuItem::StartOutput) => start_focused_output(&self.connection),
+            Some(MenuItem::Stop) => call_recorder(&self.connection, "StopRecording"),
+            Some(MenuItem::Replay) if self.state.replaying.load(Ordering::Relaxed) => {
+                call_recorder(&self.connection, "StopReplay")
+            }
+            Some(MenuItem::Replay) => call_recorder(&self.connection, "StartReplay"),
+            Some(MenuItem::OpenFolder) => open_output_folder(),
+            Some(MenuItem::Separator) | None => {}
+        }
+    }
+
+    async fn event_group(&self, events: Vec<(i32, String, OwnedValue, u32)>) -> Vec<i32> {
+        for (id, event_id, data, timestamp) in events {
+            self.event(id, event_id, data, timestamp).await;
+        }
+        Vec::new()
+    }
+
+    async fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    async fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        (Vec::new(), Vec::new())
+    }
+
+    #[zbus(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+
+    #[zbus(property)]
+    fn text_direction(&self) -> &str {
+        "ltr"
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        "normal"
+    }
+
+    #[zbus(property)]
+    fn icon_theme_path(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(signal)]
+    async fn layout_updated(
+        ctxt: &SignalEmitter<'_>,
+        revision: u32,
+        parent: i32,
+    ) -> zbus::Result<()>;
+}
+
+/// Call a method on the recorder without waiting for it, since starting
+/// only returns once a region has been selected
+fn call_recorder(connection: &Connection, method: &'static str) {
+    let connection = connection.clone();
+    tokio::spawn(async move {
+        let result = connection
+            .call_method(Some(BUS_NAME), OBJECT_PATH, Some(BUS_NAME), method, &())
+            .await;
+        if let Err(e) = result {
+            tracing::debug!("Tray call to {} failed: {}", method, e);
+        }
+    });
+}
+
+/// Record the focused output through StartRecordingOutputs, without waiting
+/// for it like `call_recorder`
+fn start_focused_output(connection: &Connection) {
+    let connection = connection.clone();
+    tokio::spawn(async move {
+        let output = match compositor::get().focused_output().await {
+            Ok(Some(output)) => output,
+            Ok(None) => {
+                tracing::debug!("No focused output to record from the tray");
+                return;
+            }
+            Err(e) => {
+                tracing::debug!("Failed to find the focused output: {}", e);
+                return;
+            }
+        };
+        let result = connection
+            .call_method(
+                Some(BUS_NAME),
+                OBJECT_PATH,
+                Some(BUS_NAME),
+                "StartRecordingOutputs",
+                &(vec![output.name],),
+            )
+            .await;
+        if let Err(e) = result {
+            tracing::debug!("Tray call to StartRecordingOutputs failed: {}", e);
+        }
+    });
+}
+
+fn open_output_folder() {
+    let result =
+        recorder::output_dir().and_then(|dir| notifications::open_path(&dir.to_string_lossy()));
+    if let Err(e) = result {
+        tracing::error!("Failed to open output folder: {}", e);
+    }
+}
+
+/// Show a tray icon through the StatusNotifierItem protocol. Does nothing
+/// visible until a status bar with a tray is running.
+pub async fn serve(connection: &Connection) -> zbus::Result<()> {
+    let state = Arc::new(State::default());
+    let revision = Arc::new(AtomicU32::new(1));
+
+    let object_server = connection.object_server();
+    object_server
+        .at(
+            ITEM_PATH,
+            StatusNotifierItem {
+                state: state.clone(),
+                connection: connection.clone(),
+            },
+        )
+        .await?;
+    object_server
+        .at(
+            MENU_PATH,
+            Menu {
+                state: state.clone(),
+                revision: revision.clone(),
+                connection: connection.clone(),
+            },
+        )
+        .await?;
+
+    tokio::spawn(register_with_watcher(connection.clone()));
+    tokio::spawn(follow_recorder(connection.clone(), state, revision));
+
+    Ok(())
+}
+
+/// Register with the StatusNotifierWatcher, again whenever it is restarted
+/// (e.g. when the status bar reloads)
+async fn register_with_watcher(connection: Connection) {
+    let register = async || match StatusNotifierWatcherProxy::new(&connection).await {
+        Ok(watcher) => match watcher.register_status_notifier_item(BUS_NAME).await {
+            Ok(()) => tracing::debug!("Registered tray icon"),
+            Err(e) => tracing::debug!("No tray available: {}", e),
+        },
+        Err(e) => tracing::debug!("No tray available: {}", e),
+    };
+
+    register().await;
+
+    let Ok(dbus) = fdo::DBusProxy::new(&connection).await else {
+        return;
+    };
+    let Ok(mut owner_changes) = dbus
+        .receive_name_owner_changed_with_args(&[(0, WATCHER)])
+        .await
+    else {
+        return;
+    };
+    while let Some(change) = owner_changes.next().await {
+        if change.args().is_ok_and(|args| args.new_owner.is_some()) {
+            register().await;
+        }
+    }
+}
+
+/// Update the icon and menu as recordings and the replay buffer start and
+/// stop
+async fn follow_recorder(
+    connection: Connection,
+    state: Arc<State>,
+    revision: Arc<AtomicU32>,
+) -> zbus::Result<()> {
+    let rule = MatchRule::builder()
+        .msg_type(zbus::message::Type::Signal)
+        .interface(BUS_NAME)?
+        .path(OBJECT_PATH)?
+        .build();
+    let mut signals = MessageStream::for_match_rule(rule, &connection, None).await?;
+
+    let item = SignalEmitter::new(&connection, ITEM_PATH)?;
+    let menu = SignalEmitter::new(&connection, MENU_PATH)?;
+
+    while let Some(Ok(message)) = signals.next().await {
+        let header = message.header();
+        match header.member().map(|m| m.as_str()) {
+            Some(member @ ("RecordingStarted" | "RecordingStopped")) => {
+                let now_recording = member == "RecordingStarted";
+                state.recording.store(now_recording, Ordering::Relaxed);
+
+                let status = if now_recording {
+                    "NeedsAttention"
+                } else {
+                    "Active"
+                };
+                StatusNotifierItem::new_icon(&item).await.ok();
+                StatusNotifierItem::new_tool_tip(&item).await.ok();
+                StatusNotifierItem::new_status(&item, status).await.ok();
+            }
+            Some("ReplayStarted") => state.replaying.store(true, Ordering::Relaxed),
+            Some("ReplayStopped") => state.replaying.store(false, Ordering::Relaxed),
+            _ => continue,
+        }
+
+        let revision = revision.fetch_add(1, Ordering::Relaxed) + 1;
+        Menu::layout_updated(&menu, revision, 0).await.ok();
+    }
+
+    Ok(())
+}
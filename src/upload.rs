@@ -0,0 +1,514 @@
+use futures_util::future::BoxFuture;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::i18n::t;
+use crate::persist::PersistentState;
+use crate::secrets;
+
+/// Delay before the first retry, doubled for every further one
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// curl's exit codes for failures on the way to the server and back, like
+/// DNS, connection, and TLS errors or timeouts, which may well pass
+const TRANSPORT_ERRORS: &[i32] = &[5, 6, 7, 28, 35, 52, 55, 56];
+
+/// Starts the line curl writes to stderr with the response's HTTP status
+const STATUS_PREFIX: &str = "http-status:";
+
+/// Which upload provider to use
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The 0x0.st file host
+    ZeroXZero,
+    /// A PUT or POST to `UPLOAD_URL`
+    Http,
+    /// An S3-compatible bucket at `UPLOAD_URL`, e.g. MinIO or Backblaze B2
+    S3,
+    /// A WebDAV folder at `UPLOAD_URL`, e.g. in Nextcloud
+    WebDav,
+    /// The custom `UPLOAD_CMD`
+    Command,
+}
+
+impl FromStr for Backend {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "0x0" | "0x0.st" => Ok(Backend::ZeroXZero),
+            "http" => Ok(Backend::Http),
+            "s3" => Ok(Backend::S3),
+            "webdav" => Ok(Backend::WebDav),
+            "command" => Ok(Backend::Command),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Somewhere recordings can be uploaded to
+pub trait Provider: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Command that uploads `file` and prints the response on stdout. curl's
+    /// `-#` progress bar on stderr is picked up as upload progress.
+    fn command(&self, file: &Path) -> Command;
+
+    /// What to feed the command on stdin. Used to pass credentials to curl
+    /// as a config file, so they don't show up in the process list.
+    fn stdin(&self) -> BoxFuture<'_, Result<Option<String>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// The public URL of the upload, from what the command printed
+    fn url(&self, _file: &Path, stdout: &str) -> Result<String> {
+        last_line(stdout).ok_or_else(|| Error::UploadFailed("no URL in the response".to_string()))
+    }
+}
+
+/// The last non-empty line, so commands may print other output before the URL
+fn last_line(output: &str) -> Option<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+/// curl with the options every provider uses. The HTTP status goes to
+/// stderr along with the progress bar, to tell whether a retry could help.
+fn curl() -> Command {
+    let mut cmd = Command::new("curl");
+    cmd.args(["--progress-bar", "--fail-with-body", "--show-error"])
+        .arg("--write-out")
+        .arg(format!("%{{stderr}}{} %{{http_code}}\n", STATUS_PREFIX));
+    cmd
+}
+
+/// Where a file PUT to `url` ends up. curl appends the file name to URLs
+/// ending in a slash.
+fn put_url(url: &str, file: &Path) -> String {
+    if url.ends_with('/') {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        format!("{}{}", url, name)
+    } else {
+        url.to_string()
+    }
+}
+
+/// The public URL of a file PUT to `url`
+fn public_url(url: &str, file: &Path) -> String {
+    match &config::get().upload_public_url {
+        Some(public) => put_url(public, file),
+        None => put_url(url, file),
+    }
+}
+
+/// A line of a curl config, with the value quoted as curl expects
+fn curl_option(name: &str, value: &str) -> String {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("{} = \"{}\"\n", name, value)
+}
+
+/// `user` line of a curl config
+fn curl_user(credentials: &secrets::Credentials) -> String {
+    curl_option(
+        "user",
+        &format!("{}:{}", credentials.user, credentials.secret),
+    )
+}
+
+/// Uploads to https://0x0.st, which replies with the URL
+struct ZeroXZero;
+
+impl Provider for ZeroXZero {
+    fn name(&self) -> &'static str {
+        "0x0.st"
+    }
+
+    fn command(&self, file: &Path) -> Command {
+        let mut cmd = curl();
+        cmd.arg("-F")
+            .arg(format!("file=@{}", file.display()))
+            .arg("https://0x0.st");
+        cmd
+    }
+}
+
+/// Uploads to a generic HTTP endpoint, either as the PUT body or as the
+/// `file` field of a POST form
+struct Http {
+    url: String,
+    post: bool,
+    /// Header such as "Authorization: Bearer ..."
+    auth_header: Option<String>,
+}
+
+impl Provider for Http {
+    fn name(&self) -> &'static str {
+        "HTTP"
+    }
+
+    fn command(&self, file: &Path) -> Command {
+        let mut cmd = curl();
+        if self.auth_header.is_some() {
+            cmd.arg("--config").arg("-");
+        }
+        if self.post {
+            cmd.arg("-F").arg(format!("file=@{}", file.display()));
+        } else {
+            cmd.arg("-T").arg(file);
+        }
+        cmd.arg(&self.url);
+        cmd
+    }
+
+    /// Endpoints that reply with a URL get that one. Otherwise a PUT is
+    /// assumed to be served from where it was put.
+    fn url(&self, file: &Path, stdout: &str) -> Result<String> {
+        if let Some(url) = last_line(stdout).filter(|l| l.contains("://")) {
+            return Ok(url);
+        }
+        if self.post {
+            return Err(Error::UploadFailed("no URL in the response".to_string()));
+        }
+        Ok(put_url(&self.url, file))
+    }
+
+    fn stdin(&self) -> BoxFuture<'_, Result<Option<String>>> {
+        let header = self.auth_header.as_deref();
+        Box::pin(async move { Ok(header.map(|header| curl_option("header", header))) })
+    }
+}
+
+/// Uploads into an S3-compatible bucket, given as a path-style URL such as
+/// `https://s3.example.com/bucket/`, signed with curl's SigV4 support
+struct S3 {
+    url: String,
+    region: String,
+}
+
+impl Provider for S3 {
+    fn name(&self) -> &'static str {
+        "S3"
+    }
+
+    fn command(&self, file: &Path) -> Command {
+        let mut cmd = curl();
+        cmd.arg("--config")
+            .arg("-")
+            .arg("--aws-sigv4")
+            .arg(format!("aws:amz:{}:s3", self.region))
+            // S3 requires a payload hash, and hashing large files up front
+            // would delay the upload
+            .arg("-H")
+            .arg("x-amz-content-sha256: UNSIGNED-PAYLOAD")
+            .arg("-T")
+            .arg(file)
+            .arg(&self.url);
+        cmd
+    }
+
+    fn stdin(&self) -> BoxFuture<'_, Result<Option<String>>> {
+        Box::pin(async { Ok(Some(curl_user(&secrets::credentials().await?))) })
+    }
+
+    fn url(&self, file: &Path, _stdout: &str) -> Result<String> {
+        Ok(public_url(&self.url, file))
+    }
+}
+
+/// Uploads into a WebDAV folder, such as Nextcloud's
+/// `https://cloud.example.com/remote.php/dav/files/<user>/Recordings/`
+struct WebDav {
+    url: String,
+}
+
+impl Provider for WebDav {
+    fn name(&self) -> &'static str {
+        "WebDAV"
+    }
+
+    fn command(&self, file: &Path) -> Command {
+        let mut cmd = curl();
+        cmd.arg("--config")
+            .arg("-")
+            .arg("-T")
+            .arg(file)
+            .arg(&self.url);
+        cmd
+    }
+
+    fn stdin(&self) -> BoxFuture<'_, Result<Option<String>>> {
+        Box::pin(async { Ok(Some(curl_user(&secrets::credentials().await?))) })
+    }
+
+    fn url(&self, file: &Path, _stdout: &str) -> Result<String> {
+        Ok(public_url(&self.url, file))
+    }
+}
+
+/// Runs a custom command through `sh` with the file appended as its last
+/// argument
+struct Custom {
+    command: String,
+}
+
+impl Provider for Custom {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn command(&self, file: &Path) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(format!("{} \"$1\"", self.command))
+            .arg("sh")
+            .arg(file);
+        cmd
+    }
+}
+
+/// The configured upload provider, if any
+pub fn provider() -> Option<Box<dyn Provider>> {
+    let config = config::get();
+    let backend = config
+        .upload
+        .or_else(|| config.upload_cmd.as_ref().map(|_| Backend::Command))?;
+
+    match backend {
+        Backend::ZeroXZero => Some(Box::new(ZeroXZero)),
+        Backend::Http => match &config.upload_url {
+            Some(url) => Some(Box::new(Http {
+                url: url.clone(),
+                post: config
+                    .upload_method
+                    .as_deref()
+                    .is_some_and(|m| m.eq_ignore_ascii_case("post")),
+                auth_header: config.upload_auth.clone(),
+            })),
+            None => {
+                tracing::warn!("HTTP uploads need NIRI_SCREEN_RECORDER_UPLOAD_URL");
+                None
+            }
+        },
+        Backend::S3 | Backend::WebDav => {
+            let Some(url) = config.upload_url.clone() else {
+                tracing::warn!("S3 and WebDAV uploads need NIRI_SCREEN_RECORDER_UPLOAD_URL");
+                return None;
+            };
+            if backend == Backend::S3 {
+                Some(Box::new(S3 {
+                    url,
+                    region: config.upload_region.clone(),
+                }))
+            } else {
+                Some(Box::new(WebDav { url }))
+            }
+        }
+        Backend::Command => match &config.upload_cmd {
+            Some(command) => Some(Box::new(Custom {
+                command: command.clone(),
+            })),
+            None => {
+                tracing::warn!("Command uploads need NIRI_SCREEN_RECORDER_UPLOAD_CMD");
+                None
+            }
+        },
+    }
+}
+
+/// Whether an upload provider has been configured
+pub fn available() -> bool {
+    provider().is_some()
+}
+
+/// Upload a recording and return its public URL. Attempts that fail in a way
+/// that may pass are retried with a growing delay. `progress` is called with
+/// the fraction (0 to 1) uploaded so far, if the provider reports it.
+pub async fn upload(file_path: &str, progress: impl Fn(f64)) -> Result<String> {
+    let provider = provider()
+        .ok_or_else(|| Error::UploadFailed("no upload provider configured".to_string()))?;
+    let file = Path::new(file_path);
+    if !file.exists() {
+        return Err(Error::FileNotFound(file_path.to_string()));
+    }
+
+    let retries = config::get().upload_retries;
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        tracing::info!("Uploading {} to {}", file_path, provider.name());
+        match attempt_upload(provider.as_ref(), file, &progress).await {
+            Ok(url) => return Ok(url),
+            Err(Failed {
+                error: e,
+                transient,
+            }) if transient && attempt < retries => {
+                attempt += 1;
+                tracing::warn!(
+                    "Upload failed ({}), retrying in {}s ({}/{})",
+                    e,
+                    delay.as_secs(),
+                    attempt,
+                    retries
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            Err(Failed { error, .. }) => return Err(error),
+        }
+    }
+}
+
+/// A failed upload attempt
+struct Failed {
+    error: Error,
+    /// Whether trying again could help: the server wasn't reached or had
+    /// trouble of its own, rather than refusing the upload
+    transient: bool,
+}
+
+impl From<Error> for Failed {
+    fn from(error: Error) -> Self {
+        Self {
+            error,
+            transient: false,
+        }
+    }
+}
+
+async fn attempt_upload(
+    provider: &dyn Provider,
+    file: &Path,
+    progress: &impl Fn(f64),
+) -> std::result::Result<String, Failed> {
+    let input = provider.stdin().await?;
+    let mut cmd = provider.command(file);
+    let program = cmd.as_std().get_program().to_string_lossy().to_string();
+    let mut child = cmd
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|source| Error::CommandFailed {
+            program: program.clone(),
+            source,
+        })?;
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin
+            .write_all(input.as_bytes())
+            .await
+            .map_err(|source| Error::CommandFailed {
+                program: program.clone(),
+                source,
+            })?;
+        // Dropping stdin closes it, so curl knows the config is complete
+    }
+
+    // Read stdout alongside stderr, so a large response can't block the upload
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let response = tokio::spawn(async move {
+        let mut response = String::new();
+        stdout.read_to_string(&mut response).await.ok();
+        response
+    });
+
+    // curl redraws its progress bar after a carriage return
+    let mut stderr = BufReader::new(child.stderr.take().expect("stderr is piped"));
+    let mut message = String::new();
+    let mut http_status = None;
+    let mut segment = Vec::new();
+    while stderr.read_until(b'\r', &mut segment).await.unwrap_or(0) > 0 {
+        let text = String::from_utf8_lossy(&segment);
+        for line in text.split(['\r', '\n']).filter(|l| !l.trim().is_empty()) {
+            if let Some(code) = line.strip_prefix(STATUS_PREFIX) {
+                http_status = code.trim().parse::<u16>().ok();
+                continue;
+            }
+            match parse_progress(line) {
+                Some(fraction) => progress(fraction),
+                None => message = line.trim().to_string(),
+            }
+        }
+        segment.clear();
+    }
+
+    let status = child.wait().await.map_err(|source| Error::CommandFailed {
+        program: program.clone(),
+        source,
+    })?;
+    let response = response.await.unwrap_or_default();
+
+    if !status.success() {
+        let detail = last_line(&response).unwrap_or(message);
+        let transient = match http_status {
+            // The server answered, so only its own errors are worth retrying
+            Some(code) if code > 0 => code >= 500,
+            // No response at all
+            Some(_) => status.code().is_some_and(|c| TRANSPORT_ERRORS.contains(&c)),
+            // A custom command, which could have failed for any reason
+            None => true,
+        };
+        return Err(Failed {
+            error: Error::UploadFailed(format!("{} exited with {}: {}", program, status, detail)),
+            transient,
+        });
+    }
+
+    Ok(provider.url(file, &response)?)
+}
+
+/// Parse a progress bar line such as "######      23.4%"
+fn parse_progress(line: &str) -> Option<f64> {
+    let percent: f64 = line
+        .split_whitespace()
+        .last()?
+        .strip_suffix('%')?
+        .parse()
+        .ok()?;
+    Some(percent / 100.0)
+}
+
+/// Upload the most recent recording for the `upload-last` command and print
+/// its URL, with progress on stderr if it is a terminal
+pub async fn upload_last() -> Result<()> {
+    let file = PersistentState::load()
+        .last_file
+        .ok_or_else(|| Error::UploadFailed(t!("cli-no-recording")))?;
+
+    let show_progress = std::io::stderr().is_terminal();
+    let url = upload(&file, |fraction| {
+        if show_progress {
+            eprint!(
+                "\r{}",
+                t!(
+                    "cli-uploading",
+                    percent = format!("{:.0}", fraction * 100.0)
+                )
+            );
+            std::io::stderr().flush().ok();
+        }
+    })
+    .await;
+    if show_progress {
+        eprintln!();
+    }
+
+    println!("{}", url?);
+    Ok(())
+}
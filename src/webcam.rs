@@ -0,0 +1,165 @@
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::postprocess::{self, RunningJob};
+
+/// Corner of the recording the webcam is shown in
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Corner {
+    /// Position for ffmpeg's overlay filter, 16 pixels in from the corner.
+    /// W and H are the size of the recording, w and h that of the webcam.
+    fn position(self) -> &'static str {
+        match self {
+            Corner::TopLeft => "16:16",
+            Corner::TopRight => "W-w-16:16",
+            Corner::BottomLeft => "16:H-h-16",
+            Corner::BottomRight => "W-w-16:H-h-16",
+        }
+    }
+}
+
+/// A webcam being recorded with ffmpeg alongside the screen. The footage is
+/// kept in the cache and composited onto the recording once it is stopped.
+pub struct Capture {
+    child: Child,
+    file: PathBuf,
+}
+
+impl Capture {
+    /// Start capturing the v4l2 `device` for the screen recording `recording`.
+    /// Best-effort: problems are logged and the recording goes on without it.
+    pub fn start(device: &Path, recording: &str) -> Option<Self> {
+        match Self::spawn(device, recording) {
+            Ok(capture) => Some(capture),
+            Err(e) => {
+                tracing::warn!("Failed to capture webcam {}: {}", device.display(), e);
+                None
+            }
+        }
+    }
+
+    fn spawn(device: &Path, recording: &str) -> Result<Self> {
+        let dir = dirs::cache_dir()
+            .ok_or(Error::NoHomeDirectory)?
+            .join("niri-screen-recorder/webcam");
+        std::fs::create_dir_all(&dir).map_err(Error::OutputDirectory)?;
+
+        let stem = Path::new(recording).file_stem().unwrap_or_default();
+        let file = dir.join(stem).with_extension("mkv");
+
+        let child = Command::new("ffmpeg")
+            .args(["-v", "error", "-nostdin", "-y", "-f", "v4l2", "-i"])
+            .arg(device)
+            .args(["-an", "-c:v", "libx264", "-preset", "ultrafast"])
+            .arg(&file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|source| Error::CommandFailed {
+                program: "ffmpeg".to_string(),
+                source,
+            })?;
+
+        tracing::info!(
+            "Capturing webcam {} to {}",
+            device.display(),
+            file.display()
+        );
+        Ok(Self { child, file })
+    }
+
+    /// Stop capturing. Returns the webcam footage, unless ffmpeg gave up
+    /// before recording any.
+    pub fn stop(mut self) -> Option<PathBuf> {
+        // ffmpeg finishes the file cleanly on SIGINT
+        let pid = Pid::from_raw(self.child.id() as i32);
+        if let Err(e) = kill(pid, Signal::SIGINT) {
+            tracing::debug!("Failed to stop webcam capture: {}", e);
+        }
+        match self.child.wait() {
+            Ok(status) if !status.success() => {
+                tracing::warn!("Webcam capture exited with {}", status)
+            }
+            Err(e) => tracing::warn!("Failed to wait for webcam capture: {}", e),
+            Ok(_) => {}
+        }
+
+        let captured = std::fs::metadata(&self.file).is_ok_and(|m| m.len() > 0);
+        if captured {
+            Some(self.file)
+        } else {
+            std::fs::remove_file(&self.file).ok();
+            None
+        }
+    }
+}
+
+/// Start compositing `webcam` into a corner of the recording `file`. The
+/// result is written next to it, to be moved over the original once done.
+pub fn composite(file: &str, webcam: &Path, duration: Duration) -> Result<RunningJob> {
+    let config = config::get();
+    let input = Path::new(file);
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = input.extension().unwrap_or_default().to_string_lossy();
+    let output = input.with_file_name(format!("{}-webcam.{}", stem, extension));
+
+    // Scale the webcam to a share of the recording's width, keeping its
+    // aspect ratio, and keep the screen going if the webcam stopped early
+    let filter = format!(
+        "[1:v][0:v]scale2ref=w=main_w*{}/100:h=ow/dar[pip][screen];\
+         [screen][pip]overlay={}:eof_action=pass[out]",
+        config.webcam_size,
+        config.webcam_corner.position()
+    );
+
+    let mut args: Vec<OsString> = vec!["-i".into(), file.into(), "-i".into()];
+    args.push(webcam.into());
+    args.extend(
+        [
+            "-filter_complex",
+            &filter,
+            "-map",
+            "[out]",
+            "-map",
+            "0:a?",
+            "-c:v",
+            "libx264",
+            "-preset",
+            "veryfast",
+            "-crf",
+            "20",
+            "-c:a",
+            "copy",
+        ]
+        .map(OsString::from),
+    );
+    postprocess::ffmpeg(args, output, duration)
+}
@@ -0,0 +1,485 @@
+//! End-to-end tests of the DBus interface and the CLI. Each test starts a
+//! private session bus and a daemon on it, with fake gpu-screen-recorder,
+//! slurp and ffprobe scripts in place of the real tools. Tests are skipped
+//! when dbus-daemon isn't installed.
+
+use futures_util::StreamExt;
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use zbus::Connection;
+
+const BUS_NAME: &str = "org.matthew_hre.NiriScreenRecorder";
+const OBJECT_PATH: &str = "/org/matthew_hre/NiriScreenRecorder";
+
+/// How long to wait for the daemon or a signal before failing
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const FAKE_RECORDER: &str = r#"#!/bin/sh
+while [ $# -gt 0 ]; do [ "$1" = "-o" ] && out=$2; shift; done
+if [ -n "$out" ]; then echo data > "$out"; else echo data; fi
+trap 'exit 0' INT
+while true; do sleep 0.1; done
+"#;
+
+/// A gpu-screen-recorder that takes a while to finish the file once told to
+/// stop, like the real one with a long recording
+const SLOW_RECORDER: &str = r#"#!/bin/sh
+while [ $# -gt 0 ]; do [ "$1" = "-o" ] && out=$2; shift; done
+echo data > "$out"
+trap 'sleep 1; exit 0' INT
+while true; do sleep 0.1; done
+"#;
+
+const FAKE_SLURP: &str = "#!/bin/sh\necho 100x100+0+0\n";
+
+const FAKE_FFPROBE: &str = r#"#!/bin/sh
+echo '{"streams":[{"codec_type":"video"}],"format":{"duration":"1.0"}}'
+"#;
+
+/// An ffprobe that takes a moment, long enough to start again while the
+/// recording is being verified
+const SLOW_FFPROBE: &str = r#"#!/bin/sh
+sleep 1
+echo '{"streams":[{"codec_type":"video"}],"format":{"duration":"1.0"}}'
+"#;
+
+/// A private session bus in a scratch directory, torn down when dropped
+struct Session {
+    dir: PathBuf,
+    address: String,
+    bus: Child,
+    daemon: Option<Child>,
+}
+
+impl Session {
+    /// Start a bus. Returns `None` if dbus-daemon isn't installed.
+    fn start() -> Option<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "niri-screen-recorder-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        std::fs::create_dir_all(dir.join("runtime")).unwrap();
+        for (name, script) in [
+            ("gpu-screen-recorder", FAKE_RECORDER),
+            ("slurp", FAKE_SLURP),
+            ("ffprobe", FAKE_FFPROBE),
+        ] {
+            let path = dir.join("bin").join(name);
+            std::fs::write(&path, script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let mut bus = match Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .env("XDG_RUNTIME_DIR", dir.join("runtime"))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(bus) => bus,
+            Err(e) => {
+                eprintln!("Skipping, dbus-daemon is unavailable: {}", e);
+                std::fs::remove_dir_all(&dir).ok();
+                return None;
+            }
+        };
+
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+
+        Some(Self {
+            dir,
+            address: address.trim().to_string(),
+            bus,
+            daemon: None,
+        })
+    }
+
+    /// The recorder binary, set up to use this bus, the fakes, and the
+    /// scratch directory instead of the user's home
+    fn command(&self) -> Command {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let paths = std::iter::once(self.dir.join("bin")).chain(std::env::split_paths(&path));
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_niri-screen-recorder"));
+        command
+            .env_clear()
+            .env("PATH", std::env::join_paths(paths).unwrap())
+            .env("DBUS_SESSION_BUS_ADDRESS", &self.address)
+            .env("DBUS_SYSTEM_BUS_ADDRESS", "unix:path=/nonexistent")
+            .env("HOME", &self.dir)
+            .env("XDG_RUNTIME_DIR", self.dir.join("runtime"))
+            .env("LANG", "en_US.UTF-8")
+            .env("NIRI_SCREEN_RECORDER_OUTPUT_DIR", self.dir.join("videos"))
+            .env("NIRI_SCREEN_RECORDER_SCOPE", "false")
+            .env("NIRI_SCREEN_RECORDER_LOG_JOURNAL", "false")
+            .env("NIRI_SCREEN_RECORDER_TRAY", "false");
+        command
+    }
+
+    /// Start the daemon and wait until it answers on the bus
+    async fn start_daemon(&mut self) -> Connection {
+        self.start_daemon_with(&[]).await
+    }
+
+    /// Start the daemon with extra environment variables
+    async fn start_daemon_with(&mut self, env: &[(&str, &str)]) -> Connection {
+        let daemon = self
+            .command()
+            .envs(env.iter().copied())
+            .arg("daemon")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        self.daemon = Some(daemon);
+
+        let connection = zbus::connection::Builder::address(self.address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        let ready = async {
+            loop {
+                if call::<bool>(&connection, "IsRecording").await.is_ok() {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+        tokio::time::timeout(TIMEOUT, ready)
+            .await
+            .expect("daemon comes up");
+        connection
+    }
+
+    /// Run the CLI with `args`
+    fn cli(&self, args: &[&str]) -> Output {
+        self.command().args(args).output().unwrap()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some(mut daemon) = self.daemon.take() {
+            // SIGTERM lets the daemon finish an active recording
+            kill(Pid::from_raw(daemon.id() as i32), Signal::SIGTERM).ok();
+            daemon.wait().ok();
+        }
+        self.bus.kill().ok();
+        self.bus.wait().ok();
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+async fn proxy(connection: &Connection) -> zbus::Proxy<'static> {
+    zbus::Proxy::new(connection, BUS_NAME, OBJECT_PATH, BUS_NAME)
+        .await
+        .unwrap()
+}
+
+async fn call<T>(connection: &Connection, method: &str) -> zbus::Result<T>
+where
+    T: serde::de::DeserializeOwned + zbus::zvariant::Type,
+{
+    proxy(connection).await.call(method, &()).await
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[tokio::test]
+async fn start_and_stop_emit_signals() {
+    let Some(mut session) = Session::start() else {
+        return;
+    };
+    let connection = session.start_daemon().await;
+    let proxy = proxy(&connection).await;
+    let mut started = proxy.receive_signal("RecordingStarted").await.unwrap();
+    let mut stopped = proxy.receive_signal("RecordingStopped").await.unwrap();
+
+    assert!(call::<bool>(&connection, "StartRecording").await.unwrap());
+    tokio::time::timeout(TIMEOUT, started.next())
+        .await
+        .expect("RecordingStarted is emitted");
+    assert!(call::<bool>(&connection, "IsRecording").await.unwrap());
+    let file: String = call(&connection, "GetCurrentFile").await.unwrap();
+    assert!(file.ends_with(".mp4"), "unexpected file {:?}", file);
+
+    assert!(call::<bool>(&connection, "StopRecording").await.unwrap());
+    let signal = tokio::time::timeout(TIMEOUT, stopped.next())
+        .await
+        .expect("RecordingStopped is emitted")
+        .unwrap();
+    let saved: String = signal.body().deserialize().unwrap();
+    assert_eq!(saved, file);
+    assert!(Path::new(&saved).exists());
+    assert!(!call::<bool>(&connection, "IsRecording").await.unwrap());
+}
+
+#[tokio::test]
+async fn deleting_the_file_stops_the_recording() {
+    let Some(mut session) = Session::start() else {
+        return;
+    };
+    let connection = session.start_daemon().await;
+    let proxy = proxy(&connection).await;
+    let mut stopped = proxy.receive_signal("RecordingStopped").await.unwrap();
+
+    assert!(call::<bool>(&connection, "StartRecording").await.unwrap());
+    let file: String = call(&connection, "GetCurrentFile").await.unwrap();
+    // The fake recorder creates the file right after it starts
+    let created = async {
+        while !Path::new(&file).exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::time::timeout(TIMEOUT, created)
+        .await
+        .expect("the recording is created");
+    std::fs::remove_file(&file).unwrap();
+
+    let signal = tokio::time::timeout(TIMEOUT, stopped.next())
+        .await
+        .expect("RecordingStopped is emitted")
+        .unwrap();
+    let saved: String = signal.body().deserialize().unwrap();
+    assert_eq!(saved, "");
+    assert!(!call::<bool>(&connection, "IsRecording").await.unwrap());
+}
+
+#[tokio::test]
+async fn partial_recordings_are_moved_into_place() {
+    let Some(mut session) = Session::start() else {
+        return;
+    };
+    let connection = session
+        .start_daemon_with(&[("NIRI_SCREEN_RECORDER_PARTIAL", "true")])
+        .await;
+    let proxy = proxy(&connection).await;
+    let mut stopped = proxy.receive_signal("RecordingStopped").await.unwrap();
+
+    assert!(call::<bool>(&connection, "StartRecording").await.unwrap());
+    let partial: String = call(&connection, "GetCurrentFile").await.unwrap();
+    let name = Path::new(&partial).file_name().unwrap().to_string_lossy();
+    assert!(
+        name.starts_with('.') && name.ends_with(".partial.mp4"),
+        "unexpected file {:?}",
+        partial
+    );
+
+    assert!(call::<bool>(&connection, "StopRecording").await.unwrap());
+    let signal = tokio::time::timeout(TIMEOUT, stopped.next())
+        .await
+        .expect("RecordingStopped is emitted")
+        .unwrap();
+    let saved: String = signal.body().deserialize().unwrap();
+    assert_eq!(Path::new(&saved).parent(), Path::new(&partial).parent());
+    assert_eq!(
+        Path::new(&saved).file_name().unwrap().to_string_lossy(),
+        name.trim_start_matches('.').replace(".partial", "")
+    );
+    assert!(Path::new(&saved).exists());
+    assert!(!Path::new(&partial).exists());
+}
+
+#[tokio::test]
+async fn start_while_finalizing_is_queued() {
+    let Some(mut session) = Session::start() else {
+        return;
+    };
+    std::fs::write(session.dir.join("bin").join("ffprobe"), SLOW_FFPROBE).unwrap();
+    let connection = session.start_daemon().await;
+    let proxy = proxy(&connection).await;
+    let mut started = proxy.receive_signal("RecordingStarted").await.unwrap();
+    let mut stopped = proxy.receive_signal("RecordingStopped").await.unwrap();
+
+    assert!(call::<bool>(&connection, "StartRecording").await.unwrap());
+    tokio::time::timeout(TIMEOUT, started.next())
+        .await
+        .expect("RecordingStarted is emitted");
+
+    let stop = call::<bool>(&connection, "StopRecording");
+    let start = async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let queued = call::<bool>(&connection, "StartRecording").await.unwrap();
+        let is_queued = call::<bool>(&connection, "IsStartQueued").await.unwrap();
+        (queued, is_queued)
+    };
+    let (stopped_ok, (queued, is_queued)) = tokio::join!(stop, start);
+    assert!(stopped_ok.unwrap());
+    assert!(queued);
+    assert!(is_queued);
+
+    tokio::time::timeout(TIMEOUT, stopped.next())
+        .await
+        .expect("RecordingStopped is emitted");
+    tokio::time::timeout(TIMEOUT, started.next())
+        .await
+        .expect("the queued recording starts");
+    assert!(call::<bool>(&connection, "IsRecording").await.unwrap());
+    assert!(!call::<bool>(&connection, "IsStartQueued").await.unwrap());
+}
+
+#[tokio::test]
+async fn start_while_the_recorder_exits_is_queued_at_once() {
+    let Some(mut session) = Session::start() else {
+        return;
+    };
+    std::fs::write(
+        session.dir.join("bin").join("gpu-screen-recorder"),
+        SLOW_RECORDER,
+    )
+    .unwrap();
+    let connection = session.start_daemon().await;
+    let proxy = proxy(&connection).await;
+    let mut started = proxy.receive_signal("RecordingStarted").await.unwrap();
+
+    assert!(call::<bool>(&connection, "StartRecording").await.unwrap());
+    tokio::time::timeout(TIMEOUT, started.next())
+        .await
+        .expect("RecordingStarted is emitted");
+
+    let stop = call::<bool>(&connection, "StopRecording");
+    let start = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        // Well before gpu-screen-recorder is done with the file
+        let queued = tokio::time::timeout(
+            Duration::from_millis(500),
+            call::<bool>(&connection, "StartRecording"),
+        )
+        .await
+        .expect("StartRecording replies while gpu-screen-recorder exits")
+        .unwrap();
+        let is_queued = call::<bool>(&connection, "IsStartQueued").await.unwrap();
+        (queued, is_queued)
+    };
+    let (stopped_ok, (queued, is_queued)) = tokio::join!(stop, start);
+    assert!(stopped_ok.unwrap());
+    assert!(queued);
+    assert!(is_queued);
+
+    tokio::time::timeout(TIMEOUT, started.next())
+        .await
+        .expect("the queued recording starts");
+    assert!(call::<bool>(&connection, "IsRecording").await.unwrap());
+}
+
+#[tokio::test]
+async fn recordings_can_go_to_a_pipe() {
+    let Some(mut session) = Session::start() else {
+        return;
+    };
+    let connection = session.start_daemon().await;
+    let proxy = proxy(&connection).await;
+    let mut stopped = proxy.receive_signal("RecordingStopped").await.unwrap();
+
+    let pipe: zbus::zvariant::OwnedFd = proxy.call("StartRecordingToPipe", &("",)).await.unwrap();
+    assert!(call::<bool>(&connection, "IsRecording").await.unwrap());
+    assert!(call::<bool>(&connection, "StopRecording").await.unwrap());
+
+    let signal = tokio::time::timeout(TIMEOUT, stopped.next())
+        .await
+        .expect("RecordingStopped is emitted")
+        .unwrap();
+    let saved: String = signal.body().deserialize().unwrap();
+    assert_eq!(saved, "");
+
+    let mut video = String::new();
+    std::fs::File::from(std::os::fd::OwnedFd::from(pipe))
+        .read_to_string(&mut video)
+        .unwrap();
+    assert_eq!(video, "data\n");
+    assert!(!session.dir.join("videos").exists());
+}
+
+#[tokio::test]
+async fn stop_without_a_recording_does_nothing() {
+    let Some(mut session) = Session::start() else {
+        return;
+    };
+    let connection = session.start_daemon().await;
+
+    assert!(!call::<bool>(&connection, "StopRecording").await.unwrap());
+    assert!(!call::<bool>(&connection, "IsRecording").await.unwrap());
+}
+
+#[tokio::test]
+async fn toggle_starts_and_stops() {
+    let Some(mut session) = Session::start() else {
+        return;
+    };
+    let connection = session.start_daemon().await;
+
+    assert!(call::<bool>(&connection, "ToggleRecording").await.unwrap());
+    assert!(call::<bool>(&connection, "IsRecording").await.unwrap());
+    assert!(call::<bool>(&connection, "ToggleRecording").await.unwrap());
+    assert!(!call::<bool>(&connection, "IsRecording").await.unwrap());
+}
+
+#[tokio::test]
+async fn invalid_region_is_a_named_error() {
+    let Some(mut session) = Session::start() else {
+        return;
+    };
+    let connection = session.start_daemon().await;
+
+    let result: zbus::Result<bool> = proxy(&connection)
+        .await
+        .call("StartRecordingRegion", &("nonsense",))
+        .await;
+    match result {
+        Err(zbus::Error::MethodError(name, _, _)) => {
+            assert_eq!(name.as_str(), format!("{}.Error.InvalidRegion", BUS_NAME));
+        }
+        other => panic!("expected an InvalidRegion error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn cli_follows_the_daemon() {
+    let Some(mut session) = Session::start() else {
+        return;
+    };
+    let _connection = session.start_daemon().await;
+
+    let status = session.cli(&["status"]);
+    assert!(status.status.success());
+    assert!(stdout(&status).contains("Recording: no"));
+
+    let start = session.cli(&["start"]);
+    assert!(start.status.success());
+    assert!(stdout(&start).contains("Recording started"));
+
+    let status = session.cli(&["status", "--stats"]);
+    assert!(stdout(&status).contains("Recording: yes"));
+    assert!(stdout(&status).contains("Uptime:"));
+
+    let stop = session.cli(&["stop"]);
+    assert!(stop.status.success());
+    assert!(stdout(&stop).contains("Recording stopped"));
+}
+
+#[tokio::test]
+async fn cli_reports_a_missing_daemon() {
+    let Some(session) = Session::start() else {
+        return;
+    };
+
+    let status = session.cli(&["status"]);
+    assert_eq!(status.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&status.stderr).contains("Could not connect to daemon"));
+}